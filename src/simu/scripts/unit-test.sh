@@ -18,8 +18,13 @@ PROGRAM=$(dirname $(readlink -f "$0"))/../target/debug/treecore_simu
 # test case
 RV32UI_P_TEST_BIN=`eval "find $RISCV_TESTS_BIN_PATH -type f -name \"rv32ui-p*\" ! -name \"*.dump\""`
 RV32UM_P_TEST_BIN=`eval "find $RISCV_TESTS_BIN_PATH -type f -name \"rv32um-p*\" ! -name \"*.dump\""`
+RV32UA_P_TEST_BIN=`eval "find $RISCV_TESTS_BIN_PATH -type f -name \"rv32ua-p*\" ! -name \"*.dump\""`
 RV64UI_P_TEST_BIN=`eval "find $RISCV_TESTS_BIN_PATH -type f -name \"rv64ui-p*\" ! -name \"*.dump\""`
 RV64UM_P_TEST_BIN=`eval "find $RISCV_TESTS_BIN_PATH -type f -name \"rv64um-p*\" ! -name \"*.dump\""`
+RV64UA_P_TEST_BIN=`eval "find $RISCV_TESTS_BIN_PATH -type f -name \"rv64ua-p*\" ! -name \"*.dump\""`
+RV64UC_P_TEST_BIN=`eval "find $RISCV_TESTS_BIN_PATH -type f -name \"rv64uc-p*\" ! -name \"*.dump\""`
+RV64UF_P_TEST_BIN=`eval "find $RISCV_TESTS_BIN_PATH -type f -name \"rv64uf-p*\" ! -name \"*.dump\""`
+RV64UD_P_TEST_BIN=`eval "find $RISCV_TESTS_BIN_PATH -type f -name \"rv64ud-p*\" ! -name \"*.dump\""`
 CPU_TESTS_BIN=`eval "find $CPU_TESTS_BIN_PATH -type f -name \"*-riscv64-treecore.bin\" ! -name \"*.dump\""`
 # RV64I_CUST_TEST_BIN2=`eval "find $CUST_TESTS_BIN_PATH2 -type f -name \"*-riscv64-treecore.bin\" ! -name \"*.dump\""`
 
@@ -38,6 +43,13 @@ unitTest() {
         $PROGRAM --bin $file --xlen x32 --start-addr 0x1000 --end-inst 0x00000073
     done
 
+    printf "$INFO===RV32UA TESTS===$END\n"
+    for file in $RV32UA_P_TEST_BIN; do
+        val=`eval "basename $file"`
+        printf "$INFO[%16s] $END" $val
+        $PROGRAM --bin $file --xlen x32 --start-addr 0x1000 --end-inst 0x00000073
+    done
+
     printf "$INFO===RV64UI TESTS===$END\n"
     for file in $RV64UI_P_TEST_BIN; do
         val=`eval "basename $file"`
@@ -52,6 +64,34 @@ unitTest() {
         $PROGRAM --bin $file --xlen x64 --start-addr 0x1000 --end-inst 0x00000073
     done
 
+    printf "$INFO===RV64UA TESTS===$END\n"
+    for file in $RV64UA_P_TEST_BIN; do
+        val=`eval "basename $file"`
+        printf "$INFO[%16s] $END" $val
+        $PROGRAM --bin $file --xlen x64 --start-addr 0x1000 --end-inst 0x00000073
+    done
+
+    printf "$INFO===RV64UC TESTS===$END\n"
+    for file in $RV64UC_P_TEST_BIN; do
+        val=`eval "basename $file"`
+        printf "$INFO[%16s] $END" $val
+        $PROGRAM --bin $file --xlen x64 --start-addr 0x1000 --end-inst 0x00000073
+    done
+
+    printf "$INFO===RV64UF TESTS===$END\n"
+    for file in $RV64UF_P_TEST_BIN; do
+        val=`eval "basename $file"`
+        printf "$INFO[%16s] $END" $val
+        $PROGRAM --bin $file --xlen x64 --start-addr 0x1000 --end-inst 0x00000073
+    done
+
+    printf "$INFO===RV64UD TESTS===$END\n"
+    for file in $RV64UD_P_TEST_BIN; do
+        val=`eval "basename $file"`
+        printf "$INFO[%16s] $END" $val
+        $PROGRAM --bin $file --xlen x64 --start-addr 0x1000 --end-inst 0x00000073
+    done
+
     printf "$INFO===RV64I CPU TESTS===$END\n"
     for file in $CPU_TESTS_BIN; do
         val=`eval "basename $file"`
@@ -0,0 +1,530 @@
+use crate::elf::SymTab;
+use std::collections::HashMap;
+
+// NOTE: the pluggable part, a predictor only gives the direction of the
+// conditional branch, the target is assumed to hit in the btb
+pub trait Predictor {
+    fn name(&self) -> &str;
+    fn predict(&mut self, pc: u64, target: u64) -> bool;
+    fn update(&mut self, pc: u64, target: u64, taken: bool);
+    // NOTE: the copy of the tables for the snapshot of the reverse exec
+    fn clone_box(&self) -> Box<dyn Predictor>;
+}
+
+// NOTE: 2-bit saturating counter, 0..1 not taken, 2..3 taken
+fn counter_update(ctr: &mut u8, taken: bool) {
+    match taken {
+        true => *ctr = (*ctr + 1).min(3),
+        false => *ctr = ctr.saturating_sub(1),
+    }
+}
+
+// NOTE: backward taken, forward not taken, the loop branch is backward
+#[derive(Clone, Default)]
+pub struct StaticBtfn;
+
+impl Predictor for StaticBtfn {
+    fn name(&self) -> &str {
+        "static"
+    }
+
+    fn predict(&mut self, pc: u64, target: u64) -> bool {
+        target < pc
+    }
+
+    fn update(&mut self, _pc: u64, _target: u64, _taken: bool) {}
+
+    fn clone_box(&self) -> Box<dyn Predictor> {
+        Box::new(self.clone())
+    }
+}
+
+// NOTE: the rvc inst is 2-byte aligned, so the index starts from pc bit 1
+#[derive(Clone)]
+pub struct Bimodal {
+    table: Vec<u8>,
+}
+
+impl Bimodal {
+    pub fn new(bits: u32) -> Self {
+        Bimodal {
+            table: vec![1u8; 1 << bits],
+        }
+    }
+
+    fn index(&self, pc: u64) -> usize {
+        (pc >> 1) as usize & (self.table.len() - 1)
+    }
+}
+
+impl Predictor for Bimodal {
+    fn name(&self) -> &str {
+        "bimodal"
+    }
+
+    fn predict(&mut self, pc: u64, _target: u64) -> bool {
+        self.table[self.index(pc)] >= 2
+    }
+
+    fn update(&mut self, pc: u64, _target: u64, taken: bool) {
+        let idx = self.index(pc);
+        counter_update(&mut self.table[idx], taken);
+    }
+
+    fn clone_box(&self) -> Box<dyn Predictor> {
+        Box::new(self.clone())
+    }
+}
+
+// NOTE: the global history xor the pc indexes the counters
+#[derive(Clone)]
+pub struct Gshare {
+    table: Vec<u8>,
+    hist: u64,
+}
+
+impl Gshare {
+    pub fn new(bits: u32) -> Self {
+        Gshare {
+            table: vec![1u8; 1 << bits],
+            hist: 0u64,
+        }
+    }
+
+    fn index(&self, pc: u64) -> usize {
+        ((pc >> 1) ^ self.hist) as usize & (self.table.len() - 1)
+    }
+}
+
+impl Predictor for Gshare {
+    fn name(&self) -> &str {
+        "gshare"
+    }
+
+    fn predict(&mut self, pc: u64, _target: u64) -> bool {
+        self.table[self.index(pc)] >= 2
+    }
+
+    fn update(&mut self, pc: u64, _target: u64, taken: bool) {
+        let idx = self.index(pc);
+        counter_update(&mut self.table[idx], taken);
+        self.hist = (self.hist << 1) | taken as u64;
+    }
+
+    fn clone_box(&self) -> Box<dyn Predictor> {
+        Box::new(self.clone())
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct TageEntry {
+    tag: u16,
+    ctr: i8, // 3-bit signed, >= 0 predicts taken
+    u: u8,   // 2-bit useful
+}
+
+const TAGE_HIST: [u32; 4] = [4, 8, 16, 32];
+const TAGE_TAG_BITS: u32 = 8;
+
+// NOTE: a bimodal base and 4 tagged tables of the geometric history lengths,
+// the longest matching table provides the prediction
+#[derive(Clone)]
+pub struct TageLite {
+    base: Bimodal,
+    tables: Vec<Vec<TageEntry>>,
+    bits: u32,
+    hist: u64,
+}
+
+impl TageLite {
+    pub fn new(bits: u32) -> Self {
+        let bits = bits.max(4);
+        TageLite {
+            base: Bimodal::new(bits),
+            tables: vec![vec![TageEntry::default(); 1 << (bits - 2)]; TAGE_HIST.len()],
+            bits: bits - 2,
+            hist: 0u64,
+        }
+    }
+
+    // NOTE: fold the recent 'len' bits of the history into 'width' bits
+    fn fold(&self, len: u32, width: u32) -> u64 {
+        let mut hist = self.hist & ((1u64 << len) - 1);
+        let mut res = 0u64;
+        while hist != 0 {
+            res ^= hist & ((1u64 << width) - 1);
+            hist >>= width;
+        }
+        res
+    }
+
+    fn index(&self, t: usize, pc: u64) -> usize {
+        let idx = (pc >> 1) ^ (pc >> (1 + self.bits)) ^ self.fold(TAGE_HIST[t], self.bits);
+        idx as usize & ((1 << self.bits) - 1)
+    }
+
+    fn tag(&self, t: usize, pc: u64) -> u16 {
+        let tag = (pc >> 1) ^ (self.fold(TAGE_HIST[t], TAGE_TAG_BITS) << 1);
+        (tag & ((1 << TAGE_TAG_BITS) - 1)) as u16
+    }
+
+    // NOTE: the provider table and the alt table, None is the base
+    fn lookup(&self, pc: u64) -> (Option<usize>, Option<usize>) {
+        let mut hits = (0..self.tables.len())
+            .rev()
+            .filter(|&t| self.tables[t][self.index(t, pc)].tag == self.tag(t, pc));
+        (hits.next(), hits.next())
+    }
+
+    fn pred_of(&mut self, t: Option<usize>, pc: u64) -> bool {
+        match t {
+            Some(t) => self.tables[t][self.index(t, pc)].ctr >= 0,
+            None => self.base.predict(pc, 0),
+        }
+    }
+}
+
+impl Predictor for TageLite {
+    fn name(&self) -> &str {
+        "tage-lite"
+    }
+
+    fn predict(&mut self, pc: u64, _target: u64) -> bool {
+        let (provider, _alt) = self.lookup(pc);
+        self.pred_of(provider, pc)
+    }
+
+    fn update(&mut self, pc: u64, _target: u64, taken: bool) {
+        let (provider, alt) = self.lookup(pc);
+        let pred = self.pred_of(provider, pc);
+        let alt_pred = self.pred_of(alt, pc);
+        match provider {
+            Some(t) => {
+                let idx = self.index(t, pc);
+                let v = &mut self.tables[t][idx];
+                v.ctr = match taken {
+                    true => (v.ctr + 1).min(3),
+                    false => (v.ctr - 1).max(-4),
+                };
+                if pred != alt_pred {
+                    v.u = match pred == taken {
+                        true => (v.u + 1).min(3),
+                        false => v.u.saturating_sub(1),
+                    };
+                }
+            }
+            None => self.base.update(pc, 0, taken),
+        }
+
+        // NOTE: the miss allocates one longer table whose entry is not
+        // useful, or ages all of them to make room later
+        let start = provider.map_or(0, |v| v + 1);
+        if pred != taken && start < self.tables.len() {
+            let free =
+                (start..self.tables.len()).find(|&t| self.tables[t][self.index(t, pc)].u == 0);
+            match free {
+                Some(t) => {
+                    let (idx, tag) = (self.index(t, pc), self.tag(t, pc));
+                    self.tables[t][idx] = TageEntry {
+                        tag,
+                        ctr: match taken {
+                            true => 0,
+                            false => -1,
+                        },
+                        u: 0,
+                    };
+                }
+                None => {
+                    for t in start..self.tables.len() {
+                        let idx = self.index(t, pc);
+                        self.tables[t][idx].u -= 1;
+                    }
+                }
+            }
+        }
+        self.hist = (self.hist << 1) | taken as u64;
+    }
+
+    fn clone_box(&self) -> Box<dyn Predictor> {
+        Box::new(self.clone())
+    }
+}
+
+// NOTE: like 'gshare' or 'tage,bits=12', bits is the log2 of the table size
+pub fn parse_predictor(spec: &str) -> Result<Box<dyn Predictor>, String> {
+    let mut items = spec.split(',');
+    let kind = items.next().unwrap_or("");
+    let mut bits = 12u32;
+    for item in items {
+        match item.split_once('=') {
+            Some(("bits", v)) => match v.parse::<u32>() {
+                Ok(v) if (4..=24).contains(&v) => bits = v,
+                _ => return Err(format!("[bpred] bits '{}' not in [4, 24]", v)),
+            },
+            _ => return Err(format!("[bpred] unknown option '{}'", item)),
+        }
+    }
+    match kind {
+        "static" => Ok(Box::new(StaticBtfn)),
+        "bimodal" => Ok(Box::new(Bimodal::new(bits))),
+        "gshare" => Ok(Box::new(Gshare::new(bits))),
+        "tage" => Ok(Box::new(TageLite::new(bits))),
+        _ => Err(format!("[bpred] unknown predictor '{}'", kind)),
+    }
+}
+
+// NOTE: the return addr stack, the oldest entry is dropped when it is full
+#[derive(Clone)]
+pub struct Ras {
+    stack: Vec<u64>,
+    size: usize,
+}
+
+impl Ras {
+    pub fn new(size: usize) -> Self {
+        Ras {
+            stack: Vec::with_capacity(size),
+            size,
+        }
+    }
+
+    pub fn push(&mut self, addr: u64) {
+        if self.size == 0 {
+            return;
+        }
+        if self.stack.len() == self.size {
+            self.stack.remove(0);
+        }
+        self.stack.push(addr);
+    }
+
+    pub fn pop(&mut self) -> Option<u64> {
+        self.stack.pop()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BranchKind {
+    Cond,
+    Call,
+    Ret,
+    Jump, // the direct jump, always predicted right
+    Indirect,
+}
+
+const KIND_NUM: usize = 5;
+const KIND_NAME: [&str; KIND_NUM] = ["cond", "call", "ret", "jump", "indirect"];
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct BranchStat {
+    pub exec: u64,
+    pub miss: u64,
+}
+
+// NOTE: the kind of the control transfer by the hint of rd and rs1 in the
+// spec, x1 and x5 are the link regs
+pub fn branch_kind(word: u32) -> BranchKind {
+    let opcode = word & 0x7F;
+    let rd = (word >> 7) & 0x1F;
+    let rs1 = (word >> 15) & 0x1F;
+    let link = |r: u32| r == 1 || r == 5;
+    match opcode {
+        0x6F if link(rd) => BranchKind::Call,
+        0x6F => BranchKind::Jump,
+        0x67 if link(rd) => BranchKind::Call,
+        0x67 if link(rs1) => BranchKind::Ret,
+        0x67 => BranchKind::Indirect,
+        _ => BranchKind::Cond,
+    }
+}
+
+// NOTE: the target of the b-type inst, the expanded rvc branch has the same one
+fn cond_target(pc: u64, word: u32) -> u64 {
+    let imm = ((word >> 31) & 0x1) << 12
+        | ((word >> 7) & 0x1) << 11
+        | ((word >> 25) & 0x3F) << 5
+        | ((word >> 8) & 0xF) << 1;
+    let imm = ((imm as i32) << 19 >> 19) as i64;
+    pc.wrapping_add(imm as u64)
+}
+
+const ITARGET_NUM: usize = 256;
+
+pub struct BranchPred {
+    dir: Box<dyn Predictor>,
+    ras: Ras,
+    itarget: Vec<u64>, // the last target of the indirect jump, indexed by pc
+    kinds: [BranchStat; KIND_NUM],
+    stats: HashMap<u64, BranchStat>,
+}
+
+// NOTE: the predictor is a trait object, so the copy goes through its clone_box
+impl Clone for BranchPred {
+    fn clone(&self) -> Self {
+        BranchPred {
+            dir: self.dir.clone_box(),
+            ras: self.ras.clone(),
+            itarget: self.itarget.clone(),
+            kinds: self.kinds,
+            stats: self.stats.clone(),
+        }
+    }
+}
+
+impl BranchPred {
+    pub fn new(dir: Box<dyn Predictor>, ras_entries: usize) -> Self {
+        BranchPred {
+            dir,
+            ras: Ras::new(ras_entries),
+            itarget: vec![0u64; ITARGET_NUM],
+            kinds: [BranchStat::default(); KIND_NUM],
+            stats: HashMap::new(),
+        }
+    }
+
+    // NOTE: the predictor state is kept across the reset, only the stats are
+    // cleared, like a warm restart
+    pub fn reset(&mut self) {
+        self.ras.stack.clear();
+        self.kinds = [BranchStat::default(); KIND_NUM];
+        self.stats.clear();
+    }
+
+    pub fn stat_of(&self, pc: u64) -> Option<BranchStat> {
+        self.stats.get(&pc).copied()
+    }
+
+    // NOTE: evaluate the prediction of the retired jump or branch against
+    // where it goes, return whether it is mispredicted
+    pub fn resolve(&mut self, pc: u64, word: u32, next_pc: u64, target: u64) -> bool {
+        let kind = branch_kind(word);
+        let miss = match kind {
+            BranchKind::Cond => {
+                let dest = cond_target(pc, word);
+                let taken = target != next_pc;
+                let pred = self.dir.predict(pc, dest);
+                self.dir.update(pc, dest, taken);
+                pred != taken
+            }
+            BranchKind::Call | BranchKind::Jump => {
+                if kind == BranchKind::Call {
+                    self.ras.push(next_pc);
+                }
+                // NOTE: the jalr call goes through the indirect target table
+                match word & 0x7F {
+                    0x67 => self.indirect(pc, target),
+                    _ => false,
+                }
+            }
+            BranchKind::Ret => self.ras.pop() != Some(target),
+            BranchKind::Indirect => self.indirect(pc, target),
+        };
+        let stat = &mut self.kinds[kind as usize];
+        stat.exec += 1;
+        stat.miss += miss as u64;
+        let stat = self.stats.entry(pc).or_default();
+        stat.exec += 1;
+        stat.miss += miss as u64;
+        miss
+    }
+
+    fn indirect(&mut self, pc: u64, target: u64) -> bool {
+        let idx = (pc >> 1) as usize & (ITARGET_NUM - 1);
+        let miss = self.itarget[idx] != target;
+        self.itarget[idx] = target;
+        miss
+    }
+
+    // NOTE: the total, each kind, and the branches which miss most with
+    // their symbols
+    pub fn report(&self, symtab: &SymTab, insts: u64, top: usize) -> String {
+        let exec: u64 = self.kinds.iter().map(|v| v.exec).sum();
+        let miss: u64 = self.kinds.iter().map(|v| v.miss).sum();
+        let mut res = format!(
+            "bpred[{}] branches: {} miss: {} accuracy: {:.2}% MPKI: {:.3}\nkind",
+            self.dir.name(),
+            exec,
+            miss,
+            100.0 * (exec - miss) as f64 / exec.max(1) as f64,
+            1000.0 * miss as f64 / insts.max(1) as f64
+        );
+        for (name, v) in KIND_NAME.iter().zip(self.kinds.iter()) {
+            if v.exec != 0 {
+                res += &format!(" {}: {}/{}", name, v.miss, v.exec);
+            }
+        }
+
+        let mut stats: Vec<(&u64, &BranchStat)> =
+            self.stats.iter().filter(|v| v.1.miss != 0).collect();
+        stats.sort_by(|a, b| b.1.miss.cmp(&a.1.miss).then(a.0.cmp(b.0)));
+        for (pc, v) in stats.iter().take(top) {
+            let sym = match symtab.lookup(**pc) {
+                Some((name, off)) => format!(" <{}+{:#x}>", name, off),
+                None => String::new(),
+            };
+            res += &format!(
+                "\n  {:#x}{} miss: {}/{} ({:.2}%)",
+                pc,
+                sym,
+                v.miss,
+                v.exec,
+                100.0 * v.miss as f64 / v.exec as f64
+            );
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bpred::*;
+
+    // NOTE: beq x0, x0 with the offset, bits of the b-type imm
+    fn beq(off: i32) -> u32 {
+        let imm = off as u32;
+        ((imm >> 12) & 0x1) << 31
+            | ((imm >> 5) & 0x3F) << 25
+            | ((imm >> 1) & 0xF) << 8
+            | ((imm >> 11) & 0x1) << 7
+            | 0x63
+    }
+
+    #[test]
+    fn bpred_predictors() {
+        assert!(parse_predictor("perceptron").is_err());
+        assert!(parse_predictor("gshare,bits=2").is_err());
+        assert_eq!(cond_target(0x1000, beq(-16)), 0xFF0);
+        assert_eq!(branch_kind(0x0000_00EF), BranchKind::Call); // jal ra
+        assert_eq!(branch_kind(0x0000_8067), BranchKind::Ret); // ret
+        assert_eq!(branch_kind(0x0002_8067), BranchKind::Ret); // jr t0
+
+        // NOTE: a loop of 4 trips, taken 3 times then falls through, the
+        // history predictors learn the exit, bimodal misses it each time
+        let mut misses = vec![];
+        for spec in ["static", "bimodal", "gshare,bits=8", "tage,bits=8"] {
+            let mut dut = BranchPred::new(parse_predictor(spec).unwrap(), 4);
+            for _ in 0..200 {
+                for i in 0..4 {
+                    let target = match i {
+                        3 => 0x1004,
+                        _ => 0xFF0,
+                    };
+                    dut.resolve(0x1000, beq(-16), 0x1004, target);
+                }
+            }
+            misses.push(dut.stat_of(0x1000).unwrap().miss);
+        }
+        assert_eq!(misses[0], 200);
+        assert!(misses[1] >= 200);
+        assert!(misses[2] < 20 && misses[3] < 20, "{:?}", misses);
+
+        // NOTE: the ras predicts the nested returns
+        let mut dut = BranchPred::new(parse_predictor("static").unwrap(), 2);
+        dut.resolve(0x100, 0x0000_00EF, 0x104, 0x200);
+        dut.resolve(0x200, 0x0000_00EF, 0x204, 0x300);
+        assert!(!dut.resolve(0x300, 0x0000_8067, 0x304, 0x204));
+        assert!(!dut.resolve(0x208, 0x0000_8067, 0x20C, 0x104));
+        assert!(dut.resolve(0x108, 0x0000_8067, 0x10C, 0x4));
+        assert!(dut.report(&SymTab::new(), 5, 10).contains("ret: 1/3"));
+    }
+}
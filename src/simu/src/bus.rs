@@ -0,0 +1,303 @@
+use crate::mem::{Page, SparseMem, PAGE_SIZE};
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::rc::Rc;
+
+// NOTE: offset is relative to the region base, size is 1, 2, 4 or 8 bytes
+pub trait MmioDevice {
+    fn read(&mut self, offset: u64, size: u64) -> u64;
+    fn write(&mut self, offset: u64, size: u64, val: u64);
+}
+
+// NOTE: the access hits no mem or device region, the addr is carried for the trap
+#[derive(Debug, PartialEq)]
+pub struct BusFault(pub u64);
+
+pub struct Region {
+    pub name: String,
+    pub base: u64,
+    pub size: u64,
+    dev: Rc<RefCell<dyn MmioDevice>>,
+}
+
+impl Region {
+    fn contains(&self, addr: u64, size: u64) -> bool {
+        addr >= self.base && addr.checked_add(size).is_some_and(|v| v <= self.base + self.size)
+    }
+}
+
+// NOTE: the rom can only be written by the loader
+pub struct MemRegion {
+    pub name: String,
+    pub base: u64,
+    read_only: bool,
+    mem: SparseMem,
+}
+
+impl MemRegion {
+    fn contains(&self, addr: u64, size: u64) -> bool {
+        addr >= self.base && addr.checked_add(size).is_some_and(|v| v <= self.base + self.mem.size())
+    }
+}
+
+// NOTE: the phy addr of the page and its pre-image, None if it was untouched
+pub type UndoLog = Vec<(u64, Option<Page>)>;
+
+// NOTE: the undo log of the reverse exec, the pre-image of each mem page the
+// first time it is written since the last take. The device regs are not
+// logged, their state is saved by the snapshot
+#[derive(Default)]
+struct Journal {
+    seen: HashSet<u64>,
+    pages: UndoLog,
+}
+
+#[derive(Default)]
+pub struct Bus {
+    mems: Vec<MemRegion>,
+    regions: Vec<Region>,
+    journal: Option<Journal>, // None keeps the write path free of the log
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Bus {
+            mems: vec![],
+            regions: vec![],
+            journal: None,
+        }
+    }
+
+    fn overlap(base_a: u64, size_a: u64, base_b: u64, size_b: u64) -> bool {
+        base_a < base_b + size_b && base_b < base_a + size_a
+    }
+
+    // NOTE: the region can not overlap any mem or device region
+    fn check_region(&self, name: &str, base: u64, size: u64) -> Result<(), String> {
+        if size == 0 || base.checked_add(size).is_none() {
+            return Err(format!("[{}] invalid region {:#x}+{:#x}", name, base, size));
+        }
+        let used = self
+            .mems
+            .iter()
+            .map(|v| (v.name.as_str(), v.base, v.mem.size()))
+            .chain(self.regions.iter().map(|v| (v.name.as_str(), v.base, v.size)));
+        for (used_name, used_base, used_size) in used {
+            if Bus::overlap(base, size, used_base, used_size) {
+                return Err(format!(
+                    "[{}] region {:#x}+{:#x} overlaps [{}]",
+                    name, base, size, used_name
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    fn add_mem(&mut self, name: &str, base: u64, size: u64, read_only: bool) -> Result<(), String> {
+        self.check_region(name, base, size)?;
+        self.mems.push(MemRegion {
+            name: name.to_string(),
+            base,
+            read_only,
+            mem: SparseMem::new(size),
+        });
+        Ok(())
+    }
+
+    pub fn add_ram(&mut self, name: &str, base: u64, size: u64) -> Result<(), String> {
+        self.add_mem(name, base, size, false)
+    }
+
+    pub fn add_rom(&mut self, name: &str, base: u64, size: u64) -> Result<(), String> {
+        self.add_mem(name, base, size, true)
+    }
+
+    pub fn add_device(
+        &mut self,
+        name: &str,
+        base: u64,
+        size: u64,
+        dev: Rc<RefCell<dyn MmioDevice>>,
+    ) -> Result<(), String> {
+        self.check_region(name, base, size)?;
+        self.regions.push(Region {
+            name: name.to_string(),
+            base,
+            size,
+            dev,
+        });
+        Ok(())
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn mems(&self) -> &[MemRegion] {
+        &self.mems
+    }
+
+    fn find_mem(&mut self, addr: u64, size: u64) -> Option<&mut MemRegion> {
+        self.mems.iter_mut().find(|v| v.contains(addr, size))
+    }
+
+    // NOTE: the access must be inside one mem or device region, or an access fault is raised
+    pub fn read(&mut self, addr: u64, size: u64) -> Result<u64, BusFault> {
+        if let Some(v) = self.find_mem(addr, size) {
+            return Ok(v.mem.read(addr - v.base, size));
+        }
+        match self.regions.iter().find(|v| v.contains(addr, size)) {
+            Some(v) => Ok(v.dev.borrow_mut().read(addr - v.base, size)),
+            None => Err(BusFault(addr)),
+        }
+    }
+
+    pub fn write(&mut self, addr: u64, size: u64, val: u64) -> Result<(), BusFault> {
+        if self.journal.is_some() {
+            self.log_page(addr);
+            self.log_page(addr.wrapping_add(size - 1));
+        }
+        if let Some(v) = self.find_mem(addr, size) {
+            if v.read_only {
+                return Err(BusFault(addr));
+            }
+            v.mem.write(addr - v.base, size, val);
+            return Ok(());
+        }
+        match self.regions.iter().find(|v| v.contains(addr, size)) {
+            Some(v) => {
+                v.dev.borrow_mut().write(addr - v.base, size, val);
+                Ok(())
+            }
+            None => Err(BusFault(addr)),
+        }
+    }
+
+    pub fn set_journal(&mut self, on: bool) {
+        self.journal = match on {
+            true => Some(Journal::default()),
+            false => None,
+        };
+    }
+
+    fn log_page(&mut self, addr: u64) {
+        let journal = match self.journal.as_mut() {
+            Some(v) => v,
+            None => return,
+        };
+        if let Some(v) = self.mems.iter().find(|v| v.contains(addr, 1)) {
+            let offset = (addr - v.base) & !(PAGE_SIZE as u64 - 1);
+            if journal.seen.insert(v.base + offset) {
+                journal.pages.push((v.base + offset, v.mem.page(offset)));
+            }
+        }
+    }
+
+    // NOTE: the pages logged since the last take, a new log is started
+    pub fn take_journal(&mut self) -> UndoLog {
+        match self.journal.as_mut() {
+            Some(v) => {
+                v.seen.clear();
+                std::mem::take(&mut v.pages)
+            }
+            None => vec![],
+        }
+    }
+
+    // NOTE: write the pre-images back, the later ones first
+    pub fn undo(&mut self, pages: UndoLog) {
+        for (addr, page) in pages.into_iter().rev() {
+            if let Some(v) = self.find_mem(addr, 1) {
+                v.mem.set_page(addr - v.base, page);
+            }
+        }
+    }
+
+    // NOTE: drop all pages of the ram and rom, it reads zero again
+    pub fn clear_mem(&mut self) {
+        for v in self.mems.iter_mut() {
+            v.mem.clear();
+        }
+    }
+
+    pub fn is_mem(&self, addr: u64, size: u64) -> bool {
+        self.mems.iter().any(|v| v.contains(addr, size))
+    }
+
+    // NOTE: bulk copy for the loader, the data must be inside one mem region
+    pub fn load(&mut self, addr: u64, data: &[u8]) -> Result<(), BusFault> {
+        match self.find_mem(addr, data.len() as u64) {
+            Some(v) => {
+                v.mem.load(addr - v.base, data);
+                Ok(())
+            }
+            None => Err(BusFault(addr)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::{Bus, BusFault, MmioDevice};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    struct Scratch {
+        reg: u64,
+        last: (u64, u64),
+    }
+
+    impl MmioDevice for Scratch {
+        fn read(&mut self, offset: u64, size: u64) -> u64 {
+            self.last = (offset, size);
+            self.reg
+        }
+
+        fn write(&mut self, offset: u64, size: u64, val: u64) {
+            self.last = (offset, size);
+            self.reg = val;
+        }
+    }
+
+    #[test]
+    fn bus_map() {
+        let mut dut = Bus::new();
+        let dev = Rc::new(RefCell::new(Scratch {
+            reg: 0,
+            last: (0, 0),
+        }));
+        assert!(dut.add_rom("rom", 0x1000, 0x1000).is_ok());
+        assert!(dut.add_ram("ram", 0x8000_0000, 0x1000).is_ok());
+        assert!(dut.add_device("scratch", 0x1000_0000, 0x100, dev.clone()).is_ok());
+        assert!(dut.add_device("dup", 0x1000_00F0, 0x100, dev.clone()).is_err());
+        assert!(dut.add_device("ram", 0x8000_0800, 0x100, dev.clone()).is_err());
+        assert!(dut.add_ram("rom", 0x0, 0x1001).is_err());
+        assert!(dut.add_device("zero", 0x2000_0000, 0, dev.clone()).is_err());
+
+        assert!(dut.write(0x1000_0008, 8, 0x1234).is_ok());
+        assert_eq!(dev.borrow().last, (0x8, 8));
+        assert_eq!(dut.read(0x1000_0010, 4), Ok(0x1234));
+        assert_eq!(dev.borrow().last, (0x10, 4));
+        // NOTE: unmapped and cross-boundary accesses fault
+        assert!(dut.read(0x1000_00FE, 4).is_err());
+        assert_eq!(dut.read(0x0, 1), Err(BusFault(0x0)));
+        assert!(dut.write(0x8000_0FFC, 8, 0).is_err());
+
+        assert!(dut.write(0x8000_0000, 4, 0xdeadbeef).is_ok());
+        assert_eq!(dut.read(0x8000_0002, 2), Ok(0xdead));
+        assert!(dut.load(0x8000_0FFF, &[1, 2]).is_err());
+        // NOTE: the end of the access at the top of the addr space does not wrap
+        assert_eq!(dut.read(u64::MAX, 1), Err(BusFault(u64::MAX)));
+        assert!(dut.read(0xFFFF_FFFF_FFFF_FFF8, 8).is_err());
+        assert!(dut.write(u64::MAX, 1, 0).is_err());
+        assert!(!dut.is_mem(u64::MAX, 1));
+
+        // NOTE: the rom is only written by the loader
+        assert!(dut.load(0x1000, &[0x13, 0x00]).is_ok());
+        assert!(dut.write(0x1000, 1, 0).is_err());
+        assert_eq!(dut.read(0x1000, 2), Ok(0x13));
+        dut.clear_mem();
+        assert_eq!(dut.read(0x1000, 2), Ok(0));
+        assert_eq!(dut.read(0x8000_0000, 4), Ok(0));
+    }
+}
@@ -0,0 +1,453 @@
+// NOTE: a tag-only model, the data always lives in the bus, the caches only
+// count the hits and misses and charge the miss penalty to the timing
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Replace {
+    Lru,
+    Random,
+    Plru, // tree pseudo-lru, the ways must be a power of 2
+}
+
+// NOTE: write-back allocates on the write miss, write-through does not and
+// sends each write to the next level
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WritePolicy {
+    WriteBack,
+    WriteThrough,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct CacheConfig {
+    pub size: u64, // bytes
+    pub line: u64, // bytes
+    pub ways: usize,
+    pub replace: Replace,
+    pub write: WritePolicy,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            size: 32 * 1024,
+            line: 64,
+            ways: 4,
+            replace: Replace::Lru,
+            write: WritePolicy::WriteBack,
+        }
+    }
+}
+
+impl CacheConfig {
+    // NOTE: the overrides of the default like "size=16k,line=32,ways=2,replace=plru,write=wt"
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut res = CacheConfig::default();
+        for item in spec.split(',').filter(|v| !v.is_empty() && *v != "default") {
+            let (key, val) = match item.split_once('=') {
+                Some(v) => v,
+                None => return Err(format!("[cache] expect key=val, get '{}'", item)),
+            };
+            match key {
+                "size" => res.size = CacheConfig::parse_size(val)?,
+                "line" => res.line = CacheConfig::parse_size(val)?,
+                "ways" => res.ways = CacheConfig::parse_size(val)? as usize,
+                "replace" => {
+                    res.replace = match val {
+                        "lru" => Replace::Lru,
+                        "random" => Replace::Random,
+                        "plru" => Replace::Plru,
+                        _ => return Err(format!("[cache] unknown replace '{}'", val)),
+                    }
+                }
+                "write" => {
+                    res.write = match val {
+                        "wb" => WritePolicy::WriteBack,
+                        "wt" => WritePolicy::WriteThrough,
+                        _ => return Err(format!("[cache] unknown write policy '{}'", val)),
+                    }
+                }
+                _ => return Err(format!("[cache] unknown key '{}'", key)),
+            }
+        }
+        res.check()?;
+        Ok(res)
+    }
+
+    fn parse_size(val: &str) -> Result<u64, String> {
+        let (num, unit) = match val.strip_suffix(['k', 'K']) {
+            Some(v) => (v, 1024),
+            None => (val, 1),
+        };
+        match num.parse::<u64>() {
+            Ok(v) => Ok(v * unit),
+            Err(_e) => Err(format!("[cache] invalid number '{}'", val)),
+        }
+    }
+
+    fn check(&self) -> Result<(), String> {
+        if !self.line.is_power_of_two() || self.line < 4 {
+            return Err(format!("[cache] line {} is not a power of 2", self.line));
+        }
+        if self.ways == 0 || self.ways > 64 {
+            return Err(format!("[cache] ways {} not in [1, 64]", self.ways));
+        }
+        if self.replace == Replace::Plru && !self.ways.is_power_of_two() {
+            return Err(format!(
+                "[cache] plru needs the ways of power of 2, get {}",
+                self.ways
+            ));
+        }
+        let sets = self.size / self.line / self.ways as u64;
+        if sets == 0 || !sets.is_power_of_two() || sets * self.line * self.ways as u64 != self.size
+        {
+            return Err(format!(
+                "[cache] size {} is not line * ways * 2^n",
+                self.size
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Line {
+    tag: u64,
+    valid: bool,
+    dirty: bool,
+    lru: u64,
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
+pub struct CacheStats {
+    pub hit: u64,
+    pub miss: u64,
+    pub writeback: u64,
+}
+
+impl CacheStats {
+    fn add(&mut self, hit: bool) {
+        match hit {
+            true => self.hit += 1,
+            false => self.miss += 1,
+        }
+    }
+
+    pub fn miss_rate(&self) -> f64 {
+        self.miss as f64 / (self.hit + self.miss).max(1) as f64
+    }
+}
+
+#[derive(Clone)]
+pub struct Cache {
+    name: &'static str,
+    cfg: CacheConfig,
+    sets: u64,
+    lines: Vec<Line>,
+    plru: Vec<u128>, // the tree bits of each set, node i at bit i, the root is 1
+    tick: u64,
+    seed: u64,
+    pub stats: CacheStats,
+    ranges: Vec<(u64, u64, CacheStats)>, // [lo, hi) of the pc and its stats
+}
+
+impl Cache {
+    pub fn new(name: &'static str, cfg: CacheConfig, ranges: &[(u64, u64)]) -> Self {
+        let sets = cfg.size / cfg.line / cfg.ways as u64;
+        Cache {
+            name,
+            cfg,
+            sets,
+            lines: vec![Line::default(); (sets as usize) * cfg.ways],
+            plru: vec![0u128; sets as usize],
+            tick: 0u64,
+            seed: 0x2545_F491_4F6C_DD1D,
+            stats: CacheStats::default(),
+            ranges: ranges
+                .iter()
+                .map(|v| (v.0, v.1, CacheStats::default()))
+                .collect(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        self.name
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.cfg
+    }
+
+    pub fn reset(&mut self) {
+        self.lines.fill(Line::default());
+        self.plru.fill(0u128);
+        self.tick = 0u64;
+        self.stats = CacheStats::default();
+        for v in self.ranges.iter_mut() {
+            v.2 = CacheStats::default();
+        }
+    }
+
+    pub fn range_stats(&self) -> &[(u64, u64, CacheStats)] {
+        &self.ranges
+    }
+
+    // NOTE: return whether it hits and the addr of the dirty line it evicts,
+    // the access across the line is counted by its first byte
+    pub fn access(&mut self, pc: u64, addr: u64, write: bool) -> (bool, Option<u64>) {
+        self.tick += 1;
+        let blk = addr / self.cfg.line;
+        let set = blk & (self.sets - 1);
+        let tag = blk / self.sets;
+        let base = set as usize * self.cfg.ways;
+
+        let hit_way = (0..self.cfg.ways).find(|&i| {
+            let v = &self.lines[base + i];
+            v.valid && v.tag == tag
+        });
+        let hit = hit_way.is_some();
+        self.stats.add(hit);
+        for v in self.ranges.iter_mut() {
+            if pc >= v.0 && pc < v.1 {
+                v.2.add(hit);
+            }
+        }
+
+        let wb = self.cfg.write == WritePolicy::WriteBack;
+        let way = match hit_way {
+            Some(v) => v,
+            None if write && !wb => return (false, None),
+            None => self.victim(set as usize),
+        };
+        let line = &mut self.lines[base + way];
+        let mut evict = None;
+        if !hit {
+            if line.valid && line.dirty {
+                evict = Some((line.tag * self.sets + set) * self.cfg.line);
+                self.stats.writeback += 1;
+            }
+            *line = Line {
+                tag,
+                valid: true,
+                dirty: false,
+                lru: 0,
+            };
+        }
+        line.dirty |= write && wb;
+        line.lru = self.tick;
+        if self.cfg.replace == Replace::Plru {
+            self.plru_touch(set as usize, way);
+        }
+        (hit, evict)
+    }
+
+    // NOTE: the invalid way is used first
+    fn victim(&mut self, set: usize) -> usize {
+        let base = set * self.cfg.ways;
+        let lines = &self.lines[base..base + self.cfg.ways];
+        if let Some(v) = lines.iter().position(|v| !v.valid) {
+            return v;
+        }
+        match self.cfg.replace {
+            Replace::Lru => (0..lines.len()).min_by_key(|&i| lines[i].lru).unwrap(),
+            Replace::Random => {
+                // NOTE: xorshift, the sequence is the same in each run
+                self.seed ^= self.seed << 13;
+                self.seed ^= self.seed >> 7;
+                self.seed ^= self.seed << 17;
+                (self.seed % self.cfg.ways as u64) as usize
+            }
+            Replace::Plru => {
+                let bits = self.plru[set];
+                let mut node = 1usize;
+                for _ in 0..self.cfg.ways.trailing_zeros() {
+                    node = node * 2 + ((bits >> node) & 1) as usize;
+                }
+                node - self.cfg.ways
+            }
+        }
+    }
+
+    // NOTE: each node on the path points to the other half of the accessed way
+    fn plru_touch(&mut self, set: usize, way: usize) {
+        let bits = &mut self.plru[set];
+        let mut node = 1usize;
+        for lvl in (0..self.cfg.ways.trailing_zeros()).rev() {
+            let right = (way >> lvl) & 1;
+            match right {
+                0 => *bits |= 1u128 << node,
+                _ => *bits &= !(1u128 << node),
+            }
+            node = node * 2 + right;
+        }
+    }
+}
+
+// NOTE: the split l1 and the optional unified l2 behind both, a missing level
+// is skipped, so a single l2 also works
+#[derive(Clone, Default)]
+pub struct CacheSys {
+    pub icache: Option<Cache>,
+    pub dcache: Option<Cache>,
+    pub l2: Option<Cache>,
+}
+
+impl CacheSys {
+    pub fn enabled(&self) -> bool {
+        self.icache.is_some() || self.dcache.is_some() || self.l2.is_some()
+    }
+
+    pub fn reset(&mut self) {
+        for v in [&mut self.icache, &mut self.dcache, &mut self.l2]
+            .into_iter()
+            .flatten()
+        {
+            v.reset();
+        }
+    }
+
+    // NOTE: the high half of a 32-bit inst is in the same fetch, it only
+    // accesses the icache again when it starts a new line
+    pub fn fetch(&mut self, pc: u64, va: u64, pa: u64) -> Option<u64> {
+        if va != pc {
+            let line = match (&self.icache, &self.l2) {
+                (Some(v), _) | (None, Some(v)) => v.config().line,
+                (None, None) => return None,
+            };
+            if pa & (line - 1) != 0 {
+                return None;
+            }
+        }
+        CacheSys::access(&mut self.icache, &mut self.l2, pc, pa, false)
+    }
+
+    pub fn load(&mut self, pc: u64, pa: u64) -> Option<u64> {
+        CacheSys::access(&mut self.dcache, &mut self.l2, pc, pa, false)
+    }
+
+    pub fn store(&mut self, pc: u64, pa: u64) -> Option<u64> {
+        CacheSys::access(&mut self.dcache, &mut self.l2, pc, pa, true)
+    }
+
+    // NOTE: return the number of the levels which miss, None when no level is
+    // accessed. The writeback and the write-through are buffered and do not stall
+    fn access(
+        l1: &mut Option<Cache>,
+        l2: &mut Option<Cache>,
+        pc: u64,
+        addr: u64,
+        write: bool,
+    ) -> Option<u64> {
+        let l1 = match l1 {
+            Some(v) => v,
+            None => return l2.as_mut().map(|v| !v.access(pc, addr, write).0 as u64),
+        };
+        let (hit, evict) = l1.access(pc, addr, write);
+        let through = write && l1.config().write == WritePolicy::WriteThrough;
+        let mut miss = 0u64;
+        if let Some(v) = l2 {
+            if let Some(evict) = evict {
+                v.access(pc, evict, true);
+            }
+            if through {
+                v.access(pc, addr, true);
+            } else if !hit {
+                miss += !v.access(pc, addr, false).0 as u64;
+            }
+        }
+        match hit || through {
+            true => Some(miss),
+            false => Some(miss + 1),
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut res = vec![];
+        for v in [&self.icache, &self.dcache, &self.l2].into_iter().flatten() {
+            let cfg = v.config();
+            res.push(format!(
+                "{}[{}KiB {}B {}-way {:?} {:?}] hit: {} miss: {} writeback: {} miss rate: {:.2}%",
+                v.name(),
+                cfg.size / 1024,
+                cfg.line,
+                cfg.ways,
+                cfg.replace,
+                cfg.write,
+                v.stats.hit,
+                v.stats.miss,
+                v.stats.writeback,
+                v.stats.miss_rate() * 100.0
+            ));
+            for (lo, hi, stats) in v.range_stats() {
+                res.push(format!(
+                    "  pc [{:#x}, {:#x}) hit: {} miss: {} miss rate: {:.2}%",
+                    lo,
+                    hi,
+                    stats.hit,
+                    stats.miss,
+                    stats.miss_rate() * 100.0
+                ));
+            }
+        }
+        res.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::*;
+
+    #[test]
+    fn cache_replace() {
+        assert!(CacheConfig::parse("size=3k").is_err());
+        assert!(CacheConfig::parse("ways=3,replace=plru,size=3k").is_err());
+        assert!(CacheConfig::parse("write=wa").is_err());
+        // NOTE: 2 sets of 2 ways, the line addrs 0x00, 0x40 and 0x80 share set 0
+        let cfg = CacheConfig::parse("size=128,line=32,ways=2").unwrap();
+        let mut dut = Cache::new("d$", cfg, &[(0x100, 0x200)]);
+        assert_eq!(dut.access(0x100, 0x00, false), (false, None));
+        assert_eq!(dut.access(0x100, 0x44, true), (false, None));
+        assert_eq!(dut.access(0x100, 0x1C, false), (true, None));
+        // NOTE: lru evicts the dirty 0x40
+        assert_eq!(dut.access(0x300, 0x80, false), (false, Some(0x40)));
+        assert_eq!(dut.access(0x300, 0x40, false), (false, None));
+        assert_eq!(
+            dut.stats,
+            CacheStats {
+                hit: 1,
+                miss: 4,
+                writeback: 1
+            }
+        );
+        assert_eq!(
+            dut.range_stats()[0].2,
+            CacheStats {
+                hit: 1,
+                miss: 2,
+                writeback: 0
+            }
+        );
+
+        let cfg = CacheConfig::parse("size=256,line=32,ways=4,replace=plru").unwrap();
+        let mut dut = Cache::new("d$", cfg, &[]);
+        for addr in [0x00, 0x40, 0x80, 0xC0, 0x00, 0x80] {
+            dut.access(0, addr, false);
+        }
+        // NOTE: the tree points away from 0x80, then from 0x00, so 0x40 goes
+        assert!(!dut.access(0, 0x100, false).0);
+        assert!(dut.access(0, 0xC0, false).0);
+        assert!(!dut.access(0, 0x40, false).0);
+
+        // NOTE: write-through does not allocate and passes the write to l2
+        let l1 = CacheConfig::parse("size=256,line=32,ways=2,write=wt").unwrap();
+        let mut sys = CacheSys {
+            icache: None,
+            dcache: Some(Cache::new("d$", l1, &[])),
+            l2: Some(Cache::new("l2", CacheConfig::default(), &[])),
+        };
+        assert_eq!(sys.store(0, 0x1000), Some(0));
+        assert_eq!(sys.load(0, 0x1000), Some(1));
+        assert_eq!(sys.load(0, 0x2000), Some(2));
+        // NOTE: the fetch without the icache goes to l2
+        assert_eq!(sys.fetch(0, 0x1000, 0x1000), Some(0));
+        assert_eq!(sys.l2.as_ref().unwrap().stats.hit, 2);
+    }
+}
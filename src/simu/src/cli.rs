@@ -1,4 +1,7 @@
-use crate::core::{Core, RunMode};
+use crate::core::Core;
+use crate::debug::{Debugger, StopReason, WatchKind, Watchpoint};
+use crate::elf;
+use crate::expr::eval;
 use std::fs::File;
 use std::io::{stdin, stdout, Read, Write};
 
@@ -20,6 +23,21 @@ enum CliCmd {
     TDBSI,
     TDBINFO,
     TDBX,
+    TDBB,
+    TDBW,
+    TDBD,
+    TDBDISAS,
+    TDBRSI,
+    TDBRC,
+}
+
+// NOTE: the watchpoint on a deref like '*(u32*)$sp' is checked by the core
+// on each access, any other expr is evaluated after each inst and stops when
+// its value changes
+enum Point {
+    Break(u64),
+    WatchMem(String, Watchpoint),
+    WatchExpr(String, u64),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -30,7 +48,10 @@ pub struct Cmd<'a> {
 
 pub struct Cli<'a> {
     prompt: &'a str,
-    cmd_list: [Cmd<'a>; 9],
+    cmd_list: [Cmd<'a>; 15],
+    dbg: Debugger,
+    points: Vec<(u64, Point)>, // the id and the point
+    point_id: u64,
 }
 
 impl Cli<'_> {
@@ -68,13 +89,40 @@ impl Cli<'_> {
                 },
                 Cmd {
                     name: "info",
-                    info: "[r|w]: print [register|watchpoint] info",
+                    info: "[r|b|w]: print [register|breakpoint|watchpoint] info",
                 },
                 Cmd {
                     name: "x",
-                    info: "x N expr: print N words based on expr addr",
+                    info: "x[/x|d|i] N expr: print N words based on expr addr in hex, decimal or inst",
+                },
+                Cmd {
+                    name: "b",
+                    info: "[tdb] b expr: set a breakpoint at the addr or the symbol",
+                },
+                Cmd {
+                    name: "w",
+                    info: "[tdb] w expr: set a watchpoint on the mem deref or the value of expr",
+                },
+                Cmd {
+                    name: "d",
+                    info: "[tdb] d N: delete the breakpoint or the watchpoint N",
+                },
+                Cmd {
+                    name: "disas",
+                    info: "disas [N [expr]]: disassemble N insts from the expr addr or the pc",
+                },
+                Cmd {
+                    name: "reverse-stepi",
+                    info: "[tdb] step back [N] insts, needs --rev-interval",
+                },
+                Cmd {
+                    name: "reverse-continue",
+                    info: "[tdb] run back to the last breakpoint or watchpoint",
                 },
             ],
+            dbg: Debugger::new(),
+            points: vec![],
+            point_id: 1u64,
         }
     }
 
@@ -96,27 +144,36 @@ impl Cli<'_> {
             "si" => CliCmd::TDBSI,
             "info" => CliCmd::TDBINFO,
             "x" => CliCmd::TDBX,
+            "b" => CliCmd::TDBB,
+            "w" => CliCmd::TDBW,
+            "d" => CliCmd::TDBD,
+            "disas" => CliCmd::TDBDISAS,
+            "reverse-stepi" => CliCmd::TDBRSI,
+            "reverse-continue" => CliCmd::TDBRC,
             _ => panic!(),
         }
     }
 
-    fn cmd_parser<'a>(&mut self, val: &'a String) -> (CliCmd, Option<&'a str>) {
-        let mut val_list = val.split_whitespace();
-        let first_args = val_list.next();
-        let sec_args = val_list.next();
+    // NOTE: return the cmd, its '/fmt' suffix and the rest of the line, which
+    // can be an expr with spaces
+    fn cmd_parser<'a>(&mut self, val: &'a str) -> (CliCmd, Option<&'a str>, Option<&'a str>) {
+        let val = val.trim();
+        let (first_args, sec_args) = match val.split_once(char::is_whitespace) {
+            Some((a, b)) => (a, Some(b.trim())),
+            None => (val, None),
+        };
+        let (first_args, fmt) = match first_args.split_once('/') {
+            Some((a, b)) => (a, Some(b)),
+            None => (first_args, None),
+        };
 
-        match first_args {
-            Some(va) => {
-                for vb in &self.cmd_list {
-                    if va == vb.name {
-                        return (self.map_cmd(vb.name), sec_args);
-                    }
-                }
+        for vb in &self.cmd_list {
+            if first_args == vb.name {
+                return (self.map_cmd(vb.name), fmt, sec_args);
             }
-            None => {}
         }
 
-        (CliCmd::NONE, None)
+        (CliCmd::NONE, None, None)
         // self.cmd_deduce(val);
     }
 
@@ -136,6 +193,185 @@ impl Cli<'_> {
         }
     }
 
+    fn sync_points(&mut self, core: &mut Core) {
+        self.dbg.breakpoints.clear();
+        core.watchpoints_mut().clear();
+        for (_id, v) in self.points.iter() {
+            match v {
+                Point::Break(addr) => self.dbg.breakpoints.push(*addr),
+                Point::WatchMem(_expr, w) => core.watchpoints_mut().push(*w),
+                Point::WatchExpr(..) => {}
+            }
+        }
+    }
+
+    fn add_point(&mut self, core: &mut Core, point: Point) {
+        match &point {
+            Point::Break(addr) => println!("breakpoint {} at {:#x}", self.point_id, addr),
+            Point::WatchMem(expr, _) | Point::WatchExpr(expr, _) => {
+                println!("watchpoint {}: {}", self.point_id, expr)
+            }
+        }
+        self.points.push((self.point_id, point));
+        self.point_id += 1;
+        self.sync_points(core);
+    }
+
+    fn set_watch(&mut self, core: &mut Core, expr: &str) {
+        let point = match eval(core, expr) {
+            Ok(v) => match v.lval {
+                Some((addr, len)) => Point::WatchMem(
+                    expr.to_string(),
+                    Watchpoint {
+                        addr,
+                        len,
+                        kind: WatchKind::Write,
+                    },
+                ),
+                None => Point::WatchExpr(expr.to_string(), v.val),
+            },
+            Err(e) => return println!("\x1b[93m[Warn] {}\x1b[0m", e),
+        };
+        self.add_point(core, point);
+    }
+
+    // NOTE: the value watchpoint steps one inst at a time, the others run
+    // at full speed until the core stops
+    fn step_run(&mut self, core: &mut Core, steps: Option<u64>) {
+        let expr = self.points.iter().any(|v| matches!(v.1, Point::WatchExpr(..)));
+        let single = steps.is_some() || expr;
+        let mut cnt = 0u64;
+        loop {
+            let reason = self.dbg.resume(core, single, || false);
+            match reason {
+                StopReason::Step => {}
+                StopReason::Breakpoint => break println!("breakpoint at {:#x}", core.pc()),
+                StopReason::Watch(_kind, addr) => {
+                    break println!("watchpoint at {:#x} hit, pc: {:#x}", addr, core.pc())
+                }
+                StopReason::Exited(code) => break println!("exited with a0: {}", code),
+                StopReason::Interrupt | StopReason::HistoryBegin => break,
+            }
+            cnt += 1;
+            if self.check_watch_expr(core) {
+                break;
+            }
+            if steps.is_some_and(|v| cnt >= v) {
+                break println!("pc: {:#x}", core.pc());
+            }
+            if self.dbg.breakpoints.contains(&core.pc()) {
+                break println!("breakpoint at {:#x}", core.pc());
+            }
+        }
+    }
+
+    fn reverse_run(&mut self, core: &mut Core, steps: Option<u64>) {
+        if core.history().is_none() {
+            return println!("\x1b[93m[Warn] no history, run with --rev-interval\x1b[0m");
+        }
+        match self.dbg.reverse(core, steps) {
+            StopReason::Breakpoint => println!("breakpoint at {:#x}", core.pc()),
+            StopReason::Watch(_kind, addr) => {
+                println!("watchpoint at {:#x} hit, pc: {:#x}", addr, core.pc())
+            }
+            StopReason::HistoryBegin => println!("no more history, pc: {:#x}", core.pc()),
+            _ => println!("pc: {:#x}", core.pc()),
+        }
+        // NOTE: the expr watchpoints are not checked backward, only the values
+        // are taken back
+        for (_id, v) in self.points.iter_mut() {
+            if let Point::WatchExpr(expr, last) = v {
+                if let Ok(v) = eval(core, expr) {
+                    *last = v.val;
+                }
+            }
+        }
+    }
+
+    fn check_watch_expr(&mut self, core: &mut Core) -> bool {
+        let mut hit = false;
+        for (id, v) in self.points.iter_mut() {
+            if let Point::WatchExpr(expr, last) = v {
+                let val = match eval(core, expr) {
+                    Ok(v) => v.val,
+                    Err(_e) => continue,
+                };
+                if val != *last {
+                    println!("watchpoint {}: {}", id, expr);
+                    println!("old: {:#x} new: {:#x}, pc: {:#x}", last, val, core.pc());
+                    *last = val;
+                    hit = true;
+                }
+            }
+        }
+        hit
+    }
+
+    fn print_points(&self, watch: bool) {
+        for (id, v) in self.points.iter() {
+            match v {
+                Point::Break(addr) if !watch => println!("{}: breakpoint at {:#x}", id, addr),
+                Point::WatchMem(expr, w) if watch => {
+                    println!("{}: watchpoint {} at {:#x} len {}", id, expr, w.addr, w.len)
+                }
+                Point::WatchExpr(expr, val) if watch => {
+                    println!("{}: watchpoint {} = {:#x}", id, expr, val)
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // NOTE: 'x' prints the words, 'd' the signed words, 'i' the insts
+    fn examine(&self, core: &mut Core, fmt: &str, args: &str) {
+        let (num, expr) = match args.split_once(char::is_whitespace) {
+            Some((a, b)) => (a.parse::<u64>().ok(), b),
+            None => (Some(1), args),
+        };
+        let (num, addr) = match (num, eval(core, expr)) {
+            (Some(num), Ok(v)) => (num, v.val),
+            (None, _) => return println!("\x1b[93m[Warn] invalid number\x1b[0m"),
+            (_, Err(e)) => return println!("\x1b[93m[Warn] {}\x1b[0m", e),
+        };
+        if fmt == "i" {
+            return core.dump_insts(addr, num, None);
+        }
+        for i in 0..num {
+            let word = match core.debug_read(addr.wrapping_add(i * 4), 4) {
+                Some(v) => u32::from_le_bytes([v[0], v[1], v[2], v[3]]),
+                None => return println!("cannot access mem at {:#x}", addr.wrapping_add(i * 4)),
+            };
+            if i % 4 == 0 {
+                print!("{:#x}:", addr.wrapping_add(i * 4));
+            }
+            match fmt {
+                "d" => print!(" {:>11}", word as i32),
+                _ => print!(" 0x{:08x}", word),
+            }
+            if i % 4 == 3 || i == num - 1 {
+                println!();
+            }
+        }
+    }
+
+    // NOTE: 'disas [N [expr]]', 8 insts from the pc by default
+    fn disas(&self, core: &mut Core, args: Option<&str>) {
+        let args = args.map(|v| v.split_once(char::is_whitespace).unwrap_or((v, "")));
+        let (num, expr) = match args {
+            Some((a, b)) => (a.parse::<u64>().ok(), b.trim()),
+            None => (Some(8), ""),
+        };
+        let addr = match expr {
+            "" => Ok(core.pc()),
+            v => eval(core, v).map(|v| v.val),
+        };
+        match (num, addr) {
+            (Some(num), Ok(addr)) => core.dump_insts(addr, num, Some(core.pc())),
+            (None, _) => println!("\x1b[93m[Warn] invalid number\x1b[0m"),
+            (_, Err(e)) => println!("\x1b[93m[Warn] {}\x1b[0m", e),
+        }
+    }
+
     pub fn inter_mode(&mut self, core: &mut Core) {
         println!("\x1b[92mTreeCore RISCV ISA Simulator 0.0.1\x1b[0m");
         println!("\x1b[92m[last-release] on Ubuntu 20.04 LTS\x1b[0m");
@@ -145,7 +381,9 @@ impl Cli<'_> {
             0x97, 0x02, 0x00, 0x00, 0x23, 0xb8, 0x02, 0x00, 0x03, 0xb5, 0x02, 0x01, 0x6b, 0x00,
             0x00, 0x00, 0xef, 0xbe, 0xad, 0xde,
         ];
-        core.load_bin_file(dummy_bin);
+        if let Err(e) = core.load_bin_file(dummy_bin) {
+            println!("\x1b[91m[Loading Failed] {}\x1b[0m", e);
+        }
 
         let mut input_dat = String::new();
         loop {
@@ -154,95 +392,253 @@ impl Cli<'_> {
             match stdin().read_line(&mut input_dat) {
                 Ok(_v) => {
                     // print!("[debug]{}", input_dat);
-                    let (fir_cmd, sec_cmd) = self.cmd_parser(&input_dat);
-                    match fir_cmd {
-                        CliCmd::NONE => {
-                            println!(
-                                "\x1b[93m[Warn] no support cmd, Type 'help' to get all legal cmds\x1b[0m"
-                            );
-                        }
-                        CliCmd::HELP => {
-                            self.print_help();
-                        }
-                        CliCmd::QUIT => break,
-                        CliCmd::RUN => {
-                            core.reset();
-                            core.run_simu(None, None, RunMode::Normal); // NOTE: now just for cmd binary
-                        }
-                        CliCmd::LOAD => match sec_cmd {
-                            Some(v) => {
-                                println!("\x1b[93m[binary loading]...\x1b[0m");
-                                match File::open(v) {
-                                    Ok(mut file) => {
-                                        let mut contents = vec![];
-                                        match file.read_to_end(&mut contents) {
-                                            Ok(_v) => {
-                                                println!("\x1b[92m[Loading Success]...\x1b[0m");
-                                                core.load_bin_file(contents);
-                                            }
-                                            Err(_e) => panic!(),
-                                        }
+                    if !self.exec_line(core, &input_dat) {
+                        break;
+                    }
+                    input_dat.clear();
+                }
+                Err(e) => {
+                    println!("[err]: {}", e);
+                    panic!()
+                }
+            }
+        }
+    }
+
+    // NOTE: exec one cmd line, return false to quit
+    fn exec_line(&mut self, core: &mut Core, line: &str) -> bool {
+        let (fir_cmd, fmt, sec_cmd) = self.cmd_parser(line);
+        match fir_cmd {
+            CliCmd::NONE => {
+                println!(
+                    "\x1b[93m[Warn] no support cmd, Type 'help' to get all legal cmds\x1b[0m"
+                );
+            }
+            CliCmd::HELP => {
+                self.print_help();
+            }
+            CliCmd::QUIT => return false,
+            CliCmd::RUN => {
+                core.reset();
+                self.step_run(core, None); // NOTE: now just for cmd binary
+            }
+            CliCmd::LOAD => match sec_cmd {
+                Some(v) => {
+                    println!("\x1b[93m[binary loading]...\x1b[0m");
+                    match File::open(v) {
+                        Ok(mut file) => {
+                            let mut contents = vec![];
+                            match file.read_to_end(&mut contents) {
+                                Ok(_v) if elf::is_elf(&contents) => {
+                                    match core.load_elf_file(contents) {
+                                        Ok(()) => println!("\x1b[92m[Loading Success]...\x1b[0m"),
+                                        Err(e) => println!("\x1b[91m[Loading Failed] {}\x1b[0m", e),
                                     }
-                                    Err(_e) => panic!(),
                                 }
+                                Ok(_v) => match core.load_bin_file(contents) {
+                                    Ok(()) => println!("\x1b[92m[Loading Success]...\x1b[0m"),
+                                    Err(e) => println!("\x1b[91m[Loading Failed] {}\x1b[0m", e),
+                                },
+                                Err(_e) => panic!(),
                             }
-                            None => println!(
-                                "\x1b[93m[Warn] none binary path, please type right one\x1b[0m"
-                            ),
-                        },
-                        CliCmd::TDB => {
-                            println!("run tdb..."); // NOTE: no impl
-                        }
-                        CliCmd::TDBC => {
-                            println!("run tdb r ..."); // NOTE: no impl
                         }
-                        CliCmd::TDBSI => {
-                            match sec_cmd {
-                                Some(v) => {
-                                    // println!("v: {}", v.parse::<u64>().unwrap());
-                                    let num = v.parse::<u64>().unwrap();
-                                    core.run_simu(None, None, RunMode::Debug(num));
-                                }
-                                _ => println!(
-                                    "\x1b[93m[Warn] no support params, Type 'help' to get all legal cmds\x1b[0m"
-                                ),
-                            }
-                        }
-                        CliCmd::TDBINFO => {
-                            match sec_cmd {
-                                Some(v) => {
-                                    if v == "r" {
-                                        let mut count_vec: Vec<(&String, &u8)> = core.reg().alias.iter().collect();
-                                        count_vec.sort_by(|a, b| a.1.cmp(b.1));
-                                        let mut cnt = 1;
-                                        for v in count_vec.iter() {
-                                            if cnt % 4 == 0 || *v.1 == 31 {
-                                                println!("{} = {:016x}", v.0, core.reg().x[*v.1 as usize]);
-                                            } else {
-                                                print!("{} = {:016x} ", v.0, core.reg().x[*v.1 as usize]);
-                                            }
-                                            cnt += 1;
-                                        }
-                                    } else if v == "w" {
-                                        println!("w print");
-                                    }
+                        Err(_e) => panic!(),
+                    }
+                }
+                None => println!(
+                    "\x1b[93m[Warn] none binary path, please type right one\x1b[0m"
+                ),
+            },
+            CliCmd::TDB => {
+                // NOTE: restart the program and stop before its first inst
+                core.reset();
+                println!("run tdb... pc: {:#x}", core.pc());
+            }
+            CliCmd::TDBC => {
+                self.step_run(core, None);
+            }
+            CliCmd::TDBSI => {
+                match sec_cmd.map_or(Ok(1), |v| v.parse::<u64>()) {
+                    Ok(num) => self.step_run(core, Some(num)),
+                    _ => println!(
+                        "\x1b[93m[Warn] no support params, Type 'help' to get all legal cmds\x1b[0m"
+                    ),
+                }
+            }
+            CliCmd::TDBINFO => {
+                match sec_cmd {
+                    Some(v) => {
+                        if v == "r" {
+                            let mut count_vec: Vec<(&String, &u8)> = core.reg().alias.iter().collect();
+                            count_vec.sort_by(|a, b| a.1.cmp(b.1));
+                            let mut cnt = 1;
+                            for v in count_vec.iter() {
+                                if cnt % 4 == 0 || *v.1 == 31 {
+                                    println!("{} = {:016x}", v.0, core.reg().x[*v.1 as usize]);
+                                } else {
+                                    print!("{} = {:016x} ", v.0, core.reg().x[*v.1 as usize]);
                                 }
-                                _ => println!(
-                                    "\x1b[93m[Warn] no support params, Type 'help' to get all legal cmds\x1b[0m"
-                                ),
+                                cnt += 1;
                             }
-                        }
-                        CliCmd::TDBX => {
-                            println!("tdb x cmd: {:?}", sec_cmd);
+                        } else if v == "b" || v == "w" {
+                            self.print_points(v == "w");
                         }
                     }
-                    input_dat.clear();
-                }
-                Err(e) => {
-                    println!("[err]: {}", e);
-                    panic!()
+                    _ => println!(
+                        "\x1b[93m[Warn] no support params, Type 'help' to get all legal cmds\x1b[0m"
+                    ),
                 }
             }
+            CliCmd::TDBX => match (fmt.unwrap_or("x"), sec_cmd) {
+                (f @ ("x" | "d" | "i"), Some(v)) => self.examine(core, f, v),
+                _ => println!(
+                    "\x1b[93m[Warn] no support params, Type 'help' to get all legal cmds\x1b[0m"
+                ),
+            },
+            CliCmd::TDBB => match sec_cmd.map(|v| eval(core, v)) {
+                Some(Ok(v)) => self.add_point(core, Point::Break(v.val)),
+                Some(Err(e)) => println!("\x1b[93m[Warn] {}\x1b[0m", e),
+                None => println!("\x1b[93m[Warn] need an addr or a symbol\x1b[0m"),
+            },
+            CliCmd::TDBW => match sec_cmd {
+                Some(v) => self.set_watch(core, v),
+                None => println!("\x1b[93m[Warn] need an expr\x1b[0m"),
+            },
+            CliCmd::TDBD => match sec_cmd.and_then(|v| v.parse::<u64>().ok()) {
+                Some(id) if self.points.iter().any(|v| v.0 == id) => {
+                    self.points.retain(|v| v.0 != id);
+                    self.sync_points(core);
+                }
+                _ => println!("\x1b[93m[Warn] no such point\x1b[0m"),
+            },
+            CliCmd::TDBDISAS => self.disas(core, sec_cmd),
+            CliCmd::TDBRSI => match sec_cmd.map_or(Ok(1), |v| v.parse::<u64>()) {
+                Ok(num) => self.reverse_run(core, Some(num)),
+                _ => println!(
+                    "\x1b[93m[Warn] no support params, Type 'help' to get all legal cmds\x1b[0m"
+                ),
+            },
+            CliCmd::TDBRC => self.reverse_run(core, None),
         }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cli, Point};
+    use crate::config::{CoreConfig, XLen};
+    use crate::core::Core;
+
+    // NOTE: li a0, 5; addi a0, a0, 1; auipc t0, 0; sd a0, 0x100(t0); the end inst
+    const PROG: [u32; 5] = [0x0050_0513, 0x0015_0513, 0x0000_0297, 0x10a2_b023, 0x6b];
+
+    fn new_core() -> Core {
+        let mut core = Core::new(
+            String::from("none"),
+            vec![],
+            XLen::X64,
+            0x8000_0000,
+            0x6b,
+            CoreConfig::default(),
+        );
+        core.load_bin_file(PROG.iter().flat_map(|v| v.to_le_bytes()).collect()).unwrap();
+        core
+    }
+
+    #[test]
+    fn cli_points() {
+        let mut core = new_core();
+        let mut cli = Cli::new();
+        assert!(cli.exec_line(&mut core, "b 0x80000008\n"));
+        assert!(cli.exec_line(&mut core, "w *(u64*)0x80000108\n"));
+        assert!(cli.exec_line(&mut core, "w $a0 + 1\n"));
+        assert!(cli.exec_line(&mut core, "info b\n"));
+        assert!(cli.exec_line(&mut core, "info w\n"));
+        assert_eq!(cli.dbg.breakpoints, vec![0x8000_0008]);
+        assert_eq!(core.watchpoints_mut().len(), 1);
+        assert_eq!(core.watchpoints_mut()[0].addr, 0x8000_0108);
+        assert!(matches!(cli.points[2], (3, Point::WatchExpr(_, 1))));
+
+        // NOTE: an unknown id and a bad expr change nothing
+        assert!(cli.exec_line(&mut core, "d 9\n"));
+        assert!(cli.exec_line(&mut core, "b (1 + 2\n"));
+        assert!(cli.exec_line(&mut core, "w\n"));
+        assert_eq!(cli.points.len(), 3);
+        assert!(cli.exec_line(&mut core, "d 1\n"));
+        assert!(cli.exec_line(&mut core, "d 2\n"));
+        assert!(cli.dbg.breakpoints.is_empty());
+        assert!(core.watchpoints_mut().is_empty());
+        assert_eq!(cli.points.len(), 1);
+        // NOTE: the ids are not reused
+        assert!(cli.exec_line(&mut core, "b 0x80000004\n"));
+        assert_eq!(cli.points[1].0, 4);
+        assert!(!cli.exec_line(&mut core, "quit\n"));
+    }
+
+    #[test]
+    fn cli_examine() {
+        let mut core = new_core();
+        let mut cli = Cli::new();
+        for v in [
+            "x 4 0x80000000",
+            "x/x 5 $pc",
+            "x/d 2 0x80000000",
+            "x/i 5 0x80000000",
+            "x 4 0xfffffffffffffffc",
+            "x 2 0xfffffffffffffffc",
+            "x/d 0 0x80000000",
+            "x y 0x80000000",
+            "x 1 $nope",
+        ] {
+            assert!(cli.exec_line(&mut core, v));
+        }
+        assert_eq!((core.pc(), core.inst_num()), (0x8000_0000, 0));
+    }
+
+    #[test]
+    fn cli_step_run() {
+        let mut core = new_core();
+        let mut cli = Cli::new();
+        assert!(cli.exec_line(&mut core, "tdb\n"));
+        assert!(cli.exec_line(&mut core, "si 2\n"));
+        assert_eq!((core.pc(), core.reg().x[10]), (0x8000_0008, 6));
+        assert!(cli.exec_line(&mut core, "si\n"));
+        assert_eq!(core.pc(), 0x8000_000c);
+
+        assert!(cli.exec_line(&mut core, "b 0x80000004\n"));
+        assert!(cli.exec_line(&mut core, "tdb\n"));
+        assert!(cli.exec_line(&mut core, "c\n"));
+        assert_eq!((core.pc(), core.inst_num()), (0x8000_0004, 1));
+
+        assert!(cli.exec_line(&mut core, "w *(u64*)0x80000108\n"));
+        assert!(cli.exec_line(&mut core, "c\n"));
+        assert_eq!(core.pc(), 0x8000_0010);
+        assert_eq!(core.debug_read(0x8000_0108, 4).unwrap()[0], 6);
+
+        assert!(cli.exec_line(&mut core, "c\n"));
+        assert_eq!((core.pc(), core.inst_num()), (0x8000_0010, 4));
+        // NOTE: 'run' restarts and stops at the breakpoint again
+        assert!(cli.exec_line(&mut core, "run\n"));
+        assert_eq!((core.pc(), core.inst_num()), (0x8000_0004, 1));
+    }
+
+    #[test]
+    fn cli_watch_expr() {
+        let mut core = new_core();
+        let mut cli = Cli::new();
+        assert!(cli.exec_line(&mut core, "tdb\n"));
+        assert!(cli.exec_line(&mut core, "w $a0 > 5\n"));
+        assert!(matches!(cli.points[0].1, Point::WatchExpr(_, 0)));
+        assert!(cli.exec_line(&mut core, "c\n"));
+        assert_eq!((core.pc(), core.reg().x[10]), (0x8000_0008, 6));
+        assert!(matches!(cli.points[0].1, Point::WatchExpr(_, 1)));
+
+        // NOTE: a deleted expr stops nothing, the run goes to the end
+        assert!(cli.exec_line(&mut core, "d 1\n"));
+        assert!(cli.exec_line(&mut core, "w $t0\n"));
+        assert!(cli.exec_line(&mut core, "d 2\n"));
+        assert!(cli.exec_line(&mut core, "c\n"));
+        assert_eq!((core.pc(), core.inst_num()), (0x8000_0010, 4));
     }
 }
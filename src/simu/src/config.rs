@@ -1,5 +1,59 @@
-#[derive(Debug)]
+use crate::bpred::Predictor;
+use crate::cache::CacheConfig;
+use crate::mmu::AdMode;
+use crate::timing::TimingModel;
+
+#[derive(Clone, Copy, Debug)]
 pub enum XLen {
     X32,
     X64,
-}
\ No newline at end of file
+}
+
+// NOTE: the misaligned load/store is split by the hardware, or traps to let
+// the software emulate it
+#[derive(Clone, Copy, Debug)]
+pub enum MisalignMode {
+    Hardware,
+    Trap,
+}
+
+// NOTE: the options of the core which are selected at startup
+pub struct CoreConfig {
+    pub mem_size: u64, // dram size in bytes
+    pub ad_mode: AdMode,
+    pub tlb_entries: usize, // 0 disables the tlb
+    pub tlb_ways: usize,
+    pub pmp_entries: usize, // 0, 16 or 64
+    pub misalign: MisalignMode,
+    pub timing: Option<Box<dyn TimingModel>>, // None only counts one cycle per inst
+    pub icache: Option<CacheConfig>, // None skips the level
+    pub dcache: Option<CacheConfig>,
+    pub l2: Option<CacheConfig>,
+    pub cache_ranges: Vec<(u64, u64)>, // [lo, hi) of the pc with its own cache stats
+    pub bpred: Option<Box<dyn Predictor>>, // None skips the branch prediction
+    pub ras_entries: usize,
+    pub rev_interval: Option<u64>, // insts between the snapshots, None disables the reverse exec
+    pub rev_snaps: usize,
+}
+
+impl Default for CoreConfig {
+    fn default() -> Self {
+        CoreConfig {
+            mem_size: 128 * 1024 * 1024,
+            ad_mode: AdMode::Svade,
+            tlb_entries: 64,
+            tlb_ways: 4,
+            pmp_entries: 16,
+            misalign: MisalignMode::Hardware,
+            timing: None,
+            icache: None,
+            dcache: None,
+            l2: None,
+            cache_ranges: vec![],
+            bpred: None,
+            ras_entries: 8,
+            rev_interval: None,
+            rev_snaps: 64,
+        }
+    }
+}
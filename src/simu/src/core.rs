@@ -1,34 +1,32 @@
-use crate::config::XLen;
-use crate::csr;
+use crate::bpred::BranchPred;
+use crate::bus::Bus;
+use crate::cache::{Cache, CacheSys};
+use crate::config::{CoreConfig, MisalignMode, XLen};
+use crate::csr::{self, CsrFile, CsrHook};
 use crate::data::Word;
+use crate::debug::{WatchKind, Watchpoint};
 use crate::decode::Decode;
-use crate::device::Device;
-use crate::inst::{get_inst_name, get_instruction_type, Inst, InstType};
-use crate::mmu::{AddrMode, MAType};
+use crate::disasm::Disasm;
+use crate::device::{Device, PLIC_CTX_MMODE, PLIC_CTX_SMODE};
+use crate::elf::{self, SymTab};
+use crate::fpu::{self, FpFmt, IntFmt, RoundMode};
+use crate::mem::{BOOT_ROM_BASE, BOOT_ROM_SIZE, DRAM_BASE, DRAM_HIGH_BASE, DRAM_LOW_SIZE};
+use crate::inst::{get_inst_class, get_inst_name, get_instruction_type, Inst, InstClass, InstType};
+use crate::mmu::{
+    page_table_shape, AdMode, AddrMode, MAType, Tlb, PTE_A, PTE_D, PTE_G, PTE_R, PTE_U, PTE_V,
+    PTE_W, PTE_X,
+};
+use crate::pmp::Pmp;
 use crate::privilege::{
     get_exception_cause, get_priv_encoding, Exception, ExceptionType, PrivMode,
 };
-use crate::regfile::Regfile;
-use crate::trace::{etrace, itrace, log, rtrace, FTrace};
+use crate::regfile::{FRegfile, Regfile};
+use crate::rev::{CpuState, History};
+use crate::timing::{Stall, Timing};
+use crate::trace::{ctrace, etrace, itrace, rtrace, FTrace};
+use std::error::Error;
 use std::sync::mpsc;
 
-// const self.start_addr: u64 = 0x1000u64;
-const MEM_CAPACITY: usize = 128 * 1024 * 1024; // mem use: 128MB
-const CSR_CAPACITY: usize = 4096;
-const PERIF_START_ADDR: u64 = 0xa1000000u64;
-const PERIF_ADDR_SIZE: u64 = 0x1000u64;
-const SERIAL_START_OFFSET: u64 = 0x3F8u64;
-// const SERIAL_ADDR_SIZE: u64 = 0x4u64;
-const RTC_START_OFFSET: u64 = 0x48u64;
-const RTC_ADDR_SIZE: u64 = 0x08u64; // HACK: addr is surplus?
-const KDB_START_OFFSET: u64 = 0x60u64;
-const KDB_ADDR_SIZE: u64 = 0x02u64; // only device -> core
-const VGA_VGACTL_START_OFFSET: u64 = 0x100u64;
-const VGA_SYNC_START_OFFSET: u64 = VGA_VGACTL_START_OFFSET + 4u64;
-const VGA_SYNC_ADDR_SIZE: u64 = 0x4u64;
-const VGA_FRAME_BUF_ADDR_START: u64 = 0xa0000000u64;
-const VGA_FRAME_BUF_ADDR_SIZE: u64 = 0x200000u64;
-
 pub enum RunMode {
     Normal,
     Debug(u64),
@@ -36,20 +34,39 @@ pub enum RunMode {
 
 pub struct Core {
     regfile: Regfile,
+    fregfile: FRegfile,
     pc: u64,
+    inst_pc: u64, // pc of the inst in exec, 'pc' already points to the next one
+    inst_raw: u32, // raw fetched bits of the inst in exec, reported as mtval
     start_addr: u64,
+    entry_addr: u64, // pc after reset, e_entry for the elf file
     end_inst: u32,
     ppn: u64,
+    asid: u16,
     priv_mode: PrivMode,
     addr_mode: AddrMode,
-    csr: [u64; CSR_CAPACITY],
-    mem: Vec<u8>,
+    ad_mode: AdMode,
+    tlb: Tlb,
+    pmp: Pmp,
+    misalign: MisalignMode,
+    timing: Option<Timing>,
+    cache: Option<CacheSys>, // None keeps the access path free of the cache model
+    bpred: Option<BranchPred>,
+    watch: Vec<Watchpoint>,
+    watch_hit: Option<(WatchKind, u64)>, // the first hit since the last take
+    history: Option<History>, // None skips the snapshots and the undo log
+    csr: CsrFile,
+    bus: Bus,
     dev: Device,
     inst_num: u64,
+    reservation: Option<u64>,
     xlen: XLen,
     dbg_level: String,
     trace_type: Vec<String>,
     ftr: FTrace,
+    symtab: SymTab,
+    dis: Disasm,
+    image: Vec<(u64, Vec<u8>)>, // loaded data and its phy addr, restored by reset
 }
 
 impl Core {
@@ -59,69 +76,231 @@ impl Core {
         xlen_val: XLen,
         start_addr: u64,
         end_inst: u32,
+        cfg: CoreConfig,
     ) -> Self {
+        let mem_size = cfg.mem_size;
         println!("trace type: {:?}", trace_type);
+        let mut bus = Bus::new();
+        let dev = Device::new();
+        let res = bus
+            .add_rom("bootrom", BOOT_ROM_BASE, BOOT_ROM_SIZE)
+            .and_then(|_| bus.add_ram("dram", DRAM_BASE, mem_size.min(DRAM_LOW_SIZE)))
+            .and_then(|_| match mem_size > DRAM_LOW_SIZE {
+                true => bus.add_ram("dram-high", DRAM_HIGH_BASE, mem_size - DRAM_LOW_SIZE),
+                false => Ok(()),
+            })
+            .and_then(|_| dev.attach(&mut bus));
+        match res {
+            Ok(()) => {}
+            Err(e) => panic!("{}", e),
+        }
+        // NOTE: the bus logs the written pages only for the reverse exec
+        bus.set_journal(cfg.rev_interval.is_some());
+        let mut csr = CsrFile::new(xlen_val);
+        csr.set_pmp_num(cfg.pmp_entries);
+        let ranges = &cfg.cache_ranges;
+        let (regfile, fregfile) = (Regfile::new(), FRegfile::new());
+        let dis = Disasm::new(&regfile, &fregfile);
+        let cache = CacheSys {
+            icache: cfg.icache.map(|v| Cache::new("icache", v, ranges)),
+            dcache: cfg.dcache.map(|v| Cache::new("dcache", v, ranges)),
+            l2: cfg.l2.map(|v| Cache::new("l2", v, ranges)),
+        };
         Core {
-            regfile: Regfile::new(),
+            regfile,
+            fregfile,
             pc: 0u64,
+            inst_pc: 0u64,
+            inst_raw: 0u32,
             ppn: 0u64,
+            asid: 0u16,
             start_addr: start_addr,
+            entry_addr: start_addr,
             end_inst: end_inst,
             priv_mode: PrivMode::Machine,
             addr_mode: AddrMode::None,
-            csr: [0; CSR_CAPACITY], // NOTE: need to prepare specific val for reg, such as mhardid
-            mem: Vec::with_capacity(MEM_CAPACITY),
-            dev: Device::new(),
+            ad_mode: cfg.ad_mode,
+            tlb: Tlb::new(cfg.tlb_entries, cfg.tlb_ways),
+            pmp: Pmp::new(cfg.pmp_entries),
+            misalign: cfg.misalign,
+            timing: cfg.timing.map(Timing::new),
+            cache: match cache.enabled() {
+                true => Some(cache),
+                false => None,
+            },
+            bpred: cfg.bpred.map(|v| BranchPred::new(v, cfg.ras_entries)),
+            watch: vec![],
+            watch_hit: None,
+            history: cfg.rev_interval.map(|v| History::new(v, cfg.rev_snaps)),
+            csr,
+            bus,
+            dev,
             inst_num: 0u64,
+            reservation: None,
             xlen: xlen_val,
             dbg_level: dbg_level,
             trace_type: trace_type,
-            ftr: FTrace::new("test"),
+            ftr: FTrace::new(),
+            symtab: SymTab::new(),
+            dis,
+            image: vec![],
         }
     }
 
-    // NOTE: like 'new' oper, but dont reset mem
+    // NOTE: like 'new' oper, the mem is restored to the loaded image
     pub fn reset(&mut self) {
+        self.load_image();
         self.regfile.reset();
-        self.pc = self.start_addr;
+        self.fregfile.reset();
+        self.pc = self.entry_addr;
+        self.inst_pc = self.entry_addr;
+        self.inst_raw = 0u32;
         self.ppn = 0u64;
+        self.asid = 0u16;
         self.priv_mode = PrivMode::Machine;
         self.addr_mode = AddrMode::None;
-        self.csr = [0; CSR_CAPACITY];
+        self.tlb.reset();
+        if let Some(v) = self.timing.as_mut() {
+            v.reset();
+        }
+        if let Some(v) = self.cache.as_mut() {
+            v.reset();
+        }
+        if let Some(v) = self.bpred.as_mut() {
+            v.reset();
+        }
+        self.watch_hit = None;
+        self.csr.reset();
+        self.pmp.update(&self.csr);
         self.dev.reset();
         self.inst_num = 0u64;
+        self.reservation = None;
+        if let Some(v) = self.history.as_mut() {
+            v.clear();
+        }
+        self.bus.take_journal();
+    }
+
+    // NOTE: new peripherals are mapped here without touching the core
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
     }
 
-    pub fn load_bin_file(&mut self, data: Vec<u8>) {
-        // clear memory
-        for _i in 0..MEM_CAPACITY {
-            // BUG: error if reset!
-            self.mem.push(0);
+    // NOTE: the untouched pages are never allocated, so the clear and the
+    // reload are cheap even for a large dram
+    fn load_image(&mut self) {
+        self.bus.clear_mem();
+        for (addr, data) in &self.image {
+            match self.bus.load(*addr, data) {
+                Ok(()) => {}
+                Err(_e) => panic!("[load]image at {:#x} is out of mem", addr),
+            }
         }
-        for i in 0..data.len() {
-            // HACK: 0x8000_0000 need mem map
-            self.mem[i] = data[i];
+    }
+
+    // NOTE: the bin file is placed at the start addr, in the boot rom or the dram
+    pub fn load_bin_file(&mut self, data: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        if !self.bus.is_mem(self.start_addr, data.len() as u64) {
+            return Err(format!("bin file at {:#x} is out of memory", self.start_addr).into());
         }
-        self.pc = self.start_addr;
-        self.dev.rtc.val_set_load(); // set load time for perf statistic
+        self.image = vec![(self.start_addr, data)];
+        self.load_image();
+        self.entry_addr = self.start_addr;
+        self.pc = self.entry_addr;
+        self.symtab = SymTab::new();
+        self.dev.rtc.borrow_mut().val_set_load(); // set load time for perf statistic
+        Ok(())
+    }
+
+    // NOTE: PT_LOAD segments are placed at their physical addr, the bss part
+    // is zero filled and the pc starts from e_entry
+    pub fn load_elf_file(&mut self, data: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let elf_file = elf::parse(&data)?;
+        let mut image = vec![];
+        for seg in elf_file.segments {
+            // NOTE: the bss part is zero after the clear, only its range is checked
+            if !self.bus.is_mem(seg.paddr, seg.mem_size.max(seg.data.len() as u64)) {
+                return Err(format!("segment at {:#x} is out of memory", seg.paddr).into());
+            }
+            image.push((seg.paddr, seg.data));
+        }
+        self.image = image;
+        self.load_image();
+        self.entry_addr = elf_file.entry;
+        self.pc = self.entry_addr;
+        self.symtab = elf_file.symtab;
+        self.dev.rtc.borrow_mut().val_set_load(); // set load time for perf statistic
+        Ok(())
+    }
+
+    pub fn symtab(&self) -> &SymTab {
+        &self.symtab
+    }
+
+    pub fn tlb(&self) -> &Tlb {
+        &self.tlb
+    }
+
+    pub fn timing(&self) -> Option<&Timing> {
+        self.timing.as_ref()
+    }
+
+    pub fn cache(&self) -> Option<&CacheSys> {
+        self.cache.as_ref()
+    }
+
+    pub fn bpred(&self) -> Option<&BranchPred> {
+        self.bpred.as_ref()
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub fn inst_num(&self) -> u64 {
+        self.inst_num
     }
 
     pub fn check_end(&mut self) -> bool {
-        let end = match self.load_word(self.pc, true) {
-            Ok(w) => w == self.end_inst,
-            Err(_e) => panic!(),
+        // NOTE: a pc which is not mapped is not the end, the fetch raises the
+        // exception. The check is not an access of the program, it translates
+        // the pc by the debug walk, once unless the inst crosses the page
+        let end = match self.debug_addr(self.pc) {
+            Some(addr) if self.pc & 0xFFF <= 0xFFC => {
+                self.bus.is_mem(addr, 4)
+                    && self.bus.read(addr, 4).is_ok_and(|w| w as u32 == self.end_inst)
+            }
+            Some(_) => self.debug_inst(self.pc) == Some(self.end_inst),
+            None => false,
         };
 
         if end {
+            let mut rtc = self.dev.rtc.borrow_mut();
             match self.regfile.x[10] {
                 0 => println!(
                     "\x1b[92mTest Passed, inst_num: {} load: {}ms elapse: {}ms\x1b[0m",
                     self.inst_num,
-                    self.dev.rtc.val_load(),
-                    self.dev.rtc.val_ms()
+                    rtc.val_load(),
+                    rtc.val_ms()
                 ),
                 _ => println!("\x1b[91mTest Failed\x1b[0m"),
             };
+            // NOTE: a high hit rate with few flushes may hide a missing sfence.vma
+            if self.tlb.hit + self.tlb.miss > 0 {
+                println!(
+                    "tlb hit: {} miss: {} flush: {}",
+                    self.tlb.hit, self.tlb.miss, self.tlb.flush
+                );
+            }
+            if let Some(v) = self.timing.as_ref() {
+                println!("{}", v.report());
+            }
+            if let Some(v) = self.cache.as_ref() {
+                println!("{}", v.report());
+            }
+            if let Some(v) = self.bpred.as_ref() {
+                println!("{}", v.report(&self.symtab, self.inst_num, 10));
+            }
         }
         end
     }
@@ -136,6 +315,276 @@ impl Core {
         &self.regfile
     }
 
+    pub fn freg(&self) -> &FRegfile {
+        &self.fregfile
+    }
+
+    pub fn pc(&self) -> u64 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, pc: u64) {
+        self.pc = pc;
+    }
+
+    pub fn xlen(&self) -> XLen {
+        self.xlen
+    }
+
+    // NOTE: x0 stays zero
+    pub fn set_reg(&mut self, idx: usize, val: u64) {
+        if idx != 0 {
+            self.regfile.x[idx] = val as i64;
+        }
+    }
+
+    pub fn set_freg(&mut self, idx: usize, val: u64) {
+        self.fregfile.f[idx] = val;
+    }
+
+    // NOTE: the debugger accesses the csr in any mode, None for the
+    // unimplemented one
+    pub fn debug_csr(&self, addr: u16) -> Option<u64> {
+        match self.csr.exists(addr) {
+            true => Some(self.csr.read(addr)),
+            false => None,
+        }
+    }
+
+    pub fn debug_set_csr(&mut self, addr: u16, val: u64) -> bool {
+        if !self.csr.exists(addr) {
+            return false;
+        }
+        match self.csr.write(addr, val) {
+            CsrHook::None => {}
+            CsrHook::AddrMode => self.update_addr_mode(self.csr.get(csr::CSR_SATP_ADDR)),
+            CsrHook::Pmp => self.pmp.update(&self.csr),
+        }
+        true
+    }
+
+    // NOTE: the debugger access is translated in the current mode by the
+    // debug walk, it bypasses the pmp, the caches and the watchpoints
+    fn debug_addr(&mut self, addr: u64) -> Option<u64> {
+        let (levels, vpn_bits, pte_size) = page_table_shape(&self.addr_mode);
+        let res = match (levels, &self.priv_mode) {
+            (0, _) | (_, PrivMode::Machine) => Some(addr),
+            _ => self.debug_walk(addr, levels, vpn_bits, pte_size),
+        };
+        match self.xlen {
+            XLen::X32 => res.map(|v| v & 0xFFFF_FFFF),
+            XLen::X64 => res,
+        }
+    }
+
+    // NOTE: the walk without side effects for the debugger, it never uses or
+    // fills the tlb, skips the permission and A/D checks and never writes
+    // the pte. Only the ptes in the mem are read
+    fn debug_walk(&mut self, va: u64, levels: u64, vpn_bits: u64, pte_size: u64) -> Option<u64> {
+        let va = match pte_size {
+            4 => va & 0xFFFF_FFFF,
+            _ => {
+                let high = ((va as i64) >> (12 + levels * vpn_bits - 1)) as u64;
+                if high != 0 && high != u64::MAX {
+                    return None;
+                }
+                va
+            }
+        };
+        let mut base = self.ppn << 12;
+        for level in (0..levels).rev() {
+            let vpn = (va >> (12 + level * vpn_bits)) & ((1 << vpn_bits) - 1);
+            let pte_addr = base + vpn * pte_size;
+            if !self.bus.is_mem(pte_addr, pte_size) {
+                return None;
+            }
+            let pte = self.bus.read(pte_addr, pte_size).ok()?;
+            let ppn = match pte_size {
+                4 => (pte >> 10) & 0x3F_FFFF,
+                _ => (pte >> 10) & 0xFFF_FFFF_FFFF,
+            };
+            if pte & PTE_V == 0 || (pte & PTE_R == 0 && pte & PTE_W != 0) || pte >> 54 != 0 {
+                return None;
+            }
+            if pte & (PTE_R | PTE_X) != 0 {
+                let page_mask = (1u64 << (12 + level * vpn_bits)) - 1;
+                return match (ppn << 12) & page_mask {
+                    0 => Some((ppn << 12) | (va & page_mask)),
+                    _ => None,
+                };
+            }
+            base = ppn << 12;
+        }
+        None
+    }
+
+    pub fn debug_read(&mut self, addr: u64, len: usize) -> Option<Vec<u8>> {
+        let mut res = Vec::with_capacity(len);
+        for i in 0..len as u64 {
+            let phy_addr = self.debug_addr(addr.wrapping_add(i))?;
+            res.push(self.bus.read(phy_addr, 1).ok()? as u8);
+        }
+        Some(res)
+    }
+
+    pub fn debug_write(&mut self, addr: u64, data: &[u8]) -> bool {
+        for (i, v) in data.iter().enumerate() {
+            let phy_addr = match self.debug_addr(addr.wrapping_add(i as u64)) {
+                Some(v) => v,
+                None => return false,
+            };
+            if self.bus.write(phy_addr, 1, *v as u64).is_err() {
+                return false;
+            }
+        }
+        true
+    }
+
+    // NOTE: the raw bits of the inst at addr, the halfword for rvc inst
+    pub fn debug_inst(&mut self, addr: u64) -> Option<u32> {
+        let low = self.debug_read(addr, 2)?;
+        let low = u16::from_le_bytes([low[0], low[1]]) as u32;
+        if low & 0x3 != 0x3 {
+            return Some(low);
+        }
+        let high = self.debug_read(addr.wrapping_add(2), 2)?;
+        Some((u16::from_le_bytes([high[0], high[1]]) as u32) << 16 | low)
+    }
+
+    pub fn disasm(&self, pc: u64, raw: u32) -> String {
+        self.dis.disasm(pc, raw, &self.xlen, &self.symtab)
+    }
+
+    // NOTE: print 'num' insts from addr like objdump, the inst at 'mark' is
+    // pointed by '=>'
+    pub fn dump_insts(&mut self, addr: u64, num: u64, mark: Option<u64>) {
+        let mut pc = addr;
+        for _ in 0..num {
+            if let Some(name) = self.symtab.func_at(pc) {
+                println!("{:016x} <{}>:", pc, name);
+            }
+            let raw = match self.debug_inst(pc) {
+                Some(v) => v,
+                None => return println!("cannot access mem at {:#x}", pc),
+            };
+            let (hex, size) = match raw & 0x3 {
+                0x3 => (format!("{:08x}", raw), 4),
+                _ => (format!("{:04x}", raw), 2),
+            };
+            let arrow = match mark == Some(pc) {
+                true => "=>",
+                false => "  ",
+            };
+            println!("{} {:#x}: {:>8}  {}", arrow, pc, hex, self.disasm(pc, raw));
+            pc = pc.wrapping_add(size);
+        }
+    }
+
+    // NOTE: the insts around the crash. The start is a guess, it may be in
+    // the middle of an inst when rvc is mixed in
+    fn crash_dump(&mut self) {
+        println!("crash at pc: {:#x} inst_num: {}", self.inst_pc, self.inst_num);
+        self.dump_insts(self.inst_pc.saturating_sub(16), 9, Some(self.inst_pc));
+    }
+
+    pub fn watchpoints_mut(&mut self) -> &mut Vec<Watchpoint> {
+        &mut self.watch
+    }
+
+    pub fn take_watch_hit(&mut self) -> Option<(WatchKind, u64)> {
+        self.watch_hit.take()
+    }
+
+    // NOTE: one inst, or the trap it raises
+    pub fn step(&mut self) {
+        if self.history.as_ref().is_some_and(|v| v.due(self.inst_num)) {
+            let state = self.save_state();
+            let undo = self.bus.take_journal();
+            if let Some(v) = self.history.as_mut() {
+                v.push(state, undo);
+            }
+        }
+        self.tick();
+        self.inst_num += 1;
+    }
+
+    fn save_state(&self) -> CpuState {
+        CpuState {
+            x: self.regfile.x,
+            f: self.fregfile.f,
+            pc: self.pc,
+            inst_pc: self.inst_pc,
+            inst_raw: self.inst_raw,
+            ppn: self.ppn,
+            asid: self.asid,
+            priv_mode: self.priv_mode,
+            addr_mode: self.addr_mode,
+            reservation: self.reservation,
+            inst_num: self.inst_num,
+            csr: self.csr.clone(),
+            dev: self.dev.save(),
+            timing: self.timing.as_ref().map(|v| v.save()),
+            cache: self.cache.clone(),
+            bpred: self.bpred.clone(),
+        }
+    }
+
+    fn restore_state(&mut self, state: CpuState) {
+        self.regfile.x = state.x;
+        self.fregfile.f = state.f;
+        self.pc = state.pc;
+        self.inst_pc = state.inst_pc;
+        self.inst_raw = state.inst_raw;
+        self.ppn = state.ppn;
+        self.asid = state.asid;
+        self.priv_mode = state.priv_mode;
+        self.addr_mode = state.addr_mode;
+        self.reservation = state.reservation;
+        self.inst_num = state.inst_num;
+        self.csr = state.csr;
+        self.pmp.update(&self.csr);
+        self.dev.restore(&state.dev);
+        if let (Some(v), Some(s)) = (self.timing.as_mut(), state.timing.as_ref()) {
+            v.restore(s);
+        }
+        self.cache = state.cache;
+        self.bpred = state.bpred;
+        // NOTE: the page table may be changed back, the stats are kept
+        let tlb_stats = (self.tlb.hit, self.tlb.miss, self.tlb.flush);
+        self.tlb.reset();
+        (self.tlb.hit, self.tlb.miss, self.tlb.flush) = tlb_stats;
+    }
+
+    // NOTE: go back to the state after 'inst_num' insts, by the last snapshot
+    // before it and the replay. False if it is out of the history
+    pub fn reverse_to(&mut self, inst_num: u64) -> bool {
+        let start = self.history.as_ref().and_then(|v| v.start());
+        if start.is_none_or(|v| inst_num < v) || inst_num > self.inst_num {
+            return false;
+        }
+        let undo = self.bus.take_journal();
+        let (state, undo) = match self.history.as_mut().and_then(|v| v.rewind(inst_num, undo)) {
+            Some(v) => v,
+            None => return false,
+        };
+        self.bus.undo(undo);
+        self.restore_state(state);
+        self.replay(inst_num, |_| {});
+        self.watch_hit = None;
+        true
+    }
+
+    // NOTE: step forward to 'inst_num' with 'each' called after each inst.
+    // The insts are run again, so the uart output is muted
+    pub fn replay(&mut self, inst_num: u64, mut each: impl FnMut(&mut Core)) {
+        self.dev.uart.borrow_mut().set_mute(true);
+        while self.inst_num < inst_num {
+            self.step();
+            each(self);
+        }
+        self.dev.uart.borrow_mut().set_mute(false);
+    }
+
     // HACK: can refactor to improve readability
     fn trace_find(&self, val: &str) -> bool {
         for v in &self.trace_type {
@@ -161,7 +610,7 @@ impl Core {
                             match v.try_recv() {
                                 Ok(vv) => {
                                     // println!("Got: {:?}", v)
-                                    self.dev.kdb.det(vv.0, vv.1);
+                                    self.dev.kdb.borrow_mut().det(vv.0, vv.1);
                                 }
                                 Err(_e) => {}
                             }
@@ -172,12 +621,10 @@ impl Core {
                     if self.check_end() {
                         break;
                     }
-                    self.tick();
-                    self.inst_num += 1;
-                    // log!(self.pc);
-                    if self.dev.vga.sync {
+                    self.step();
+                    if self.dev.vga.borrow().sync {
                         match vga_tx {
-                            Some(ref v) => v.send(self.dev.vga.send_dat()).unwrap(),
+                            Some(ref v) => v.send(self.dev.vga.borrow_mut().send_dat()).unwrap(),
                             None => {}
                         }
                     }
@@ -187,9 +634,7 @@ impl Core {
                 // println!("v: {}", v);
                 let mut cnt = 0;
                 while cnt < v && !self.check_end() {
-                    self.tick();
-                    // log!(self.pc);
-                    self.inst_num += 1;
+                    self.step();
                     cnt += 1;
                 }
             }
@@ -197,26 +642,71 @@ impl Core {
     }
 
     fn tick(&mut self) {
-        match self.tick_wrap() {
-            Ok(()) => {}
-            Err(e) => self.handle_trap(e),
+        self.dev.clint.borrow_mut().update_time();
+        self.csr.set_time(self.dev.clint.borrow().mtime());
+        self.dev.update_irq();
+        self.update_mip();
+        let start = self.timing.as_ref().map_or(0, |v| v.cycles);
+        if let Some((code, to_smode)) = self.pending_interrupt() {
+            self.handle_interrupt(code, to_smode);
+            if let Some(v) = self.timing.as_mut() {
+                v.trap();
+            }
+        }
+
+        // NOTE: the inst which traps does not retire, the cache stalls of the
+        // inst are already charged in its accesses
+        let retired = match self.tick_wrap() {
+            Ok((class, taken)) => {
+                if let Some(v) = self.timing.as_mut() {
+                    v.retire(class, taken);
+                }
+                true
+            }
+            Err(e) => {
+                self.handle_trap(e);
+                if let Some(v) = self.timing.as_mut() {
+                    v.trap();
+                }
+                false
+            }
         };
+        // NOTE: without the timing model each tick is one cycle
+        let cycles = match self.timing.as_ref() {
+            Some(v) => v.cycles - start,
+            None => 1,
+        };
+        self.csr.count(cycles, retired);
         // rtrace(&self.regfile, "ra");
         // rtrace(&self.regfile, "sp");
         // rtrace(&self.regfile, "a4");
         // rtrace(&self.regfile, "t2");
     }
 
-    fn tick_wrap(&mut self) -> Result<(), Exception> {
-        let word = match self.fetch() {
+    // NOTE: return the class of the retired inst and whether it redirects the pc
+    fn tick_wrap(&mut self) -> Result<(InstClass, bool), Exception> {
+        let raw = match self.fetch() {
             Ok(w) => w,
             Err(e) => return Err(e),
         };
-        let inst = Decode::decode(self.pc, word, &self.xlen);
+        self.inst_raw = raw;
+        // NOTE: rvc inst is expanded to its 32-bit equivalent before decode
+        let word = match raw & 0x3 {
+            0x3 => raw,
+            _ => match Decode::expand_compressed(raw as u16, &self.xlen) {
+                0 => return Err(self.illegal_inst()),
+                v => v,
+            },
+        };
+        let inst = match Decode::decode(word, &self.xlen) {
+            Ok(v) => v,
+            Err(_) => return Err(self.illegal_inst()),
+        };
         match self.dbg_level.as_str() {
             "trace" => {
                 if self.trace_find("itrace") {
-                    itrace(self.pc, word, &inst, &[0x83000000u64, 0x88000490u64]);
+                    let asm = self.dis.format(self.inst_pc, word, &inst, &self.symtab);
+                    itrace(self.inst_pc, raw, &asm, &[0x83000000u64, 0x88000490u64]);
                 }
             }
             "err" => {
@@ -226,7 +716,26 @@ impl Core {
             } // HACK:
             _ => {}
         }
-        self.exec(word, inst)
+        let class = get_inst_class(&inst);
+        let next_pc = self.pc;
+        self.exec(word, inst)?;
+        if let (Some(bp), InstClass::Branch | InstClass::Jump) = (self.bpred.as_mut(), class) {
+            if bp.resolve(self.inst_pc, word, next_pc, self.pc) {
+                if let Some(v) = self.timing.as_mut() {
+                    let penalty = v.model().mispredict_penalty();
+                    v.stall(Stall::Mispredict, penalty);
+                }
+            }
+        }
+        Ok((class, self.pc != next_pc))
+    }
+
+    // NOTE: mtval of illegal inst is the raw bits, rvc inst reports its halfword
+    fn illegal_inst(&self) -> Exception {
+        Exception {
+            excpt_type: ExceptionType::IllegalInst,
+            addr: self.inst_raw as u64,
+        }
     }
 
     fn handle_trap(&mut self, excpt: Exception) {
@@ -234,399 +743,577 @@ impl Core {
             etrace(&excpt);
         }
 
+        let cause = get_exception_cause(&excpt);
+        // NOTE: traps taken in m-mode are never delegated
+        let to_smode = !matches!(self.priv_mode, PrivMode::Machine)
+            && (self.csr.get(csr::CSR_MEDELEG_ADDR) >> cause) & 1 == 1;
+        self.trap_enter(cause, excpt.addr, self.inst_pc, to_smode);
+    }
+
+    // NOTE: the msb of xcause marks the interrupt
+    fn intr_bit(&self) -> u64 {
+        match self.xlen {
+            XLen::X32 => 1u64 << 31,
+            XLen::X64 => 1u64 << 63,
+        }
+    }
+
+    fn handle_interrupt(&mut self, code: u64, to_smode: bool) {
+        // NOTE: epc is the next inst to exec, no inst of this tick is fetched yet
+        self.trap_enter(self.intr_bit() | code, 0, self.pc, to_smode);
+    }
+
+    // NOTE: in vectored mode(tvec[1:0] == 1), interrupts jump to base + 4 * code
+    fn trap_vector(&self, tvec: u64, cause: u64) -> u64 {
+        let intr_bit = self.intr_bit();
+        let base = tvec & !0x3;
+        match (tvec & 0x3, cause & intr_bit != 0) {
+            (1, true) => base.wrapping_add(4 * (cause & !intr_bit)),
+            _ => base,
+        }
+    }
+
+    // NOTE: common trap entry, xIE is stacked into xPIE and cleared so the
+    // handler is not interrupted again before it returns. The lr reservation
+    // does not survive a trap
+    fn trap_enter(&mut self, cause: u64, tval: u64, epc: u64, to_smode: bool) {
+        self.reservation = None;
         let cur_priv_encode = get_priv_encoding(&self.priv_mode) as u64;
-        self.priv_mode =
-            match (self.csr[csr::CSR_MEDELEG_ADDR as usize] >> get_exception_cause(&excpt)) & 1 {
-                1u64 => PrivMode::Supervisor,
-                0u64 => PrivMode::Machine,
-                _ => panic!(),
-            };
-        match self.priv_mode {
-            PrivMode::Supervisor => {
-                self.csr[csr::CSR_SEPC_ADDR as usize] = self.pc.wrapping_sub(4);
-                self.csr[csr::CSR_SCAUSE_ADDR as usize] = get_exception_cause(&excpt);
-                self.csr[csr::CSR_STVAL_ADDR as usize] = excpt.addr;
-                self.pc = self.csr[csr::CSR_STVEC_ADDR as usize];
+        let mstatus = self.csr.get(csr::CSR_MSTATUS_ADDR);
+        match to_smode {
+            true => {
+                self.priv_mode = PrivMode::Supervisor;
+                self.csr.set(csr::CSR_SEPC_ADDR, epc);
+                self.csr.set(csr::CSR_SCAUSE_ADDR, cause);
+                self.csr.set(csr::CSR_STVAL_ADDR, tval);
+                self.pc = self.trap_vector(self.csr.get(csr::CSR_STVEC_ADDR), cause);
                 // override SPP bit[8] with the current privilege mode encoding
-                self.csr[csr::CSR_SSTATUS_ADDR as usize] =
-                    (self.csr[csr::CSR_SSTATUS_ADDR as usize] & !0x100)
-                        | ((cur_priv_encode & 1) << 8);
-            }
-            PrivMode::Machine => {
-                self.csr[csr::CSR_MEPC_ADDR as usize] = self.pc.wrapping_sub(4);
-                self.csr[csr::CSR_MCAUSE_ADDR as usize] = get_exception_cause(&excpt);
-                self.csr[csr::CSR_MTVAL_ADDR as usize] = excpt.addr;
-                self.pc = self.csr[csr::CSR_MTVEC_ADDR as usize];
+                // NOTE: sstatus is the view of mstatus
+                let spie = (mstatus & csr::MSTATUS_SIE) << 4;
+                self.csr.set(
+                    csr::CSR_MSTATUS_ADDR,
+                    (mstatus & !(csr::MSTATUS_SPP | csr::MSTATUS_SPIE | csr::MSTATUS_SIE))
+                        | ((cur_priv_encode & 1) << 8)
+                        | spie,
+                );
+            }
+            false => {
+                self.priv_mode = PrivMode::Machine;
+                self.csr.set(csr::CSR_MEPC_ADDR, epc);
+                self.csr.set(csr::CSR_MCAUSE_ADDR, cause);
+                self.csr.set(csr::CSR_MTVAL_ADDR, tval);
+                self.pc = self.trap_vector(self.csr.get(csr::CSR_MTVEC_ADDR), cause);
                 // override MPP bits[12:11] with the current privilege mode encoding
-                self.csr[csr::CSR_MSTATUS_ADDR as usize] =
-                    (self.csr[csr::CSR_MSTATUS_ADDR as usize] & !0x1800)
-                        | ((cur_priv_encode & 0x3) << 11);
+                let mpie = (mstatus & csr::MSTATUS_MIE) << 4;
+                self.csr.set(
+                    csr::CSR_MSTATUS_ADDR,
+                    (mstatus & !(csr::MSTATUS_MPP_MASK | csr::MSTATUS_MPIE | csr::MSTATUS_MIE))
+                        | ((cur_priv_encode & 0x3) << 11)
+                        | mpie,
+                );
             }
-            _ => panic!(),
         }
     }
 
-    fn fetch(&mut self) -> Result<u32, Exception> {
-        let word = match self.load_word(self.pc, true) {
-            Ok(w) => w,
-            Err(_e) => {
-                self.pc = self.pc.wrapping_add(4);
-                return Err(Exception {
-                    excpt_type: ExceptionType::InstPageFault,
-                    addr: self.pc.wrapping_sub(4),
-                }); // NOTE: coverage the LoadPageFault
-            }
+    // NOTE: mip.MTIP/MSIP mirror the clint state, MEIP/SEIP the plic outputs
+    fn update_mip(&mut self) {
+        let mut hw_ip = 0u64;
+        let (clint, plic) = (self.dev.clint.borrow(), self.dev.plic.borrow());
+        if clint.mtip() {
+            hw_ip |= csr::MIP_MTIP;
+        }
+        if clint.msip() {
+            hw_ip |= csr::MIP_MSIP;
+        }
+        if plic.irq(PLIC_CTX_MMODE) {
+            hw_ip |= csr::MIP_MEIP;
+        }
+        if plic.irq(PLIC_CTX_SMODE) {
+            hw_ip |= csr::MIP_SEIP;
+        }
+        drop((clint, plic));
+        self.csr.set_hw_ip(hw_ip);
+    }
+
+    // NOTE: return the highest priority enabled interrupt and whether it is
+    // delegated to s-mode, m-mode interrupts are handled before s-mode ones
+    fn pending_interrupt(&self) -> Option<(u64, bool)> {
+        let pending = self.csr.mip() & self.csr.get(csr::CSR_MIE_ADDR);
+        if pending == 0 {
+            return None;
+        }
+
+        let mstatus = self.csr.get(csr::CSR_MSTATUS_ADDR);
+        let mideleg = self.csr.get(csr::CSR_MIDELEG_ADDR);
+        let (m_enable, s_enable) = match self.priv_mode {
+            PrivMode::Machine => (mstatus & csr::MSTATUS_MIE != 0, false),
+            PrivMode::Supervisor => (true, mstatus & csr::MSTATUS_SIE != 0),
+            _ => (true, true),
         };
-        self.pc = self.pc.wrapping_add(4);
-        Ok(word)
-    }
-
-    fn mmap_load_oper(&mut self, addr: u64) -> u8 {
-        // println!("addr: {:016x}", addr);
-        // HACK: range addr check
-        if addr == PERIF_START_ADDR + SERIAL_START_OFFSET {
-            panic!();
-        } else if addr >= PERIF_START_ADDR + RTC_START_OFFSET
-            && addr <= PERIF_START_ADDR + RTC_START_OFFSET + RTC_ADDR_SIZE
-        {
-            self.dev.rtc.val()
-        } else if addr >= PERIF_START_ADDR + KDB_START_OFFSET
-            && addr < PERIF_START_ADDR + KDB_START_OFFSET + KDB_ADDR_SIZE
-        {
-            if addr == PERIF_START_ADDR + KDB_START_OFFSET {
-                self.dev.kdb.val(false)
-            } else if addr == PERIF_START_ADDR + KDB_START_OFFSET + KDB_ADDR_SIZE - 1u64 {
-                self.dev.kdb.val(true)
-            } else {
-                panic!("[kdb] error addr space")
-            }
-        } else {
-            panic!();
-        }
-    }
-
-    fn mmap_store_oper(&mut self, addr: u64, val: u8) {
-        // println!("addr: {:016x}", addr);
-        if addr == PERIF_START_ADDR + SERIAL_START_OFFSET {
-            self.dev.uart.out(val);
-        } else if addr == PERIF_START_ADDR + RTC_START_OFFSET {
-            panic!();
-        } else if addr >= VGA_FRAME_BUF_ADDR_START
-            && addr <= VGA_FRAME_BUF_ADDR_START + VGA_FRAME_BUF_ADDR_SIZE
-        {
-            //NOTE: need to guard data transfer by use sync flag
-            self.dev.vga.store(addr, val);
-        } else if addr >= PERIF_START_ADDR + VGA_SYNC_START_OFFSET
-            && addr <= PERIF_START_ADDR + VGA_SYNC_START_OFFSET + VGA_SYNC_ADDR_SIZE
-        {
-            self.dev.vga.set_sync(val);
-        } else {
-            panic!();
-        }
-    }
-
-    fn load_phy_mem(&mut self, addr: u64) -> u8 {
-        // HACK: boundery check
-        if addr < self.start_addr {
-            log!(addr);
-            log!(self.start_addr);
-            panic!("[load]mem out of boundery");
-        }
-
-        if addr >= PERIF_START_ADDR && addr <= PERIF_START_ADDR + PERIF_ADDR_SIZE {
-            self.mmap_load_oper(addr) // BUG: bit width!
-        } else {
-            // HACK: need to dbg_level seek for season
-            match self.start_addr {
-                0x8000_0000u64 => self.mem[(addr - self.start_addr) as usize],
-                _ => self.mem[addr as usize],
+        let m_pending = if m_enable { pending & !mideleg } else { 0 };
+        let s_pending = if s_enable { pending & mideleg } else { 0 };
+        // priority: MEI, MSI, MTI, SEI, SSI, STI
+        for (irqs, to_smode) in [(m_pending, false), (s_pending, true)] {
+            for code in [11u64, 3, 7, 9, 1, 5] {
+                if (irqs >> code) & 1 == 1 {
+                    return Some((code, to_smode));
+                }
             }
         }
+        None
     }
 
-    fn load_byte(&mut self, addr: u64, trans: bool) -> Result<u8, Exception> {
+    // NOTE: fetch the low halfword first, the high one is only needed for
+    // a 32-bit inst and can live in the next page
+    // NOTE: with rvc the pc is only misaligned when it is odd
+    fn fetch(&mut self) -> Result<u32, Exception> {
+        self.inst_pc = self.pc;
+        if self.pc & 0x1 != 0 {
+            return Err(Exception {
+                excpt_type: MAType::Exec.misaligned(),
+                addr: self.pc,
+            });
+        }
+        let low = self.read_mem(self.pc, 2, MAType::Exec, true)? as u32;
+
+        if low & 0x3 != 0x3 {
+            self.pc = self.pc.wrapping_add(2);
+            return Ok(low);
+        }
+
+        let high = self.read_mem(self.pc.wrapping_add(2), 2, MAType::Exec, true)? as u32;
+        self.pc = self.pc.wrapping_add(4);
+        Ok((high << 16) | low)
+    }
+
+    // NOTE: the pmp checks the phy addr with the effective mode, also for the
+    // access which is already translated
+    fn phy_addr(
+        &mut self,
+        addr: u64,
+        size: u64,
+        ma_type: MAType,
+        trans: bool,
+    ) -> Result<u64, Exception> {
         let phy_addr = match trans {
-            true => match self.trans_addr(addr, MAType::Read) {
-                Ok(v) => v,
-                Err(_e) => {
-                    return Err(Exception {
-                        excpt_type: ExceptionType::LoadPageFault,
-                        addr: addr,
-                    })
-                }
-            },
+            true => self.trans_addr(addr, ma_type)?,
             false => addr,
         };
-
-        Ok(self.load_phy_mem(match self.xlen {
+        let phy_addr = match self.xlen {
             XLen::X32 => phy_addr & 0xFFFF_FFFF,
             XLen::X64 => phy_addr,
-        }))
+        };
+
+        let priv_val = self.access_priv(&ma_type);
+        match self.pmp.check(phy_addr, size, ma_type, priv_val) {
+            true => Ok(phy_addr),
+            false => Err(Exception {
+                excpt_type: ma_type.access_fault(),
+                addr,
+            }),
+        }
     }
 
-    fn load_halfword(&mut self, addr: u64, trans: bool) -> Result<u16, Exception> {
-        let mut res = 0u16;
-        for i in 0..2 {
-            match self.load_byte(addr.wrapping_add(i), trans) {
-                Ok(v) => res |= (v as u16) << (8 * i),
-                Err(e) => return Err(e),
+    // NOTE: each page has its own translation, so the access across the page
+    // boundary is split into bytes
+    fn cross_page(addr: u64, size: u64, trans: bool) -> bool {
+        trans && size > 1 && (addr & 0xFFF) + size > 0x1000
+    }
+
+    // NOTE: one load is one bus transaction, the device sees the whole access,
+    // the fetch shares the path with its own fault types
+    fn read_mem(
+        &mut self,
+        addr: u64,
+        size: u64,
+        ma_type: MAType,
+        trans: bool,
+    ) -> Result<u64, Exception> {
+        if Core::cross_page(addr, size, trans) {
+            let mut res = 0u64;
+            for i in 0..size {
+                res |= self.read_mem(addr.wrapping_add(i), 1, ma_type, trans)? << (8 * i);
             }
+            return Ok(res);
         }
-        Ok(res)
+
+        let phy_addr = self.phy_addr(addr, size, ma_type, trans)?;
+        self.phy_read(addr, phy_addr, size, ma_type)
     }
 
-    fn load_word(&mut self, addr: u64, trans: bool) -> Result<u32, Exception> {
-        let mut res = 0u32;
-        for i in 0..2 {
-            match self.load_halfword(addr.wrapping_add(i * 2), trans) {
-                Ok(v) => res |= (v as u32) << (8 * i * 2),
-                Err(e) => return Err(e),
-            }
+    // NOTE: the access of the translated addr, the cache model and the
+    // watchpoints see the va, the fault reports it
+    fn phy_read(
+        &mut self,
+        va: u64,
+        phy_addr: u64,
+        size: u64,
+        ma_type: MAType,
+    ) -> Result<u64, Exception> {
+        if self.cache.is_some() {
+            self.cache_access(va, phy_addr, ma_type);
+        }
+        if !self.watch.is_empty() && !matches!(ma_type, MAType::Exec) {
+            self.check_watch(va, size, false);
+        }
+        match self.bus.read(phy_addr, size) {
+            Ok(v) => Ok(v),
+            Err(_e) => Err(Exception {
+                excpt_type: ma_type.access_fault(),
+                addr: va,
+            }),
         }
-        Ok(res)
     }
 
-    fn load_doubleword(&mut self, addr: u64, trans: bool) -> Result<u64, Exception> {
-        let mut res = 0u64;
-        for i in 0..2 {
-            match self.load_word(addr.wrapping_add(i * 4), trans) {
-                Ok(v) => res |= (v as u64) << (8 * i * 4),
-                Err(e) => return Err(e),
-            }
+    fn phy_write(&mut self, va: u64, phy_addr: u64, size: u64, val: u64) -> Result<(), Exception> {
+        if self.cache.is_some() {
+            self.cache_access(va, phy_addr, MAType::Write);
+        }
+        if !self.watch.is_empty() {
+            self.check_watch(va, size, true);
+        }
+        // NOTE: a store to the reserved doubleword breaks the lr/sc pair
+        if self.reservation.is_some_and(|v| v & !7 == phy_addr & !7) {
+            self.reservation = None;
+        }
+        match self.bus.write(phy_addr, size, val) {
+            Ok(()) => Ok(()),
+            Err(_e) => Err(Exception {
+                excpt_type: MAType::Write.access_fault(),
+                addr: va,
+            }),
         }
-        Ok(res)
     }
 
-    fn store_phy_mem(&mut self, addr: u64, val: u8) {
-        if addr < self.start_addr {
-            log!(addr);
-            log!(self.start_addr);
-            panic!("[store]mem out of boundery");
+    // NOTE: only the mem regions are cached, the device access and the page
+    // walk bypass the caches. Each missing level costs one miss penalty
+    fn cache_access(&mut self, va: u64, pa: u64, ma_type: MAType) {
+        let cache = match self.cache.as_mut() {
+            Some(v) if self.bus.is_mem(pa, 1) => v,
+            _ => return,
+        };
+        let pc = self.inst_pc;
+        let miss = match ma_type {
+            MAType::Exec => cache.fetch(pc, va, pa),
+            MAType::Read => cache.load(pc, pa),
+            MAType::Write => cache.store(pc, pa),
+        };
+        let miss = match miss {
+            Some(v) => v,
+            None => return,
+        };
+        if self.dbg_level == "trace" && self.trace_find("ctrace") {
+            ctrace(pc, pa, ma_type, miss);
+        }
+        if let Some(v) = self.timing.as_mut() {
+            let penalty = v.model().miss_penalty();
+            v.stall(Stall::Cache, miss * penalty);
         }
+    }
 
-        if (addr >= PERIF_START_ADDR && addr <= PERIF_START_ADDR + PERIF_ADDR_SIZE)
-            || (addr >= VGA_FRAME_BUF_ADDR_START
-                && addr <= VGA_FRAME_BUF_ADDR_START + VGA_FRAME_BUF_ADDR_SIZE)
-        {
-            self.mmap_store_oper(addr, val);
-        } else {
-            // HACK: need to dbg_level seek for season
-            match self.start_addr {
-                0x8000_0000u64 => self.mem[(addr - self.start_addr) as usize] = val,
-                _ => self.mem[addr as usize] = val,
+    fn check_watch(&mut self, addr: u64, size: u64, write: bool) {
+        if self.watch_hit.is_none() {
+            self.watch_hit = self
+                .watch
+                .iter()
+                .find(|v| v.hit(addr, size, write))
+                .map(|v| (v.kind, v.addr));
+        }
+    }
+
+    // NOTE: the misaligned access traps before the translation, mtval is the va
+    fn check_align(&self, addr: u64, size: u64, ma_type: MAType) -> Result<(), Exception> {
+        match self.misalign {
+            MisalignMode::Trap if addr & (size - 1) != 0 => Err(Exception {
+                excpt_type: ma_type.misaligned(),
+                addr,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn load_mem(&mut self, addr: u64, size: u64, trans: bool) -> Result<u64, Exception> {
+        self.check_align(addr, size, MAType::Read)?;
+        self.read_mem(addr, size, MAType::Read, trans)
+    }
+
+    fn load_byte(&mut self, addr: u64, trans: bool) -> Result<u8, Exception> {
+        Ok(self.load_mem(addr, 1, trans)? as u8)
+    }
+
+    fn load_halfword(&mut self, addr: u64, trans: bool) -> Result<u16, Exception> {
+        Ok(self.load_mem(addr, 2, trans)? as u16)
+    }
+
+    fn load_word(&mut self, addr: u64, trans: bool) -> Result<u32, Exception> {
+        Ok(self.load_mem(addr, 4, trans)? as u32)
+    }
+
+    fn load_doubleword(&mut self, addr: u64, trans: bool) -> Result<u64, Exception> {
+        self.load_mem(addr, 8, trans)
+    }
+
+    fn store_mem(&mut self, addr: u64, size: u64, val: u64, trans: bool) -> Result<(), Exception> {
+        self.check_align(addr, size, MAType::Write)?;
+        if Core::cross_page(addr, size, trans) {
+            for i in 0..size {
+                self.store_mem(addr.wrapping_add(i), 1, val >> (8 * i), trans)?;
             }
+            return Ok(());
         }
+
+        let phy_addr = self.phy_addr(addr, size, MAType::Write, trans)?;
+        self.phy_write(addr, phy_addr, size, val)
     }
 
     fn store_byte(&mut self, addr: u64, val: u8, trans: bool) -> Result<(), Exception> {
-        let phy_addr = match trans {
-            true => match self.trans_addr(addr, MAType::Write) {
-                Ok(v) => v,
-                Err(_e) => {
-                    return Err(Exception {
-                        excpt_type: ExceptionType::StorePageFault,
-                        addr: addr,
-                    })
-                }
-            },
-            false => addr,
-        };
-
-        self.store_phy_mem(
-            match self.xlen {
-                XLen::X32 => phy_addr & 0xFFFF_FFFF,
-                XLen::X64 => phy_addr,
-            },
-            val,
-        );
-        Ok(())
+        self.store_mem(addr, 1, val as u64, trans)
     }
 
     fn store_halfword(&mut self, addr: u64, val: u16, trans: bool) -> Result<(), Exception> {
-        for i in 0..2 {
-            match self.store_byte(
-                addr.wrapping_add(i),
-                (val >> (8 * i) & 0xFFu16) as u8,
-                trans,
-            ) {
-                Ok(()) => {}
-                Err(e) => return Err(e),
-            };
-        }
-        Ok(())
+        self.store_mem(addr, 2, val as u64, trans)
     }
 
     fn store_word(&mut self, addr: u64, val: u32, trans: bool) -> Result<(), Exception> {
-        for i in 0..2 {
-            match self.store_halfword(
-                addr.wrapping_add(i * 2),
-                (val >> (8 * i * 2) & 0xFFFFu32) as u16,
-                trans,
-            ) {
-                Ok(()) => {}
-                Err(e) => return Err(e),
-            };
-        }
-        Ok(())
+        self.store_mem(addr, 4, val as u64, trans)
     }
 
     fn store_doubleword(&mut self, addr: u64, val: u64, trans: bool) -> Result<(), Exception> {
-        for i in 0..2 {
-            match self.store_word(
-                addr.wrapping_add(i * 4),
-                (val >> (8 * i * 4) & 0xFFFF_FFFFFu64) as u32,
-                trans,
-            ) {
-                Ok(()) => {}
-                Err(e) => return Err(e),
-            };
-        }
-        Ok(())
+        self.store_mem(addr, 8, val, trans)
     }
 
-    fn trans_addr(&mut self, addr: u64, ma_type: MAType) -> Result<u64, ()> {
-        match self.addr_mode {
-            AddrMode::None => Ok(addr),
-            AddrMode::SV32 => match self.priv_mode {
-                PrivMode::User | PrivMode::Supervisor => {
-                    let vpns = [(addr >> 12) & 0x3FF, (addr >> 22) & 0x3FF];
-                    self.trav_page(addr, 2 - 1, self.ppn, &vpns, ma_type)
-                }
-                _ => Ok(addr),
-            },
-            AddrMode::SV39 => match self.priv_mode {
-                PrivMode::User | PrivMode::Supervisor => {
-                    let vpns = [
-                        (addr >> 12) & 0x1FF,
-                        (addr >> 21) & 0x1FF,
-                        (addr >> 30) & 0x1FF,
-                    ];
-                    self.trav_page(addr, 3 - 1, self.ppn, &vpns, ma_type)
-                }
-                _ => Ok(addr),
-            },
-            _ => panic!(), // HACK: not impl for sv48
+    // NOTE: with mstatus.MPRV, loads and stores in m-mode are translated and
+    // protected as in the mode of MPP, the fetch always uses the current mode
+    fn access_priv(&self, ma_type: &MAType) -> u8 {
+        let cur = get_priv_encoding(&self.priv_mode);
+        let mstatus = self.csr.get(csr::CSR_MSTATUS_ADDR);
+        match ma_type {
+            MAType::Read | MAType::Write if cur == 3 && mstatus & csr::MSTATUS_MPRV != 0 => {
+                ((mstatus & csr::MSTATUS_MPP_MASK) >> 11) as u8
+            }
+            _ => cur,
         }
     }
 
-    fn trav_page(
-        &mut self,
-        virt_addr: u64,
-        level: u8,
-        parent_ppn: u64,
-        vpns: &[u64],
-        ma_type: MAType,
-    ) -> Result<u64, ()> {
-        let pagesize = 4096;
-        let ptesize = match self.addr_mode {
-            AddrMode::SV32 => 4,
-            _ => 8, // sv39 and sv48
+    fn trans_addr(&mut self, addr: u64, ma_type: MAType) -> Result<u64, Exception> {
+        let priv_val = self.access_priv(&ma_type);
+        let addr = match self.addr_mode {
+            AddrMode::None => return Ok(addr),
+            _ if priv_val == 3 => return Ok(addr),
+            AddrMode::SV32 => addr & 0xFFFF_FFFF,
+            _ => addr,
         };
 
-        let pte_addr = parent_ppn * pagesize + vpns[level as usize] * ptesize;
-        let pte = match self.addr_mode {
-            AddrMode::SV32 => match self.load_word(pte_addr, false) {
-                Ok(v) => v as u64,
-                Err(_e) => panic!(),
-            },
-            _ => match self.load_doubleword(pte_addr, false) {
-                Ok(v) => v as u64,
-                Err(_e) => panic!(),
-            },
-        };
+        // NOTE: the permission of the hit entry is checked again as the mode and
+        // mstatus may change after the fill, a missing A/D bit goes to the walk
+        if let Some((pa_base, page_mask, pte)) = self.tlb.lookup(addr, self.asid) {
+            let ad = ma_type.ad_bits();
+            if self.leaf_perm(pte, ma_type, priv_val) && pte & ad == ad {
+                return Ok(pa_base | (addr & page_mask));
+            }
+        }
+        self.walk_page(addr, ma_type, priv_val)
+    }
 
-        let ppn = match self.addr_mode {
-            AddrMode::SV32 => (pte >> 10) & 0x3FFFFF,
-            AddrMode::SV39 => (pte >> 10) & 0xFFF_FFFF_FFFF,
-            _ => panic!(),
+    // NOTE: with MXR, the executable page is also readable, u-mode only accesses
+    // the user page, s-mode accesses it with SUM but never executes it
+    fn leaf_perm(&self, pte: u64, ma_type: MAType, priv_val: u8) -> bool {
+        let mstatus = self.csr.get(csr::CSR_MSTATUS_ADDR);
+        let perm = match ma_type {
+            MAType::Exec => pte & PTE_X != 0,
+            MAType::Read => {
+                pte & PTE_R != 0 || (mstatus & csr::MSTATUS_MXR != 0 && pte & PTE_X != 0)
+            }
+            MAType::Write => pte & PTE_W != 0,
         };
-
-        let ppns = match self.addr_mode {
-            AddrMode::SV32 => [(pte >> 10) & 0x3FF, (pte >> 20) & 0xFFF, 0],
-            AddrMode::SV39 => [
-                (pte >> 10) & 0x1FF,
-                (pte >> 19) & 0x1FF,
-                (pte >> 28) & 0x3FF_FFFF,
-            ],
-            _ => panic!(),
+        let user = pte & PTE_U != 0;
+        let priv_ok = match priv_val {
+            0 => user,
+            _ => !user || (mstatus & csr::MSTATUS_SUM != 0 && !matches!(ma_type, MAType::Exec)),
         };
+        perm && priv_ok
+    }
 
-        let _rsw = (pte >> 8) & 0x3;
-        let d = (pte >> 7) & 1;
-        let a = (pte >> 6) & 1;
-        let _g = (pte >> 5) & 1;
-        let _u = (pte >> 4) & 1;
-        let x = (pte >> 3) & 1;
-        let w = (pte >> 2) & 1;
-        let r = (pte >> 1) & 1;
-        let v = pte & 1;
-
-        // println!("VA:{:X} Level:{:X} PTE_AD:{:X} PTE:{:X} PPN:{:X} PPN1:{:X} PPN0:{:X}", v_address, level, pte_addr, pte, ppn, ppns[1], ppns[0]);
-
-        if v == 0 || (r == 0 && w == 1) {
-            return Err({});
+    // NOTE: the page walk in the spec, the pte fetch fault is reported as the
+    // access fault of the original access
+    fn walk_page(&mut self, virt_addr: u64, ma_type: MAType, priv_val: u8) -> Result<u64, Exception> {
+        let page_fault = Exception {
+            excpt_type: ma_type.page_fault(),
+            addr: virt_addr,
+        };
+        let (levels, vpn_bits, pte_size) = page_table_shape(&self.addr_mode);
+        // NOTE: the va of sv39/48/57 must be the sign extension of its msb
+        if pte_size == 8 {
+            let va_bits = 12 + levels * vpn_bits;
+            let high = ((virt_addr as i64) >> (va_bits - 1)) as u64;
+            if high != 0 && high != u64::MAX {
+                return Err(page_fault);
+            }
         }
 
-        if r == 0 && x == 0 {
-            return match level {
-                0 => Err(()),
-                _ => self.trav_page(virt_addr, level - 1, ppn, vpns, ma_type),
+        let access_fault = Exception {
+            excpt_type: ma_type.access_fault(),
+            addr: virt_addr,
+        };
+        let mut base = self.ppn << 12;
+        let mut level = levels - 1;
+        // NOTE: G of a non-leaf pte makes all the pages below it global
+        let mut global = 0u64;
+        loop {
+            let vpn = (virt_addr >> (12 + level * vpn_bits)) & ((1 << vpn_bits) - 1);
+            let pte_addr = base + vpn * pte_size;
+            // NOTE: the pte access is checked by the pmp as in s-mode
+            if !self.pmp.check(pte_addr, pte_size, MAType::Read, 1) {
+                return Err(access_fault);
+            }
+            let pte = match self.bus.read(pte_addr, pte_size) {
+                Ok(v) => v,
+                Err(_e) => return Err(access_fault),
+            };
+            let ppn = match pte_size {
+                4 => (pte >> 10) & 0x3F_FFFF,
+                _ => (pte >> 10) & 0xFFF_FFFF_FFFF,
             };
-        }
-
-        if a == 0 {
-            return Err(());
-        }
 
-        match ma_type {
-            MAType::Exec => {
-                if x == 0 {
-                    return Err({});
-                }
+            // NOTE: pte[63:54] is reserved without Svnapot and Svpbmt
+            if pte & PTE_V == 0 || (pte & PTE_R == 0 && pte & PTE_W != 0) || pte >> 54 != 0 {
+                return Err(page_fault);
             }
-            MAType::Read => {
-                if r == 0 {
-                    return Err({});
+            global |= pte & PTE_G;
+
+            if pte & (PTE_R | PTE_X) == 0 {
+                // NOTE: D/A/U of the non-leaf pte are reserved
+                if level == 0 || pte & (PTE_D | PTE_A | PTE_U) != 0 {
+                    return Err(page_fault);
                 }
+                level -= 1;
+                base = ppn << 12;
+                continue;
             }
-            MAType::Write => {
-                if d == 0 || w == 0 {
-                    return Err(());
-                }
+
+            // NOTE: the superpage must be aligned
+            let page_mask = (1u64 << (12 + level * vpn_bits)) - 1;
+            if !self.leaf_perm(pte, ma_type, priv_val) || (ppn << 12) & page_mask != 0 {
+                return Err(page_fault);
             }
-        };
 
-        let offset = virt_addr & 0xFFF; // [11:0]
-        let phy_addr = match self.addr_mode {
-            AddrMode::SV32 => match level {
-                1 => {
-                    if ppns[0] != 0 {
-                        return Err(());
-                    }
-                    (ppns[1] << 22) | (vpns[0] << 12) | offset
-                }
-                0 => (ppn << 12) | offset,
-                _ => panic!(), // Shouldn't happen
-            },
-            _ => match level {
-                2 => {
-                    if ppns[1] != 0 || ppns[0] != 0 {
-                        return Err(());
-                    }
-                    (ppns[2] << 30) | (vpns[1] << 21) | (vpns[0] << 12) | offset
-                }
-                1 => {
-                    if ppns[0] != 0 {
-                        return Err(());
+            let ad = ma_type.ad_bits();
+            if pte & ad != ad {
+                match self.ad_mode {
+                    AdMode::Svade => return Err(page_fault),
+                    AdMode::Svadu => {
+                        if !self.pmp.check(pte_addr, pte_size, MAType::Write, 1)
+                            || self.bus.write(pte_addr, pte_size, pte | ad).is_err()
+                        {
+                            return Err(access_fault);
+                        }
                     }
-                    (ppns[2] << 30) | (ppns[1] << 21) | (vpns[0] << 12) | offset
                 }
-                0 => (ppn << 12) | offset,
-                _ => panic!(), // Shouldn't happen
-            },
+            }
+            let pa_base = (ppn << 12) & !page_mask;
+            let pte = pte | ad | global;
+            let asid = self.asid;
+            self.tlb.insert(virt_addr, pa_base, page_mask, asid, pte);
+            return Ok(pa_base | (virt_addr & page_mask));
+        }
+    }
+
+    // NOTE: LR/SC/AMO need a naturally aligned addr, and the whole access
+    // uses one translation, so faults are reported with the store/AMO cause
+    fn atomic_addr(&mut self, addr: u64, align: u64, ma_type: MAType) -> Result<u64, Exception> {
+        if addr & (align - 1) != 0 {
+            return Err(Exception {
+                excpt_type: ma_type.misaligned(),
+                addr,
+            });
+        }
+
+        self.trans_addr(addr, ma_type)
+    }
+
+    // NOTE: the phy access of LR/SC/AMO reports the access fault with the va
+    // and the cause of the inst, not the one of the load half. The pmp checks
+    // each half, the hooks see the va
+    fn atomic_load(
+        &mut self,
+        va: u64,
+        phy_addr: u64,
+        size: u64,
+        ma_type: MAType,
+    ) -> Result<i64, Exception> {
+        let res = self
+            .phy_addr(phy_addr, size, MAType::Read, false)
+            .and_then(|pa| self.phy_read(va, pa, size, MAType::Read));
+        match res {
+            Ok(v) if size == 4 => Ok(v as i32 as i64),
+            Ok(v) => Ok(v as i64),
+            Err(_e) => Err(Exception {
+                excpt_type: ma_type.access_fault(),
+                addr: va,
+            }),
+        }
+    }
+
+    fn atomic_store(
+        &mut self,
+        va: u64,
+        phy_addr: u64,
+        size: u64,
+        val: i64,
+    ) -> Result<(), Exception> {
+        let res = self
+            .phy_addr(phy_addr, size, MAType::Write, false)
+            .and_then(|pa| self.phy_write(va, pa, size, val as u64));
+        match res {
+            Ok(()) => Ok(()),
+            Err(_e) => Err(Exception {
+                excpt_type: MAType::Write.access_fault(),
+                addr: va,
+            }),
+        }
+    }
+
+    fn exec_amo(&mut self, inst: &Inst, rd: u32, rs1: u32, rs2: u32) -> Result<(), Exception> {
+        let word_oper = matches!(
+            inst,
+            Inst::AMOSWAPW
+                | Inst::AMOADDW
+                | Inst::AMOXORW
+                | Inst::AMOANDW
+                | Inst::AMOORW
+                | Inst::AMOMINW
+                | Inst::AMOMAXW
+                | Inst::AMOMINUW
+                | Inst::AMOMAXUW
+        );
+        let src = self.regfile.x[rs2 as usize];
+        let va = self.regfile.x[rs1 as usize] as u64;
+        let size = if word_oper { 4 } else { 8 };
+        let phy_addr = self.atomic_addr(va, size, MAType::Write)?;
+        let old = self.atomic_load(va, phy_addr, size, MAType::Write)?;
+
+        let res = match inst {
+            Inst::AMOSWAPW | Inst::AMOSWAPD => src,
+            Inst::AMOADDW | Inst::AMOADDD => old.wrapping_add(src),
+            Inst::AMOXORW | Inst::AMOXORD => old ^ src,
+            Inst::AMOANDW | Inst::AMOANDD => old & src,
+            Inst::AMOORW | Inst::AMOORD => old | src,
+            Inst::AMOMINW => (old as i32).min(src as i32) as i64,
+            Inst::AMOMAXW => (old as i32).max(src as i32) as i64,
+            Inst::AMOMINUW => (old as u32).min(src as u32) as i32 as i64,
+            Inst::AMOMAXUW => (old as u32).max(src as u32) as i32 as i64,
+            Inst::AMOMIND => old.min(src),
+            Inst::AMOMAXD => old.max(src),
+            Inst::AMOMINUD => (old as u64).min(src as u64) as i64,
+            Inst::AMOMAXUD => (old as u64).max(src as u64) as i64,
+            _ => panic!(),
         };
-        // println!("PA:{:X}", phy_addr);
-        Ok(phy_addr)
+
+        self.atomic_store(va, phy_addr, size, res)?;
+        if rd > 0 {
+            self.regfile.x[rd as usize] = old;
+        }
+        Ok(())
     }
 
     fn get_csr_access_priv(&self, addr: u16) -> bool {
@@ -634,42 +1321,81 @@ impl Core {
         (priv_val as u8) <= get_priv_encoding(&self.priv_mode)
     }
 
-    fn read_csr(&self, addr: u16) -> Result<u64, Exception> {
-        match self.get_csr_access_priv(addr) {
-            true => Ok(self.csr[addr as usize]),
-            false => Err(Exception {
-                excpt_type: ExceptionType::IllegalInst,
-                addr: self.pc.wrapping_sub(4),
-            }),
+    fn is_fp_csr(addr: u16) -> bool {
+        matches!(
+            addr,
+            csr::CSR_FFLAGS_ADDR | csr::CSR_FRM_ADDR | csr::CSR_FCSR_ADDR
+        )
+    }
+
+    // NOTE: the user counters are enabled for s-mode by mcounteren, and for
+    // u-mode by both mcounteren and scounteren
+    fn counter_enabled(&self, addr: u16) -> bool {
+        let bit = match addr {
+            0xc00..=0xc1f | 0xc80..=0xc9f => 1u64 << (addr & 0x1F),
+            _ => return true,
+        };
+        let mcounteren = self.csr.get(csr::CSR_MCOUNTEREN_ADDR);
+        let scounteren = self.csr.get(csr::CSR_SCOUNTEREN_ADDR);
+        match self.priv_mode {
+            PrivMode::Machine => true,
+            PrivMode::Supervisor => mcounteren & bit != 0,
+            _ => mcounteren & scounteren & bit != 0,
+        }
+    }
+
+    // NOTE: unimplemented csr, higher privilege csr and fp csr when fs is off are illegal
+    fn check_csr_access(&self, addr: u16) -> bool {
+        self.csr.exists(addr)
+            && self.get_csr_access_priv(addr)
+            && self.counter_enabled(addr)
+            && (!Core::is_fp_csr(addr) || self.fp_enabled())
+            && !(addr == csr::CSR_SATP_ADDR && self.tvm_trap())
+    }
+
+    // NOTE: mstatus.TVM traps satp access and sfence.vma in s-mode
+    fn tvm_trap(&self) -> bool {
+        matches!(self.priv_mode, PrivMode::Supervisor)
+            && self.csr.get(csr::CSR_MSTATUS_ADDR) & csr::MSTATUS_TVM != 0
+    }
+
+    fn read_csr(&self, addr: u16) -> Result<u64, Exception> {
+        match self.check_csr_access(addr) {
+            true => Ok(self.csr.read(addr)),
+            false => Err(self.illegal_inst()),
         }
     }
 
+    // NOTE: csr addr[11:10] == 3 marks the read-only csr
+    fn is_ro_csr(addr: u16) -> bool {
+        (addr >> 10) & 0x3 == 0x3
+    }
+
     fn write_csr(&mut self, addr: u16, val: u64) -> Result<(), Exception> {
-        match self.get_csr_access_priv(addr) {
+        match self.check_csr_access(addr) && !Core::is_ro_csr(addr) {
             true => {
-                self.csr[addr as usize] = val;
-                if addr == csr::CSR_SATP_ADDR {
-                    self.update_addr_mode(val);
+                match self.csr.write(addr, val) {
+                    CsrHook::None => {}
+                    CsrHook::AddrMode => self.update_addr_mode(self.csr.get(csr::CSR_SATP_ADDR)),
+                    CsrHook::Pmp => self.pmp.update(&self.csr),
                 }
                 Ok(())
             }
-            false => Err(Exception {
-                excpt_type: ExceptionType::IllegalInst,
-                addr: self.pc.wrapping_sub(4),
-            }),
+            false => Err(self.illegal_inst()),
         }
     }
+
     fn update_addr_mode(&mut self, val: u64) {
         self.addr_mode = match self.xlen {
-            XLen::X32 => match val >> 31 {
+            XLen::X32 => match (val >> 31) & 0x1 {
                 0 => AddrMode::None,
-                1 => AddrMode::SV32,
-                _ => panic!(),
+                _ => AddrMode::SV32,
             },
             XLen::X64 => match val >> 60 {
                 0 => AddrMode::None,
                 8 => AddrMode::SV39,
                 9 => AddrMode::SV48,
+                10 => AddrMode::SV57,
                 _ => panic!(),
             },
         };
@@ -677,10 +1403,254 @@ impl Core {
         self.ppn = match self.xlen {
             XLen::X32 => val & 0x3FFFFF,
             XLen::X64 => val & 0xFFFFFFFFFFF,
+        };
+        // NOTE: writing satp does not flush the tlb, the entries are tagged by asid
+        self.asid = match self.xlen {
+            XLen::X32 => ((val >> 22) & 0x1FF) as u16,
+            XLen::X64 => ((val >> 44) & 0xFFFF) as u16,
+        };
+    }
+
+    fn fp_enabled(&self) -> bool {
+        self.csr.get(csr::CSR_MSTATUS_ADDR) & csr::MSTATUS_FS_MASK != 0
+    }
+
+    // NOTE: any change of the fp state marks mstatus.FS dirty, SD is derived on read
+    fn set_fp_dirty(&mut self) {
+        let mstatus = self.csr.get(csr::CSR_MSTATUS_ADDR);
+        self.csr.set(csr::CSR_MSTATUS_ADDR, mstatus | csr::MSTATUS_FS_DIRTY);
+    }
+
+    fn accrue_fflags(&mut self, flags: u8) {
+        if flags != 0 {
+            let fcsr = self.csr.get(csr::CSR_FCSR_ADDR);
+            self.csr.set(csr::CSR_FCSR_ADDR, fcsr | flags as u64);
+            self.set_fp_dirty();
+        }
+    }
+
+    fn read_freg(&self, idx: u32, fmt: FpFmt) -> u64 {
+        match fmt {
+            FpFmt::Single => fpu::unbox_f32(self.fregfile.f[idx as usize]),
+            FpFmt::Double => self.fregfile.f[idx as usize],
+        }
+    }
+
+    fn write_freg(&mut self, idx: u32, val: u64, fmt: FpFmt) {
+        self.fregfile.f[idx as usize] = match fmt {
+            FpFmt::Single => fpu::box_f32(val),
+            FpFmt::Double => val,
+        };
+        self.set_fp_dirty();
+    }
+
+    // rm 7 selects the dynamic rounding mode in frm
+    fn fp_round_mode(&self, rm: u32) -> Result<RoundMode, Exception> {
+        let rm = match rm {
+            7 => self.csr.read(csr::CSR_FRM_ADDR) as u32,
+            _ => rm,
+        };
+        match RoundMode::from_bits(rm) {
+            Some(v) => Ok(v),
+            None => Err(self.illegal_inst()),
+        }
+    }
+
+    fn exec_fp(&mut self, word: u32, inst: Inst) -> Result<(), Exception> {
+        if !self.fp_enabled() {
+            return Err(self.illegal_inst());
+        }
+
+        let inst_wrap = Word::new(word);
+        let rd = inst_wrap.val(11, 7);
+        let rs1 = inst_wrap.val(19, 15);
+        let rs2 = inst_wrap.val(24, 20);
+        let rs3 = inst_wrap.val(31, 27);
+        let rm = inst_wrap.val(14, 12);
+        // NOTE: the fmt field is width for load/store and inst[26:25] for the others
+        let fmt = match (inst_wrap.val(6, 0), inst_wrap.val(14, 12), inst_wrap.val(26, 25)) {
+            (0x07 | 0x27, 2, _) => FpFmt::Single,
+            (0x07 | 0x27, _, _) => FpFmt::Double,
+            (_, _, 0) => FpFmt::Single,
+            _ => FpFmt::Double,
+        };
+        let sign_bit = match fmt {
+            FpFmt::Single => 1u64 << 31,
+            FpFmt::Double => 1u64 << 63,
+        };
+        let (a, b, c) = (
+            self.read_freg(rs1, fmt),
+            self.read_freg(rs2, fmt),
+            self.read_freg(rs3, fmt),
+        );
+
+        match inst {
+            Inst::FLW | Inst::FLD => {
+                let addr = self.regfile.x[rs1 as usize]
+                    .wrapping_add(Core::imm_ext_gen(InstType::I, word))
+                    as u64;
+                let val = match fmt {
+                    FpFmt::Single => self.load_word(addr, true)? as u64,
+                    FpFmt::Double => self.load_doubleword(addr, true)?,
+                };
+                self.write_freg(rd, val, fmt);
+            }
+            Inst::FSW | Inst::FSD => {
+                // NOTE: store the raw bits, no NaN-boxing check
+                let addr = self.regfile.x[rs1 as usize]
+                    .wrapping_add(Core::imm_ext_gen(InstType::S, word))
+                    as u64;
+                let val = self.fregfile.f[rs2 as usize];
+                match fmt {
+                    FpFmt::Single => self.store_word(addr, val as u32, true)?,
+                    FpFmt::Double => self.store_doubleword(addr, val, true)?,
+                };
+            }
+            Inst::FMADDS
+            | Inst::FMSUBS
+            | Inst::FNMSUBS
+            | Inst::FNMADDS
+            | Inst::FMADDD
+            | Inst::FMSUBD
+            | Inst::FNMSUBD
+            | Inst::FNMADDD => {
+                let rm = self.fp_round_mode(rm)?;
+                let (neg_prod, neg_add) = match inst {
+                    Inst::FMADDS | Inst::FMADDD => (false, false),
+                    Inst::FMSUBS | Inst::FMSUBD => (false, true),
+                    Inst::FNMSUBS | Inst::FNMSUBD => (true, false),
+                    _ => (true, true),
+                };
+                let (val, flags) = fpu::fma(a, b, c, neg_prod, neg_add, fmt, rm);
+                self.accrue_fflags(flags);
+                self.write_freg(rd, val, fmt);
+            }
+            Inst::FADDS
+            | Inst::FSUBS
+            | Inst::FMULS
+            | Inst::FDIVS
+            | Inst::FSQRTS
+            | Inst::FADDD
+            | Inst::FSUBD
+            | Inst::FMULD
+            | Inst::FDIVD
+            | Inst::FSQRTD => {
+                let rm = self.fp_round_mode(rm)?;
+                let (val, flags) = match inst {
+                    Inst::FADDS | Inst::FADDD => fpu::add(a, b, fmt, rm),
+                    Inst::FSUBS | Inst::FSUBD => fpu::sub(a, b, fmt, rm),
+                    Inst::FMULS | Inst::FMULD => fpu::mul(a, b, fmt, rm),
+                    Inst::FDIVS | Inst::FDIVD => fpu::div(a, b, fmt, rm),
+                    _ => fpu::sqrt(a, fmt, rm),
+                };
+                self.accrue_fflags(flags);
+                self.write_freg(rd, val, fmt);
+            }
+            Inst::FSGNJS | Inst::FSGNJD => {
+                self.write_freg(rd, (a & !sign_bit) | (b & sign_bit), fmt);
+            }
+            Inst::FSGNJNS | Inst::FSGNJND => {
+                self.write_freg(rd, (a & !sign_bit) | (!b & sign_bit), fmt);
+            }
+            Inst::FSGNJXS | Inst::FSGNJXD => {
+                self.write_freg(rd, a ^ (b & sign_bit), fmt);
+            }
+            Inst::FMINS | Inst::FMAXS | Inst::FMIND | Inst::FMAXD => {
+                let is_max = matches!(inst, Inst::FMAXS | Inst::FMAXD);
+                let (val, flags) = fpu::min_max(a, b, fmt, is_max);
+                self.accrue_fflags(flags);
+                self.write_freg(rd, val, fmt);
+            }
+            Inst::FEQS | Inst::FLTS | Inst::FLES | Inst::FEQD | Inst::FLTD | Inst::FLED => {
+                let (val, flags) = match inst {
+                    Inst::FEQS | Inst::FEQD => fpu::eq(a, b, fmt),
+                    Inst::FLTS | Inst::FLTD => fpu::lt(a, b, fmt),
+                    _ => fpu::le(a, b, fmt),
+                };
+                self.accrue_fflags(flags);
+                if rd > 0 {
+                    self.regfile.x[rd as usize] = val as i64;
+                }
+            }
+            Inst::FCLASSS | Inst::FCLASSD => {
+                if rd > 0 {
+                    self.regfile.x[rd as usize] = fpu::classify(a, fmt) as i64;
+                }
+            }
+            Inst::FCVTWS
+            | Inst::FCVTWUS
+            | Inst::FCVTLS
+            | Inst::FCVTLUS
+            | Inst::FCVTWD
+            | Inst::FCVTWUD
+            | Inst::FCVTLD
+            | Inst::FCVTLUD => {
+                let rm = self.fp_round_mode(rm)?;
+                let ifmt = match inst {
+                    Inst::FCVTWS | Inst::FCVTWD => IntFmt::W,
+                    Inst::FCVTWUS | Inst::FCVTWUD => IntFmt::WU,
+                    Inst::FCVTLS | Inst::FCVTLD => IntFmt::L,
+                    _ => IntFmt::LU,
+                };
+                let (val, flags) = fpu::to_int(a, fmt, ifmt, rm);
+                self.accrue_fflags(flags);
+                if rd > 0 {
+                    self.regfile.x[rd as usize] = val as i64;
+                }
+            }
+            Inst::FCVTSW
+            | Inst::FCVTSWU
+            | Inst::FCVTSL
+            | Inst::FCVTSLU
+            | Inst::FCVTDW
+            | Inst::FCVTDWU
+            | Inst::FCVTDL
+            | Inst::FCVTDLU => {
+                let rm = self.fp_round_mode(rm)?;
+                let ifmt = match inst {
+                    Inst::FCVTSW | Inst::FCVTDW => IntFmt::W,
+                    Inst::FCVTSWU | Inst::FCVTDWU => IntFmt::WU,
+                    Inst::FCVTSL | Inst::FCVTDL => IntFmt::L,
+                    _ => IntFmt::LU,
+                };
+                let (val, flags) =
+                    fpu::from_int(self.regfile.x[rs1 as usize] as u64, ifmt, fmt, rm);
+                self.accrue_fflags(flags);
+                self.write_freg(rd, val, fmt);
+            }
+            Inst::FCVTSD => {
+                let rm = self.fp_round_mode(rm)?;
+                let src = self.read_freg(rs1, FpFmt::Double);
+                let (val, flags) = fpu::convert(src, FpFmt::Double, FpFmt::Single, rm);
+                self.accrue_fflags(flags);
+                self.write_freg(rd, val, FpFmt::Single);
+            }
+            Inst::FCVTDS => {
+                let rm = self.fp_round_mode(rm)?;
+                let src = self.read_freg(rs1, FpFmt::Single);
+                let (val, flags) = fpu::convert(src, FpFmt::Single, FpFmt::Double, rm);
+                self.accrue_fflags(flags);
+                self.write_freg(rd, val, FpFmt::Double);
+            }
+            Inst::FMVXW | Inst::FMVXD => {
+                // NOTE: move the raw bits, the upper bits of fmv.x.w are sign-extended
+                let val = self.fregfile.f[rs1 as usize];
+                if rd > 0 {
+                    self.regfile.x[rd as usize] = match fmt {
+                        FpFmt::Single => val as u32 as i32 as i64,
+                        FpFmt::Double => val as i64,
+                    };
+                }
+            }
+            Inst::FMVWX | Inst::FMVDX => {
+                self.write_freg(rd, self.regfile.x[rs1 as usize] as u64, fmt);
+            }
+            _ => panic!(),
         }
+        Ok(())
     }
 
-    fn imm_ext_gen(inst_type: InstType, word: u32) -> i64 {
+    pub(crate) fn imm_ext_gen(inst_type: InstType, word: u32) -> i64 {
         let inst = Word::new(word);
         match inst_type {
             InstType::I => {
@@ -876,16 +1846,14 @@ impl Core {
                     }
                     Inst::JALR => {
                         let tmp_pc = self.pc; // important!!!, if rs1 == rd
-                        self.pc = (self.regfile.x[rs1 as usize] as u64).wrapping_add(imm as u64);
+                        self.pc =
+                            (self.regfile.x[rs1 as usize] as u64).wrapping_add(imm as u64) & !1;
                         if rd > 0 {
                             self.regfile.x[rd as usize] = tmp_pc as i64;
                         }
 
                         if self.dbg_level == "trace" && self.trace_find("ftrace") {
-                            match self.ftr.ftrace(tmp_pc.wrapping_sub(4), self.pc) {
-                                Ok(()) => {}
-                                Err(_e) => panic!(),
-                            }
+                            self.ftr.ftrace(self.inst_pc, self.pc, &self.symtab);
                         }
                     }
                     Inst::LB => {
@@ -952,6 +1920,9 @@ impl Core {
                     Inst::FENCE => {
                         // HACK: no impl
                     }
+                    Inst::FENCEI => {
+                        // NOTE: the fetch always reads the mem, no icache coherence to keep
+                    }
                     Inst::ECALL => {
                         let excpt_type = match self.priv_mode {
                             PrivMode::User => ExceptionType::EnvCallFromUMode,
@@ -960,19 +1931,25 @@ impl Core {
                             _ => panic!(),
                         };
 
+                        // NOTE: mtval of ecall is zero
                         return Err(Exception {
                             excpt_type: excpt_type,
-                            addr: self.pc.wrapping_sub(4),
+                            addr: 0,
                         });
                     }
+                    // NOTE: mtval of ebreak is its own pc
                     Inst::EBREAK => {
-                        // HACK: no impl
+                        return Err(Exception {
+                            excpt_type: ExceptionType::Breakpoint,
+                            addr: self.inst_pc,
+                        });
                     }
                     _ => {
                         println!(
                             "{}",
                             get_inst_name(&inst).to_owned() + " inst is not supported yet."
                         );
+                        self.crash_dump();
                         panic!();
                     }
                 }
@@ -1267,36 +2244,140 @@ impl Core {
                             }
                         }
                     }
-                    Inst::URET => {}
+                    Inst::LRW | Inst::LRD => {
+                        let va = self.regfile.x[rs1 as usize] as u64;
+                        let size = if matches!(inst, Inst::LRW) { 4 } else { 8 };
+                        let phy_addr = self.atomic_addr(va, size, MAType::Read)?;
+                        let dat = self.atomic_load(va, phy_addr, size, MAType::Read)?;
+                        if rd > 0 {
+                            self.regfile.x[rd as usize] = dat;
+                        }
+                        self.reservation = Some(phy_addr);
+                    }
+                    Inst::SCW | Inst::SCD => {
+                        let va = self.regfile.x[rs1 as usize] as u64;
+                        let size = if matches!(inst, Inst::SCW) { 4 } else { 8 };
+                        let phy_addr = self.atomic_addr(va, size, MAType::Write)?;
+                        // NOTE: any sc invalidates the reservation, even if it fails
+                        let succ = self.reservation == Some(phy_addr);
+                        self.reservation = None;
+                        if succ {
+                            let dat = self.regfile.x[rs2 as usize];
+                            self.atomic_store(va, phy_addr, size, dat)?;
+                        }
+                        if rd > 0 {
+                            self.regfile.x[rd as usize] = if succ { 0 } else { 1 };
+                        }
+                    }
+                    Inst::AMOSWAPW
+                    | Inst::AMOADDW
+                    | Inst::AMOXORW
+                    | Inst::AMOANDW
+                    | Inst::AMOORW
+                    | Inst::AMOMINW
+                    | Inst::AMOMAXW
+                    | Inst::AMOMINUW
+                    | Inst::AMOMAXUW
+                    | Inst::AMOSWAPD
+                    | Inst::AMOADDD
+                    | Inst::AMOXORD
+                    | Inst::AMOANDD
+                    | Inst::AMOORD
+                    | Inst::AMOMIND
+                    | Inst::AMOMAXD
+                    | Inst::AMOMINUD
+                    | Inst::AMOMAXUD => self.exec_amo(&inst, rd, rs1, rs2)?,
+                    // NOTE: n extension is not supported
+                    Inst::URET => return Err(self.illegal_inst()),
                     Inst::SRET => {
+                        // NOTE: mstatus.TSR traps sret in s-mode
+                        let tsr = self.csr.get(csr::CSR_MSTATUS_ADDR) & csr::MSTATUS_TSR != 0;
+                        match self.priv_mode {
+                            PrivMode::User => return Err(self.illegal_inst()),
+                            PrivMode::Supervisor if tsr => return Err(self.illegal_inst()),
+                            _ => {}
+                        }
                         self.pc = match self.read_csr(csr::CSR_SEPC_ADDR) {
                             Ok(v) => v,
                             Err(e) => return Err(e),
                         };
+                        self.reservation = None;
 
-                        self.priv_mode = match self.csr[csr::CSR_SSTATUS_ADDR as usize] & 0x100u64 {
+                        let mstatus = self.csr.get(csr::CSR_MSTATUS_ADDR);
+                        self.priv_mode = match mstatus & csr::MSTATUS_SPP {
                             0 => PrivMode::User,
-                            _ => {
-                                self.csr[csr::CSR_SSTATUS_ADDR as usize] &= !0x100;
-                                PrivMode::Supervisor
-                            }
+                            _ => PrivMode::Supervisor,
+                        };
+                        // SIE = SPIE, SPIE = 1, SPP = U, MPRV is cleared as the new mode is not M
+                        let sie = (mstatus & csr::MSTATUS_SPIE) >> 4;
+                        self.csr.set(
+                            csr::CSR_MSTATUS_ADDR,
+                            (mstatus & !(csr::MSTATUS_SIE | csr::MSTATUS_SPP | csr::MSTATUS_MPRV))
+                                | sie
+                                | csr::MSTATUS_SPIE,
+                        );
+                    }
+                    // NOTE: rs1 == x0 fences all addrs and rs2 == x0 all asids
+                    Inst::SFENCEVMA => match self.priv_mode {
+                        PrivMode::User => return Err(self.illegal_inst()),
+                        _ if self.tvm_trap() => return Err(self.illegal_inst()),
+                        _ => {
+                            let va = self.regfile.x[rs1 as usize] as u64;
+                            let va = match (rs1, self.xlen) {
+                                (0, _) => None,
+                                (_, XLen::X32) => Some(va & 0xFFFF_FFFF),
+                                (_, XLen::X64) => Some(va),
+                            };
+                            let asid = match rs2 {
+                                0 => None,
+                                _ => Some(self.regfile.x[rs2 as usize] as u16),
+                            };
+                            self.tlb.fence(va, asid);
+                        }
+                    },
+                    // NOTE: the pending interrupt is checked every tick, so wfi can be a nop,
+                    // but it traps in u-mode and in s-mode with mstatus.TW as the time limit is 0
+                    Inst::WFI => {
+                        let tw = self.csr.get(csr::CSR_MSTATUS_ADDR) & csr::MSTATUS_TW != 0;
+                        match self.priv_mode {
+                            PrivMode::User => return Err(self.illegal_inst()),
+                            PrivMode::Supervisor if tw => return Err(self.illegal_inst()),
+                            _ => {}
                         }
                     }
-                    Inst::SFENCEVMA => {}
                     Inst::MRET => {
+                        if !matches!(self.priv_mode, PrivMode::Machine) {
+                            return Err(self.illegal_inst());
+                        }
                         self.pc = match self.read_csr(csr::CSR_MEPC_ADDR) {
                             Ok(v) => v,
                             Err(e) => return Err(e),
                         };
+                        self.reservation = None;
 
                         // NOTE: need to set right mstatus value in process context switch
-                        self.priv_mode =
-                            match (self.csr[csr::CSR_MSTATUS_ADDR as usize] >> 11) & 0x3 {
-                                0 => PrivMode::User,
-                                1 => PrivMode::Supervisor,
-                                3 => PrivMode::Machine,
-                                _ => panic!(),
-                            };
+                        // NOTE: the csr write never leaves the reserved MPP 2, but a raw
+                        // set can, it returns to u-mode like MPP 0
+                        let mstatus = self.csr.get(csr::CSR_MSTATUS_ADDR);
+                        self.priv_mode = match (mstatus >> 11) & 0x3 {
+                            1 => PrivMode::Supervisor,
+                            3 => PrivMode::Machine,
+                            _ => PrivMode::User,
+                        };
+                        // MIE = MPIE, MPIE = 1, MPP = U, MPRV is cleared if the new mode is not M
+                        let mie = (mstatus & csr::MSTATUS_MPIE) >> 4;
+                        let mprv = match self.priv_mode {
+                            PrivMode::Machine => mstatus & csr::MSTATUS_MPRV,
+                            _ => 0,
+                        };
+                        self.csr.set(
+                            csr::CSR_MSTATUS_ADDR,
+                            (mstatus
+                                & !(csr::MSTATUS_MIE | csr::MSTATUS_MPP_MASK | csr::MSTATUS_MPRV))
+                                | mie
+                                | mprv
+                                | csr::MSTATUS_MPIE,
+                        );
                     }
                     _ => {
                         panic!()
@@ -1359,13 +2440,9 @@ impl Core {
                         if rd > 0 {
                             self.regfile.x[rd as usize] = self.pc as i64;
                         }
-                        let tmp_pc = self.pc;
-                        self.pc = self.pc.wrapping_sub(4).wrapping_add(imm as u64);
+                        self.pc = self.inst_pc.wrapping_add(imm as u64);
                         if self.dbg_level == "trace" && self.trace_find("ftrace") {
-                            match self.ftr.ftrace(tmp_pc.wrapping_sub(4), self.pc) {
-                                Ok(()) => {}
-                                Err(_e) => panic!(),
-                            }
+                            self.ftr.ftrace(self.inst_pc, self.pc, &self.symtab);
                         }
                     }
                     _ => {
@@ -1373,7 +2450,7 @@ impl Core {
                             "{}",
                             get_inst_name(&inst).to_owned() + " inst is not supported yet."
                         );
-                        // self.dump_instruction(self.pc.wrapping_sub(4));
+                        self.crash_dump();
                         panic!();
                     }
                 };
@@ -1387,7 +2464,7 @@ impl Core {
                 match inst {
                     Inst::BEQ => {
                         if self.regfile.x[rs1 as usize] == self.regfile.x[rs2 as usize] {
-                            self.pc = self.pc.wrapping_sub(4).wrapping_add(imm as u64);
+                            self.pc = self.inst_pc.wrapping_add(imm as u64);
                         }
                     }
                     Inst::BNE => match self.xlen {
@@ -1395,49 +2472,48 @@ impl Core {
                             if (self.regfile.x[rs1 as usize] as i32)
                                 != (self.regfile.x[rs2 as usize] as i32)
                             {
-                                self.pc = self.pc.wrapping_sub(4).wrapping_add(imm as u64);
+                                self.pc = self.inst_pc.wrapping_add(imm as u64);
                             }
                         }
                         XLen::X64 => {
                             if self.regfile.x[rs1 as usize] != self.regfile.x[rs2 as usize] {
-                                self.pc = self.pc.wrapping_sub(4).wrapping_add(imm as u64);
+                                self.pc = self.inst_pc.wrapping_add(imm as u64);
                             }
                         }
                     },
                     Inst::BLT => {
-                        // trace::execpt_handle(self.pc, word);
                         match self.xlen {
                             XLen::X32 => {
                                 if (self.regfile.x[rs1 as usize] as i32)
                                     < (self.regfile.x[rs2 as usize] as i32)
                                 {
-                                    self.pc = self.pc.wrapping_sub(4).wrapping_add(imm as u64);
+                                    self.pc = self.inst_pc.wrapping_add(imm as u64);
                                 }
                             }
                             XLen::X64 => {
                                 if self.regfile.x[rs1 as usize] < self.regfile.x[rs2 as usize] {
-                                    self.pc = self.pc.wrapping_sub(4).wrapping_add(imm as u64);
+                                    self.pc = self.inst_pc.wrapping_add(imm as u64);
                                 }
                             }
                         }
                     }
                     Inst::BGE => {
                         if self.regfile.x[rs1 as usize] >= self.regfile.x[rs2 as usize] {
-                            self.pc = self.pc.wrapping_sub(4).wrapping_add(imm as u64);
+                            self.pc = self.inst_pc.wrapping_add(imm as u64);
                         }
                     }
                     Inst::BLTU => {
                         if (self.regfile.x[rs1 as usize] as u64)
                             < (self.regfile.x[rs2 as usize] as u64)
                         {
-                            self.pc = self.pc.wrapping_sub(4).wrapping_add(imm as u64);
+                            self.pc = self.inst_pc.wrapping_add(imm as u64);
                         }
                     }
                     Inst::BGEU => {
                         if (self.regfile.x[rs1 as usize] as u64)
                             >= (self.regfile.x[rs2 as usize] as u64)
                         {
-                            self.pc = self.pc.wrapping_sub(4).wrapping_add(imm as u64);
+                            self.pc = self.inst_pc.wrapping_add(imm as u64);
                         }
                     }
                     _ => {
@@ -1452,7 +2528,7 @@ impl Core {
                     Inst::AUIPC => {
                         if rd > 0 {
                             self.regfile.x[rd as usize] =
-                                self.pc.wrapping_sub(4).wrapping_add(imm) as i64;
+                                self.inst_pc.wrapping_add(imm) as i64;
                         }
                     }
                     Inst::LUI => {
@@ -1466,61 +2542,976 @@ impl Core {
                 }
             }
 
+            InstType::F => return self.exec_fp(word, inst),
             InstType::C => {
                 let rd = inst_wrap.val(11, 7);
                 let rs1 = inst_wrap.val(19, 15);
                 let csr = inst_wrap.val(31, 20) as u16;
 
-                match inst {
-                    Inst::CSRRW => {
-                        let dat = match self.read_csr(csr) {
-                            Ok(v) => v,
-                            Err(e) => return Err(e),
-                        };
-                        if rd > 0 {
-                            self.regfile.x[rd as usize] = dat as i64;
-                        }
-                        match self.write_csr(csr, self.regfile.x[rs1 as usize] as u64) {
-                            Ok(()) => {}
-                            Err(e) => return Err(e),
-                        };
-                    }
-                    Inst::CSRRS => {
-                        let dat = match self.read_csr(csr) {
-                            Ok(v) => v,
-                            Err(e) => return Err(e),
-                        };
-                        if rd > 0 {
-                            self.regfile.x[rd as usize] = dat as i64;
-                        }
-                        match self.write_csr(
-                            csr,
-                            (self.regfile.x[rd as usize] | self.regfile.x[rs1 as usize]) as u64,
-                        ) {
-                            Ok(()) => {}
-                            Err(e) => return Err(e),
-                        };
-                    }
-                    Inst::CSRRWI => {
-                        let dat = match self.read_csr(csr) {
-                            Ok(v) => v,
-                            Err(e) => return Err(e),
-                        };
-                        if rd > 0 {
-                            self.regfile.x[rd as usize] = dat as i64;
-                        }
-                        match self.write_csr(csr, rs1 as u64) {
-                            Ok(()) => {}
-                            Err(e) => return Err(e),
-                        };
-                        // self.csr[csr as usize] = rs1 as u64;
-                    }
-                    _ => {
-                        panic!();
-                    }
+                // NOTE: the imm variants take the 5-bit uimm from the rs1 field
+                let src = match inst {
+                    Inst::CSRRW | Inst::CSRRS | Inst::CSRRC => self.regfile.x[rs1 as usize] as u64,
+                    _ => rs1 as u64,
+                };
+                // NOTE: csrrw[i] with rd=x0 shall not read the csr, csrrs[i]/csrrc[i]
+                // with rs1=x0 or uimm=0 shall not write it, so no side effect is raised
+                let is_swap = matches!(inst, Inst::CSRRW | Inst::CSRRWI);
+                let dat = match is_swap && rd == 0 {
+                    true => 0u64,
+                    false => match self.read_csr(csr) {
+                        Ok(v) => v,
+                        Err(e) => return Err(e),
+                    },
+                };
+                if is_swap || rs1 != 0 {
+                    let val = match inst {
+                        Inst::CSRRW | Inst::CSRRWI => src,
+                        Inst::CSRRS | Inst::CSRRSI => dat | src,
+                        Inst::CSRRC | Inst::CSRRCI => dat & !src,
+                        _ => panic!(),
+                    };
+                    match self.write_csr(csr, val) {
+                        Ok(()) => {}
+                        Err(e) => return Err(e),
+                    };
+                }
+                // NOTE: rd is updated last, so a faulting csr access leaves it unchanged
+                if rd > 0 {
+                    self.regfile.x[rd as usize] = dat as i64;
                 }
             }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{CoreConfig, MisalignMode, XLen};
+    use crate::core::Core;
+    use crate::debug::{WatchKind, Watchpoint};
+    use crate::csr;
+    use crate::decode::Decode;
+    use crate::device::CLINT_START_ADDR;
+    use crate::inst::Inst;
+    use crate::mem::{BOOT_ROM_BASE, BOOT_ROM_SIZE};
+    use crate::mmu::{AdMode, MAType, PTE_A, PTE_D, PTE_G, PTE_R, PTE_U, PTE_V, PTE_W, PTE_X};
+    use crate::pmp::{PMP_A_NAPOT, PMP_A_TOR, PMP_L, PMP_R, PMP_W, PMP_X};
+    use crate::privilege::{Exception, ExceptionType, PrivMode};
+
+    // NOTE: like the firmware, all the mem is opened by the pmp for s/u-mode
+    fn new_core() -> Core {
+        let mut core = Core::new(
+            String::from("none"),
+            vec![],
+            XLen::X64,
+            0x80000000u64,
+            0x6b,
+            CoreConfig::default(),
+        );
+        core.write_csr(csr::CSR_PMPADDR0_ADDR, u64::MAX).unwrap();
+        core.write_csr(csr::CSR_PMPCFG0_ADDR, PMP_A_NAPOT | PMP_R | PMP_W | PMP_X).unwrap();
+        core
+    }
+
+    fn csr_word(func3: u32, rd: u32, rs1: u32, addr: u16) -> u32 {
+        ((addr as u32) << 20) | (rs1 << 15) | (func3 << 12) | (rd << 7) | 0x73
+    }
+
+    fn exec_word(core: &mut Core, word: u32) -> Result<(), ExceptionType> {
+        core.inst_raw = word;
+        let inst = Decode::decode(word, &core.xlen).map_err(|e| e.excpt_type)?;
+        core.exec(word, inst).map_err(|e| e.excpt_type)
+    }
+
+    const MSCRATCH: u16 = csr::CSR_MSCRATCH_ADDR;
+
+    #[test]
+    fn csrrw() {
+        let mut core = new_core();
+        core.csr.set(MSCRATCH, 0x55);
+        core.regfile.x[5] = 0xAA;
+        exec_word(&mut core, csr_word(1, 6, 5, MSCRATCH)).unwrap();
+        assert_eq!(core.regfile.x[6], 0x55);
+        assert_eq!(core.csr.get(MSCRATCH), 0xAA);
+        // NOTE: rd == rs1 must swap with the old rs1 value
+        exec_word(&mut core, csr_word(1, 5, 5, MSCRATCH)).unwrap();
+        assert_eq!(core.regfile.x[5], 0xAA);
+        assert_eq!(core.csr.get(MSCRATCH), 0xAA);
+    }
+
+    #[test]
+    fn csrrs() {
+        let mut core = new_core();
+        core.csr.set(MSCRATCH, 0x0F);
+        core.regfile.x[5] = 0xF0;
+        exec_word(&mut core, csr_word(2, 0, 5, MSCRATCH)).unwrap();
+        assert_eq!(core.csr.get(MSCRATCH), 0xFF);
+        exec_word(&mut core, csr_word(2, 6, 0, MSCRATCH)).unwrap();
+        assert_eq!(core.regfile.x[6], 0xFF);
+    }
+
+    #[test]
+    fn csrrc() {
+        let mut core = new_core();
+        core.csr.set(MSCRATCH, 0xFF);
+        core.regfile.x[5] = 0x0F;
+        exec_word(&mut core, csr_word(3, 6, 5, MSCRATCH)).unwrap();
+        assert_eq!(core.regfile.x[6], 0xFF);
+        assert_eq!(core.csr.get(MSCRATCH), 0xF0);
+    }
+
+    #[test]
+    fn csrrwi() {
+        let mut core = new_core();
+        core.csr.set(MSCRATCH, 0x55);
+        exec_word(&mut core, csr_word(5, 6, 0x1F, MSCRATCH)).unwrap();
+        assert_eq!(core.regfile.x[6], 0x55);
+        assert_eq!(core.csr.get(MSCRATCH), 0x1F);
+    }
+
+    #[test]
+    fn csrrsi() {
+        let mut core = new_core();
+        core.csr.set(MSCRATCH, 0x10);
+        exec_word(&mut core, csr_word(6, 6, 0x03, MSCRATCH)).unwrap();
+        assert_eq!(core.regfile.x[6], 0x10);
+        assert_eq!(core.csr.get(MSCRATCH), 0x13);
+    }
+
+    #[test]
+    fn csrrci() {
+        let mut core = new_core();
+        core.csr.set(MSCRATCH, 0x1F);
+        exec_word(&mut core, csr_word(7, 6, 0x03, MSCRATCH)).unwrap();
+        assert_eq!(core.regfile.x[6], 0x1F);
+        assert_eq!(core.csr.get(MSCRATCH), 0x1C);
+    }
+
+    #[test]
+    fn csr_read_only() {
+        let mut core = new_core();
+        core.csr.set(csr::CSR_MHARTID_ADDR, 0x3);
+        // NOTE: csrrs/csrrc with rs1=x0 and csrrsi/csrrci with uimm=0 only read
+        for func3 in [2, 3, 6, 7] {
+            exec_word(&mut core, csr_word(func3, 6, 0, csr::CSR_MHARTID_ADDR)).unwrap();
+            assert_eq!(core.regfile.x[6], 0x3);
+        }
+        core.regfile.x[6] = 0;
+        core.regfile.x[5] = 0x1;
+        for func3 in [1, 2, 3, 5, 6, 7] {
+            let res = exec_word(&mut core, csr_word(func3, 6, 5, csr::CSR_MHARTID_ADDR));
+            assert!(matches!(res, Err(ExceptionType::IllegalInst)));
+            assert_eq!(core.regfile.x[6], 0);
+        }
+        assert_eq!(core.csr.get(csr::CSR_MHARTID_ADDR), 0x3);
+    }
+
+    #[test]
+    fn mprv_access_priv() {
+        let mut core = new_core();
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_MPRV | (1 << 11));
+        assert_eq!(core.access_priv(&MAType::Read), 1);
+        assert_eq!(core.access_priv(&MAType::Write), 1);
+        assert_eq!(core.access_priv(&MAType::Exec), 3);
+        core.priv_mode = PrivMode::User;
+        assert_eq!(core.access_priv(&MAType::Read), 0);
+    }
+
+    #[test]
+    fn csr_priv() {
+        let mut core = new_core();
+        core.priv_mode = PrivMode::User;
+        let res = exec_word(&mut core, csr_word(2, 6, 0, MSCRATCH));
+        assert!(matches!(res, Err(ExceptionType::IllegalInst)));
+    }
+
+    #[test]
+    fn counter_enable() {
+        let mut core = new_core();
+        // NOTE: two ticks of nop, then csrr x6, instret in each mode
+        core.bus.write(0x8000_0000, 8, 0x0000_0013_0000_0013).unwrap();
+        core.pc = 0x8000_0000;
+        core.tick();
+        core.tick();
+        let rdinstret = csr_word(2, 6, 0, csr::CSR_INSTRET_ADDR);
+        assert!(exec_word(&mut core, rdinstret).is_ok());
+        assert_eq!(core.regfile.x[6], 2);
+        core.priv_mode = PrivMode::Supervisor;
+        let res = exec_word(&mut core, rdinstret);
+        assert!(matches!(res, Err(ExceptionType::IllegalInst)));
+        core.csr.set(csr::CSR_MCOUNTEREN_ADDR, 0x4);
+        assert!(exec_word(&mut core, rdinstret).is_ok());
+        core.priv_mode = PrivMode::User;
+        let res = exec_word(&mut core, rdinstret);
+        assert!(matches!(res, Err(ExceptionType::IllegalInst)));
+        core.csr.set(csr::CSR_SCOUNTEREN_ADDR, 0x4);
+        assert!(exec_word(&mut core, rdinstret).is_ok());
+        // NOTE: time follows the clint
+        core.priv_mode = PrivMode::Machine;
+        let rdtime = csr_word(2, 6, 0, csr::CSR_TIME_ADDR);
+        assert!(exec_word(&mut core, rdtime).is_ok());
+        assert_eq!(core.regfile.x[6] as u64, core.dev.clint.borrow().mtime());
+        assert_ne!(core.regfile.x[6], 0);
+    }
+
+    #[test]
+    fn access_fault() {
+        let mut core = new_core();
+        // NOTE: 0x1000_0000 is neither ram nor device
+        core.regfile.x[5] = 0x1000_0000;
+        // lw x6, 0(x5) and sw x6, 0(x5)
+        let res = exec_word(&mut core, 0x0002a303);
+        assert!(matches!(res, Err(ExceptionType::LoadAccessFault)));
+        let res = exec_word(&mut core, 0x0062a023);
+        assert!(matches!(res, Err(ExceptionType::StoreAccessFault)));
+        core.pc = 0x1000_0000;
+        let res = core.fetch().map_err(|e| e.excpt_type);
+        assert!(matches!(res, Err(ExceptionType::InstAccessFault)));
+    }
+
+    fn exec_tval(core: &mut Core, word: u32) -> Result<(), (ExceptionType, u64)> {
+        core.inst_raw = word;
+        let inst = Decode::decode(word, &core.xlen).map_err(|e| (e.excpt_type, e.addr))?;
+        core.exec(word, inst).map_err(|e| (e.excpt_type, e.addr))
+    }
+
+    #[test]
+    fn exception_tval() {
+        let mut core = new_core();
+        core.inst_pc = 0x8000_0010;
+        // ebreak and ecall
+        let res = exec_tval(&mut core, 0x00100073);
+        assert_eq!(res, Err((ExceptionType::Breakpoint, 0x8000_0010)));
+        let res = exec_tval(&mut core, 0x00000073);
+        assert_eq!(res, Err((ExceptionType::EnvCallFromMMode, 0)));
+        // NOTE: amo reports the store cause with the va, lr the load cause
+        core.regfile.x[5] = 0x1000_0000;
+        // amoadd.w x6, x6, (x5) and lr.w x6, (x5)
+        let res = exec_tval(&mut core, 0x0062a32f);
+        assert_eq!(res, Err((ExceptionType::StoreAccessFault, 0x1000_0000)));
+        let res = exec_tval(&mut core, 0x1002a32f);
+        assert_eq!(res, Err((ExceptionType::LoadAccessFault, 0x1000_0000)));
+
+        // NOTE: the misaligned access is split by default, or traps
+        core.regfile.x[5] = 0x8000_0001;
+        assert!(exec_tval(&mut core, 0x0002a303).is_ok());
+        core.misalign = MisalignMode::Trap;
+        let res = exec_tval(&mut core, 0x0002a303);
+        assert_eq!(res, Err((ExceptionType::LoadAddrMisaligned, 0x8000_0001)));
+        let res = exec_tval(&mut core, 0x0062a023);
+        assert_eq!(res, Err((ExceptionType::StoreAddrMisaligned, 0x8000_0001)));
+        core.pc = 0x8000_0001;
+        let res = core.fetch().map_err(|e| (e.excpt_type, e.addr));
+        assert_eq!(res, Err((ExceptionType::InstAddrMisaligned, 0x8000_0001)));
+    }
+
+    const FADD_S_DYN: u32 = 0x00c5f553; // fadd.s fa0, fa1, fa2
+    const FADD_S_RNE: u32 = 0x00c58553; // fadd.s fa0, fa1, fa2, rne
+    const MSTATUS: u16 = csr::CSR_MSTATUS_ADDR;
+
+    fn fs_bits(core: &Core) -> u64 {
+        core.csr.get(MSTATUS) & csr::MSTATUS_FS_MASK
+    }
+
+    #[test]
+    fn fp_state() {
+        let mut core = new_core();
+        // NOTE: any fp inst is illegal with FS off, also the load
+        let mstatus = core.csr.get(MSTATUS);
+        core.csr.set(MSTATUS, mstatus & !csr::MSTATUS_FS_MASK);
+        let res = exec_word(&mut core, FADD_S_DYN);
+        assert_eq!(res, Err(ExceptionType::IllegalInst));
+        core.regfile.x[5] = 0x8000_1000;
+        let res = exec_word(&mut core, 0x0002a507); // flw fa0, 0(t0)
+        assert_eq!(res, Err(ExceptionType::IllegalInst));
+        assert!(core.write_csr(csr::CSR_FRM_ADDR, 0).is_err());
+
+        // NOTE: the reserved frm only traps when the inst uses the dynamic rm
+        for frm in 5..=7 {
+            core.csr.set(MSTATUS, mstatus);
+            core.write_csr(csr::CSR_FRM_ADDR, frm).unwrap();
+            core.csr.set(MSTATUS, mstatus);
+            let res = exec_word(&mut core, FADD_S_DYN);
+            assert_eq!(res, Err(ExceptionType::IllegalInst));
+            assert_eq!(fs_bits(&core), csr::MSTATUS_FS_INITIAL);
+            assert!(exec_word(&mut core, FADD_S_RNE).is_ok());
+            assert_eq!(fs_bits(&core), csr::MSTATUS_FS_DIRTY);
+        }
+        core.write_csr(csr::CSR_FRM_ADDR, 0).unwrap();
+        core.csr.set(MSTATUS, mstatus);
+        assert!(exec_word(&mut core, FADD_S_DYN).is_ok());
+        assert_eq!(fs_bits(&core), csr::MSTATUS_FS_DIRTY);
+    }
+
+    #[test]
+    fn fp_nan_box() {
+        let mut core = new_core();
+        // NOTE: the single operand which is not boxed reads as the canonical NaN
+        core.fregfile.f[11] = 0x3F80_0000;
+        exec_word(&mut core, 0x20b58553).unwrap(); // fmv.s fa0, fa1
+        assert_eq!(core.fregfile.f[10], 0xFFFF_FFFF_7FC0_0000);
+        core.fregfile.f[11] = 0xFFFF_FFFF_3F80_0000;
+        core.fregfile.f[12] = 0xFFFF_FFFF_4000_0000;
+        exec_word(&mut core, FADD_S_RNE).unwrap();
+        assert_eq!(core.fregfile.f[10], 0xFFFF_FFFF_4040_0000);
+
+        // NOTE: flw and fmv.w.x box the result
+        core.bus.write(0x8000_1000, 8, 0x1234_5678_3F80_0000).unwrap();
+        core.regfile.x[5] = 0x8000_1000;
+        exec_word(&mut core, 0x0002a507).unwrap(); // flw fa0, 0(t0)
+        assert_eq!(core.fregfile.f[10], 0xFFFF_FFFF_3F80_0000);
+        core.regfile.x[11] = 0x1234_5678_4000_0000;
+        exec_word(&mut core, 0xf0058553).unwrap(); // fmv.w.x fa0, a1
+        assert_eq!(core.fregfile.f[10], 0xFFFF_FFFF_4000_0000);
+    }
+
+    // NOTE: a 4KiB mapping of va, the tables are placed from 0x8010_0000
+    fn map_page(core: &mut Core, levels: u64, va: u64, pa: u64, flags: u64) {
+        let mut table = 0x8010_0000u64;
+        for level in (1..levels).rev() {
+            let vpn = (va >> (12 + level * 9)) & 0x1FF;
+            let next = table + 0x1000;
+            core.bus.write(table + vpn * 8, 8, ((next >> 12) << 10) | PTE_V).unwrap();
+            table = next;
+        }
+        let vpn = (va >> 12) & 0x1FF;
+        core.bus.write(table + vpn * 8, 8, ((pa >> 12) << 10) | flags | PTE_V).unwrap();
+    }
+
+    fn trans(core: &mut Core, va: u64, ma_type: MAType) -> Result<u64, ExceptionType> {
+        core.trans_addr(va, ma_type).map_err(|e| e.excpt_type)
+    }
+
+    #[test]
+    fn page_walk() {
+        for (mode, levels) in [(8u64, 3u64), (9, 4), (10, 5)] {
+            let mut core = new_core();
+            let va = 0x1234_5000 | (1 << (12 + 9 * levels - 2));
+            map_page(&mut core, levels, va, 0x8000_3000, PTE_R | PTE_W | PTE_A | PTE_D);
+            core.write_csr(csr::CSR_SATP_ADDR, (mode << 60) | 0x80100).unwrap();
+            core.priv_mode = PrivMode::Supervisor;
+            assert_eq!(trans(&mut core, va | 0x18, MAType::Write), Ok(0x8000_3018));
+            assert_eq!(trans(&mut core, va, MAType::Exec), Err(ExceptionType::InstPageFault));
+            // NOTE: the va which is not sign-extended
+            let bad = va | (1 << 62);
+            assert_eq!(trans(&mut core, bad, MAType::Read), Err(ExceptionType::LoadPageFault));
+            assert_eq!(trans(&mut core, 0x0, MAType::Write), Err(ExceptionType::StorePageFault));
+        }
+    }
+
+    #[test]
+    fn page_walk_ad() {
+        let mut core = new_core();
+        map_page(&mut core, 3, 0x1000, 0x8000_3000, PTE_R | PTE_W);
+        core.write_csr(csr::CSR_SATP_ADDR, (8 << 60) | 0x80100).unwrap();
+        core.priv_mode = PrivMode::Supervisor;
+        assert_eq!(trans(&mut core, 0x1000, MAType::Read), Err(ExceptionType::LoadPageFault));
+        core.ad_mode = AdMode::Svadu;
+        assert_eq!(trans(&mut core, 0x1000, MAType::Read), Ok(0x8000_3000));
+        let pte_addr = 0x8010_2000 + 8;
+        assert_eq!(core.bus.read(pte_addr, 8).unwrap() & (PTE_A | PTE_D), PTE_A);
+        assert_eq!(trans(&mut core, 0x1000, MAType::Write), Ok(0x8000_3000));
+        assert_eq!(core.bus.read(pte_addr, 8).unwrap() & PTE_D, PTE_D);
+    }
+
+    #[test]
+    fn page_walk_perm() {
+        let mut core = new_core();
+        map_page(&mut core, 3, 0x1000, 0x8000_3000, PTE_R | PTE_X | PTE_U | PTE_A);
+        map_page(&mut core, 3, 0x2000, 0x8000_4000, PTE_X | PTE_A);
+        core.write_csr(csr::CSR_SATP_ADDR, (8 << 60) | 0x80100).unwrap();
+        core.priv_mode = PrivMode::Supervisor;
+        // NOTE: s-mode accesses the user page only with SUM, and never executes it
+        assert_eq!(trans(&mut core, 0x1000, MAType::Read), Err(ExceptionType::LoadPageFault));
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_SUM);
+        assert_eq!(trans(&mut core, 0x1000, MAType::Read), Ok(0x8000_3000));
+        assert_eq!(trans(&mut core, 0x1000, MAType::Exec), Err(ExceptionType::InstPageFault));
+        // NOTE: MXR makes the execute-only page readable
+        assert_eq!(trans(&mut core, 0x2000, MAType::Read), Err(ExceptionType::LoadPageFault));
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_MXR);
+        assert_eq!(trans(&mut core, 0x2000, MAType::Read), Ok(0x8000_4000));
+        // NOTE: u-mode can not access the supervisor page
+        core.priv_mode = PrivMode::User;
+        assert_eq!(trans(&mut core, 0x1000, MAType::Exec), Ok(0x8000_3000));
+        assert_eq!(trans(&mut core, 0x2000, MAType::Exec), Err(ExceptionType::InstPageFault));
+        // NOTE: the pte outside of the mem raises the access fault
+        core.write_csr(csr::CSR_SATP_ADDR, (8 << 60) | 0x10000).unwrap_err();
+        core.priv_mode = PrivMode::Machine;
+        core.write_csr(csr::CSR_SATP_ADDR, (8 << 60) | 0x10000).unwrap();
+        core.tlb.fence(None, None);
+        core.priv_mode = PrivMode::User;
+        assert_eq!(trans(&mut core, 0x1000, MAType::Read), Err(ExceptionType::LoadAccessFault));
+    }
+
+    #[test]
+    fn debug_walk() {
+        let mut core = new_core();
+        map_page(&mut core, 3, 0x1000, 0x8000_3000, PTE_R | PTE_W);
+        map_page(&mut core, 3, 0x2000, 0x8000_4000, PTE_X);
+        map_page(&mut core, 3, 0x3000, 0x8000_5000, PTE_R | PTE_U);
+        core.write_csr(csr::CSR_SATP_ADDR, (8 << 60) | 0x80100).unwrap();
+        core.bus.write(0x8000_3008, 4, 0x1122_3344).unwrap();
+        core.bus.write(0x8000_4000, 4, 0x13).unwrap();
+        core.priv_mode = PrivMode::Supervisor;
+        core.ad_mode = AdMode::Svadu;
+        // NOTE: no A/D or permission check, the A=0, execute-only and user
+        // pages are all visible to the debugger
+        assert_eq!(core.debug_read(0x1008, 4), Some(vec![0x44, 0x33, 0x22, 0x11]));
+        assert_eq!(core.debug_inst(0x2000), Some(0x13));
+        assert!(core.debug_write(0x3000, &[0xAA]));
+        assert_eq!(core.bus.read(0x8000_5000, 1).unwrap(), 0xAA);
+        assert_eq!(core.debug_read(0x4000, 1), None);
+        assert_eq!(core.debug_read(0x1000 | (1 << 62), 1), None);
+        // NOTE: the pte is not written and the tlb is not used or filled
+        assert_eq!(core.bus.read(0x8010_2000 + 8, 8).unwrap() & (PTE_A | PTE_D), 0);
+        assert_eq!((core.tlb.hit, core.tlb.miss), (0, 0));
+        assert_eq!(trans(&mut core, 0x1000, MAType::Read), Ok(0x8000_3000));
+        assert_eq!((core.tlb.hit, core.tlb.miss), (0, 1));
+        // NOTE: m-mode sees the phy addr
+        core.priv_mode = PrivMode::Machine;
+        assert_eq!(core.debug_read(0x8000_3008, 1), Some(vec![0x44]));
+    }
+
+    #[test]
+    fn check_end_paged() {
+        let mut core = new_core();
+        map_page(&mut core, 3, 0x1000, 0x8000_3000, PTE_X);
+        map_page(&mut core, 3, 0x2000, 0x8000_4000, PTE_X | PTE_A);
+        core.write_csr(csr::CSR_SATP_ADDR, (8 << 60) | 0x80100).unwrap();
+        core.bus.write(0x8000_3ffc, 4, 0x13).unwrap();
+        core.bus.write(0x8000_4004, 4, 0x6b).unwrap();
+        // NOTE: the end inst across the page
+        core.bus.write(0x8000_3ffe, 2, 0x6b).unwrap();
+        core.priv_mode = PrivMode::User;
+        core.ad_mode = AdMode::Svadu;
+        core.pc = 0x1ffc;
+        assert!(!core.check_end());
+        core.pc = 0x1ffe;
+        assert!(core.check_end());
+        core.pc = 0x2004;
+        assert!(core.check_end());
+        core.pc = 0x5000;
+        assert!(!core.check_end());
+        // NOTE: the check neither uses the tlb nor sets the A bit
+        assert_eq!((core.tlb.hit, core.tlb.miss), (0, 0));
+        assert_eq!(core.bus.read(0x8010_2000 + 8, 8).unwrap() & PTE_A, 0);
+    }
+
+    #[test]
+    fn tlb_sfence() {
+        let mut core = new_core();
+        let flags = PTE_R | PTE_W | PTE_A | PTE_D;
+        map_page(&mut core, 3, 0x1000, 0x8000_3000, flags);
+        map_page(&mut core, 3, 0x2000, 0x8000_4000, flags | PTE_G);
+        core.write_csr(csr::CSR_SATP_ADDR, (8 << 60) | (1 << 44) | 0x80100).unwrap();
+        core.priv_mode = PrivMode::Supervisor;
+        assert_eq!(trans(&mut core, 0x1000, MAType::Read), Ok(0x8000_3000));
+        assert_eq!(trans(&mut core, 0x2000, MAType::Read), Ok(0x8000_4000));
+        // NOTE: the stale translation is used until the sfence.vma
+        map_page(&mut core, 3, 0x1000, 0x8000_5000, flags);
+        map_page(&mut core, 3, 0x2000, 0x8000_6000, flags | PTE_G);
+        assert_eq!(trans(&mut core, 0x1000, MAType::Read), Ok(0x8000_3000));
+        // NOTE: the fence by asid keeps the global page
+        core.regfile.x[5] = 0x1000;
+        core.regfile.x[6] = 1;
+        core.exec(0x1262_8073, Inst::SFENCEVMA).unwrap();
+        assert_eq!(trans(&mut core, 0x1000, MAType::Read), Ok(0x8000_5000));
+        assert_eq!(trans(&mut core, 0x2000, MAType::Read), Ok(0x8000_4000));
+        core.exec(0x1200_0073, Inst::SFENCEVMA).unwrap();
+        assert_eq!(trans(&mut core, 0x2000, MAType::Read), Ok(0x8000_6000));
+        assert_eq!((core.tlb.hit, core.tlb.miss, core.tlb.flush), (2, 4, 2));
+    }
+
+    #[test]
+    fn pmp_access() {
+        let mut core = new_core();
+        // NOTE: entry 1 makes [0x8000_0000, 0x8000_1000) locked read-only, the
+        // rest is opened by entry 2
+        core.write_csr(csr::CSR_PMPADDR0_ADDR, 0x8000_0000 >> 2).unwrap();
+        core.write_csr(csr::CSR_PMPADDR0_ADDR + 1, 0x8000_1000 >> 2).unwrap();
+        core.write_csr(csr::CSR_PMPADDR0_ADDR + 2, u64::MAX).unwrap();
+        let cfg = (PMP_A_TOR | PMP_R | PMP_L) << 8 | (PMP_A_NAPOT | PMP_R | PMP_W | PMP_X) << 16;
+        core.write_csr(csr::CSR_PMPCFG0_ADDR, cfg).unwrap();
+
+        let fault = |v: Exception| (v.excpt_type, v.addr);
+        let res = core.store_mem(0x8000_0ff8, 8, 0, true).map_err(fault);
+        assert_eq!(res, Err((ExceptionType::StoreAccessFault, 0x8000_0ff8)));
+        assert!(core.store_mem(0x8000_1000, 8, 0, true).is_ok());
+        assert!(core.load_mem(0x8000_0000, 8, true).is_ok());
+        // NOTE: the untranslated access across the entry boundary fails
+        let res = core.load_mem(0x8000_0ffc, 8, false).map_err(fault);
+        assert_eq!(res, Err((ExceptionType::LoadAccessFault, 0x8000_0ffc)));
+        core.priv_mode = PrivMode::Supervisor;
+        let res = core.read_mem(0x8000_0000, 2, MAType::Exec, true).map_err(fault);
+        assert_eq!(res, Err((ExceptionType::InstAccessFault, 0x8000_0000)));
+        assert!(core.read_mem(0x8000_2000, 2, MAType::Exec, true).is_ok());
+        // NOTE: the locked entry and the pmpaddr below it ignore the write
+        core.priv_mode = PrivMode::Machine;
+        core.write_csr(csr::CSR_PMPCFG0_ADDR, 0).unwrap();
+        core.write_csr(csr::CSR_PMPADDR0_ADDR, 0).unwrap();
+        assert_eq!(core.read_csr(csr::CSR_PMPCFG0_ADDR).unwrap() >> 8 & 0xFF, PMP_A_TOR | PMP_R | PMP_L);
+        assert_eq!(core.read_csr(csr::CSR_PMPADDR0_ADDR).unwrap(), 0x8000_0000 >> 2);
+    }
+
+    #[test]
+    fn rvc_fetch() {
+        let mut core = new_core();
+        core.csr.set(csr::CSR_MTVEC_ADDR, 0x8000_0100);
+        core.load_bin_file(vec![
+            0x7d, 0x15, // c.addi a0, -1
+            0x13, 0x05, 0x25, 0x00, // addi a0, a0, 2
+            0x09, 0xa0, // c.j 2
+            0x01, 0x61, // c.addi16sp sp, 0
+        ]).unwrap();
+        core.step();
+        assert_eq!(
+            (core.inst_pc, core.pc, core.inst_raw),
+            (0x8000_0000, 0x8000_0002, 0x157d)
+        );
+        // NOTE: the 32-bit inst is only halfword aligned
+        core.step();
+        assert_eq!(
+            (core.inst_pc, core.pc, core.inst_raw),
+            (0x8000_0002, 0x8000_0006, 0x00250513)
+        );
+        assert_eq!(core.regfile.x[10], 1);
+        core.step();
+        assert_eq!((core.inst_pc, core.pc), (0x8000_0006, 0x8000_0008));
+        // NOTE: the reserved rvc inst reports its halfword in mtval
+        core.step();
+        assert_eq!(core.pc, 0x8000_0100);
+        assert_eq!(core.csr.get(csr::CSR_MEPC_ADDR), 0x8000_0008);
+        assert_eq!(core.csr.get(csr::CSR_MCAUSE_ADDR), 2);
+        assert_eq!(core.csr.get(csr::CSR_MTVAL_ADDR), 0x6101);
+    }
+
+    #[test]
+    fn jalr_odd() {
+        let mut core = new_core();
+        core.csr.set(csr::CSR_MTVEC_ADDR, 0x8000_0100);
+        core.load_bin_file(vec![
+            0xe7, 0x80, 0x02, 0x00, // jalr ra, 0(t0)
+            0x67, 0x80, 0x12, 0x00, // jalr zero, 1(t0)
+        ]).unwrap();
+        // NOTE: the bit 0 of the target is cleared, it does not trap
+        core.regfile.x[5] = 0x8000_0005;
+        core.step();
+        assert_eq!((core.pc, core.regfile.x[1]), (0x8000_0004, 0x8000_0004));
+        core.regfile.x[5] = 0x8000_0000;
+        core.step();
+        assert_eq!(core.pc, 0x8000_0000);
+    }
+
+    // NOTE: a minimal elf64 exec with one PT_LOAD per (paddr, data, memsz), the
+    // vaddr differs from the paddr to check the placement
+    fn elf64(entry: u64, segs: &[(u64, &[u8], u64)]) -> Vec<u8> {
+        let mut res = vec![0x7f, b'E', b'L', b'F', 2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        res.extend(2u16.to_le_bytes()); // e_type
+        res.extend(0xf3u16.to_le_bytes()); // e_machine
+        res.extend(1u32.to_le_bytes()); // e_version
+        res.extend(entry.to_le_bytes());
+        res.extend(64u64.to_le_bytes()); // e_phoff
+        res.extend(0u64.to_le_bytes()); // e_shoff
+        res.extend(0u32.to_le_bytes()); // e_flags
+        for v in [64u16, 56, segs.len() as u16, 64, 0, 0] {
+            res.extend(v.to_le_bytes());
+        }
+        let mut offset = 64 + 56 * segs.len() as u64;
+        for (paddr, data, mem_size) in segs {
+            res.extend(1u32.to_le_bytes()); // PT_LOAD
+            res.extend(7u32.to_le_bytes()); // rwx
+            for v in [offset, paddr + 0x1000_0000, *paddr, data.len() as u64, *mem_size, 8] {
+                res.extend(v.to_le_bytes());
+            }
+            offset += data.len() as u64;
+        }
+        for (_, data, _) in segs {
+            res.extend(*data);
+        }
+        res
+    }
+
+    #[test]
+    fn elf_load() {
+        let mut core = new_core();
+        let code: &[u8] = &[0x13, 0x00, 0x00, 0x00, 0x13, 0x05, 0x15, 0x00];
+        let data: &[u8] = &[0x11, 0x22, 0x33, 0x44];
+        let file = elf64(0x8000_0004, &[(0x8000_0000, code, 8), (0x8000_2000, data, 0x10)]);
+        // NOTE: the stale data in the bss range is cleared by the load
+        core.bus.write(0x8000_2008, 8, u64::MAX).unwrap();
+        core.load_elf_file(file.clone()).unwrap();
+        assert_eq!((core.pc, core.entry_addr), (0x8000_0004, 0x8000_0004));
+        assert_eq!(core.bus.read(0x8000_0004, 4).unwrap(), 0x00150513);
+        assert_eq!(core.bus.read(0x8000_2000, 8).unwrap(), 0x4433_2211);
+        assert_eq!(core.bus.read(0x8000_2008, 8).unwrap(), 0);
+        core.step();
+        assert_eq!((core.pc, core.regfile.x[10]), (0x8000_0008, 1));
+        // NOTE: the reset reloads the image and starts from e_entry again
+        core.bus.write(0x8000_2008, 8, u64::MAX).unwrap();
+        core.reset();
+        assert_eq!(core.pc, 0x8000_0004);
+        assert_eq!(core.bus.read(0x8000_2008, 8).unwrap(), 0);
+
+        // NOTE: the bss beyond the mem is rejected and the old image is kept
+        let file = elf64(0x8000_0000, &[(0x8000_0000, code, u64::from(u32::MAX))]);
+        assert!(core.load_elf_file(file).is_err());
+        assert_eq!(core.pc, 0x8000_0004);
+    }
+
+    #[test]
+    fn bin_load() {
+        let mut core = Core::new(
+            String::from("none"),
+            vec![],
+            XLen::X64,
+            BOOT_ROM_BASE,
+            0x6b,
+            CoreConfig::default(),
+        );
+        core.load_bin_file(vec![0x13, 0x05, 0x15, 0x00]).unwrap();
+        assert_eq!(core.bus.read(BOOT_ROM_BASE, 4).unwrap(), 0x00150513);
+        // NOTE: the bin beyond the rom is rejected and the old image is kept
+        assert!(core.load_bin_file(vec![0; BOOT_ROM_SIZE as usize + 1]).is_err());
+        core.reset();
+        assert_eq!(core.bus.read(BOOT_ROM_BASE, 4).unwrap(), 0x00150513);
+    }
+
+    const MSIP: u64 = CLINT_START_ADDR;
+    const MTIMECMP: u64 = CLINT_START_ADDR + 0x4000;
+
+    #[test]
+    fn interrupt_priority() {
+        let mut core = new_core();
+        let pending = |core: &mut Core| {
+            core.update_mip();
+            core.pending_interrupt()
+        };
+        core.write_csr(csr::CSR_MIE_ADDR, u64::MAX).unwrap();
+        core.bus.write(MSIP, 4, 1).unwrap();
+        core.bus.write(MTIMECMP, 8, 0).unwrap();
+        core.write_csr(csr::CSR_MIP_ADDR, csr::MIP_SSIP | csr::MIP_STIP).unwrap();
+        assert_eq!(pending(&mut core), None);
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_MIE);
+        // priority: MSI, MTI, SSI, STI without delegation
+        assert_eq!(pending(&mut core), Some((3, false)));
+        core.bus.write(MSIP, 4, 0).unwrap();
+        assert_eq!(pending(&mut core), Some((7, false)));
+        core.bus.write(MTIMECMP, 8, u64::MAX).unwrap();
+        assert_eq!(pending(&mut core), Some((1, false)));
+        core.write_csr(csr::CSR_MIP_ADDR, csr::MIP_STIP).unwrap();
+        assert_eq!(pending(&mut core), Some((5, false)));
+        core.write_csr(csr::CSR_MIE_ADDR, !csr::MIP_STIP).unwrap();
+        assert_eq!(pending(&mut core), None);
+    }
+
+    #[test]
+    fn interrupt_mideleg() {
+        let mut core = new_core();
+        let pending = |core: &mut Core| {
+            core.update_mip();
+            core.pending_interrupt()
+        };
+        core.write_csr(csr::CSR_MIE_ADDR, u64::MAX).unwrap();
+        core.write_csr(csr::CSR_MIDELEG_ADDR, u64::MAX).unwrap();
+        core.write_csr(csr::CSR_MIP_ADDR, csr::MIP_SSIP | csr::MIP_STIP).unwrap();
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_MIE);
+        // NOTE: the delegated interrupts are never taken in m-mode
+        assert_eq!(pending(&mut core), None);
+        core.priv_mode = PrivMode::Supervisor;
+        assert_eq!(pending(&mut core), None);
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_SIE);
+        assert_eq!(pending(&mut core), Some((1, true)));
+        // NOTE: the m-mode interrupts are taken in s-mode even if MIE is 0
+        core.bus.write(MTIMECMP, 8, 0).unwrap();
+        assert_eq!(pending(&mut core), Some((7, false)));
+        core.bus.write(MTIMECMP, 8, u64::MAX).unwrap();
+        // NOTE: the s-mode interrupts are taken in u-mode even if SIE is 0
+        core.priv_mode = PrivMode::User;
+        core.csr.set(csr::CSR_MSTATUS_ADDR, 0);
+        assert_eq!(pending(&mut core), Some((1, true)));
+        core.csr.set(csr::CSR_MIDELEG_ADDR, csr::MIP_STIP);
+        assert_eq!(pending(&mut core), Some((1, false)));
+    }
+
+    #[test]
+    fn clint_interrupt() {
+        let mut core = new_core();
+        core.load_bin_file([0x13, 0x00, 0x00, 0x00].repeat(0x80)).unwrap(); // nop
+        core.csr.set(csr::CSR_MTVEC_ADDR, 0x8000_0100);
+        core.write_csr(csr::CSR_MIE_ADDR, csr::MIP_MTIP | csr::MIP_MSIP).unwrap();
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_MIE);
+        // NOTE: mtimecmp resets to the max, no timer irq is pending
+        core.step();
+        assert_eq!(core.csr.mip() & csr::MIP_MTIP, 0);
+        // NOTE: mtime is 1 after the first tick and advances once per tick
+        core.bus.write(MTIMECMP, 8, 3).unwrap();
+        core.step();
+        assert_eq!(core.pc, 0x8000_0008);
+        core.step();
+        assert_ne!(core.csr.mip() & csr::MIP_MTIP, 0);
+        assert_eq!(core.csr.get(csr::CSR_MCAUSE_ADDR), 1 << 63 | 7);
+        assert_eq!(core.csr.get(csr::CSR_MEPC_ADDR), 0x8000_0008);
+        assert_eq!(core.pc, 0x8000_0104);
+        let mstatus = core.csr.get(csr::CSR_MSTATUS_ADDR);
+        assert_eq!(mstatus & (csr::MSTATUS_MIE | csr::MSTATUS_MPIE), csr::MSTATUS_MPIE);
+
+        core.bus.write(MTIMECMP, 8, u64::MAX).unwrap();
+        core.bus.write(MSIP, 4, 1).unwrap();
+        core.step();
+        assert_eq!(core.csr.mip() & (csr::MIP_MTIP | csr::MIP_MSIP), csr::MIP_MSIP);
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_MIE);
+        core.step();
+        assert_eq!(core.csr.get(csr::CSR_MCAUSE_ADDR), 1 << 63 | 3);
+        assert_eq!(core.csr.get(csr::CSR_MEPC_ADDR), 0x8000_0108);
+    }
+
+    const MRET: u32 = 0x3020_0073;
+    const SRET: u32 = 0x1020_0073;
+    const WFI: u32 = 0x1050_0073;
+    const SFENCE_VMA: u32 = 0x1200_0073;
+
+    #[test]
+    fn trap_vector() {
+        let mut core = new_core();
+        let ecall = |core: &mut Core| {
+            core.handle_trap(Exception {
+                excpt_type: ExceptionType::EnvCallFromMMode,
+                addr: 0,
+            })
+        };
+        core.write_csr(csr::CSR_MTVEC_ADDR, 0x8000_0101).unwrap();
+        core.write_csr(csr::CSR_STVEC_ADDR, 0x8000_0201).unwrap();
+        // NOTE: the exceptions go to the base in vectored mode too
+        ecall(&mut core);
+        assert_eq!(core.pc, 0x8000_0100);
+        core.handle_interrupt(7, false);
+        assert_eq!(core.pc, 0x8000_011c);
+        core.handle_interrupt(5, true);
+        assert_eq!(core.pc, 0x8000_0214);
+        core.trap_enter(8, 0, 0, true);
+        assert_eq!(core.pc, 0x8000_0200);
+        core.priv_mode = PrivMode::Machine;
+        // NOTE: the reserved mode 2 is not written, 3 keeps the vectored bit
+        core.write_csr(csr::CSR_MTVEC_ADDR, 0x8000_0302).unwrap();
+        core.handle_interrupt(7, false);
+        assert_eq!(core.pc, 0x8000_0300);
+        core.write_csr(csr::CSR_MTVEC_ADDR, 0x8000_0303).unwrap();
+        core.handle_interrupt(3, false);
+        assert_eq!(core.pc, 0x8000_030c);
+    }
+
+    #[test]
+    fn xret_stack() {
+        let mut core = new_core();
+        let mstatus = |core: &Core| core.csr.get(csr::CSR_MSTATUS_ADDR);
+        // MIE = MPIE, MPIE = 1, MPP = U and MPRV is cleared for s-mode
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_MPIE | 1 << 11 | csr::MSTATUS_MPRV);
+        core.csr.set(csr::CSR_MEPC_ADDR, 0x8000_0040);
+        exec_word(&mut core, MRET).unwrap();
+        assert!(matches!(core.priv_mode, PrivMode::Supervisor));
+        assert_eq!(core.pc, 0x8000_0040);
+        assert_eq!(mstatus(&core), csr::MSTATUS_MIE | csr::MSTATUS_MPIE);
+
+        // SIE = SPIE, SPIE = 1, SPP = U
+        core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_SIE | csr::MSTATUS_SPP);
+        core.csr.set(csr::CSR_SEPC_ADDR, 0x8000_0080);
+        exec_word(&mut core, SRET).unwrap();
+        assert!(matches!(core.priv_mode, PrivMode::Supervisor));
+        assert_eq!(core.pc, 0x8000_0080);
+        assert_eq!(mstatus(&core), csr::MSTATUS_SPIE);
+        exec_word(&mut core, SRET).unwrap();
+        assert!(matches!(core.priv_mode, PrivMode::User));
+        assert_eq!(mstatus(&core), csr::MSTATUS_SIE | csr::MSTATUS_SPIE);
+
+        // NOTE: the trap stacks the mode into xPP and xIE into xPIE
+        core.trap_enter(8, 0, 0x8000_0100, false);
+        assert_eq!(mstatus(&core) & (csr::MSTATUS_MPP_MASK | csr::MSTATUS_MPIE), 0);
+        core.priv_mode = PrivMode::Supervisor;
+        core.trap_enter(9, 0, 0x8000_0104, true);
+        assert!(matches!(core.priv_mode, PrivMode::Supervisor));
+        let mask = csr::MSTATUS_SPP | csr::MSTATUS_SPIE | csr::MSTATUS_SIE;
+        assert_eq!(mstatus(&core) & mask, csr::MSTATUS_SPP | csr::MSTATUS_SPIE);
+
+        // NOTE: MPRV is kept if mret returns to m-mode, the sret from m-mode clears it
+        core.priv_mode = PrivMode::Machine;
+        core.csr.set(csr::CSR_MSTATUS_ADDR, 3 << 11 | csr::MSTATUS_MPRV);
+        exec_word(&mut core, MRET).unwrap();
+        assert!(matches!(core.priv_mode, PrivMode::Machine));
+        assert_ne!(mstatus(&core) & csr::MSTATUS_MPRV, 0);
+        exec_word(&mut core, SRET).unwrap();
+        assert!(matches!(core.priv_mode, PrivMode::User));
+        assert_eq!(mstatus(&core) & csr::MSTATUS_MPRV, 0);
+
+        // NOTE: the reserved MPP 2 goes to u-mode
+        core.priv_mode = PrivMode::Machine;
+        core.csr.set(csr::CSR_MSTATUS_ADDR, 2 << 11);
+        exec_word(&mut core, MRET).unwrap();
+        assert!(matches!(core.priv_mode, PrivMode::User));
+        assert_eq!(mstatus(&core) & csr::MSTATUS_MPP_MASK, 0);
+        assert_eq!(exec_word(&mut core, MRET), Err(ExceptionType::IllegalInst));
+        assert_eq!(exec_word(&mut core, SRET), Err(ExceptionType::IllegalInst));
+    }
+
+    #[test]
+    fn trap_virt() {
+        let mut core = new_core();
+        let satp = csr_word(2, 10, 0, csr::CSR_SATP_ADDR);
+        for (bit, words) in [
+            (csr::MSTATUS_TSR, vec![SRET]),
+            (csr::MSTATUS_TVM, vec![satp, SFENCE_VMA]),
+            (csr::MSTATUS_TW, vec![WFI]),
+        ] {
+            for word in words {
+                // NOTE: the traps only apply to s-mode, m-mode ignores the bits
+                core.csr.set(csr::CSR_MSTATUS_ADDR, bit | csr::MSTATUS_SPP);
+                core.priv_mode = PrivMode::Machine;
+                assert_eq!(exec_word(&mut core, word), Ok(()), "{:08x}", word);
+                core.priv_mode = PrivMode::Supervisor;
+                assert_eq!(exec_word(&mut core, word), Err(ExceptionType::IllegalInst));
+                core.csr.set(csr::CSR_MSTATUS_ADDR, csr::MSTATUS_SPP);
+                assert_eq!(exec_word(&mut core, word), Ok(()), "{:08x}", word);
+            }
+        }
+        // NOTE: wfi always traps in u-mode
+        core.priv_mode = PrivMode::User;
+        assert_eq!(exec_word(&mut core, WFI), Err(ExceptionType::IllegalInst));
+    }
+
+    // NOTE: lr/sc/amo with aq=rl=0, 'func5' selects the op and 'func3' the width
+    fn amo_word(func5: u32, func3: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+        (func5 << 27) | (rs2 << 20) | (rs1 << 15) | (func3 << 12) | (rd << 7) | 0x2f
+    }
+
+    const LR: u32 = 0b00010;
+    const SC: u32 = 0b00011;
+
+    #[test]
+    fn lr_sc() {
+        let mut core = new_core();
+        core.regfile.x[5] = 0x8000_1000;
+        core.regfile.x[6] = 0x55;
+        core.bus.write(0x8000_1000, 8, 0x11).unwrap();
+        // NOTE: sc without a reservation fails and keeps the mem
+        exec_word(&mut core, amo_word(SC, 2, 7, 5, 6)).unwrap();
+        assert_eq!(core.regfile.x[7], 1);
+        assert_eq!(core.bus.read(0x8000_1000, 8).unwrap(), 0x11);
+
+        exec_word(&mut core, amo_word(LR, 2, 7, 5, 0)).unwrap();
+        assert_eq!(core.regfile.x[7], 0x11);
+        exec_word(&mut core, amo_word(SC, 2, 7, 5, 6)).unwrap();
+        assert_eq!(core.regfile.x[7], 0);
+        assert_eq!(core.bus.read(0x8000_1000, 8).unwrap(), 0x55);
+        // NOTE: the reservation is used up by the first sc
+        exec_word(&mut core, amo_word(SC, 2, 7, 5, 6)).unwrap();
+        assert_eq!(core.regfile.x[7], 1);
+        // NOTE: an sc to another addr fails
+        exec_word(&mut core, amo_word(LR, 3, 7, 5, 0)).unwrap();
+        core.regfile.x[5] = 0x8000_1008;
+        exec_word(&mut core, amo_word(SC, 3, 7, 5, 6)).unwrap();
+        assert_eq!(core.regfile.x[7], 1);
+    }
+
+    #[test]
+    fn lr_sc_invalidate() {
+        let mut core = new_core();
+        core.regfile.x[5] = 0x8000_1000;
+        core.regfile.x[8] = 0x8000_1004;
+        let sc = |core: &mut Core| {
+            exec_word(core, amo_word(SC, 2, 7, 5, 0)).unwrap();
+            core.regfile.x[7]
+        };
+        // NOTE: a store to the reserved doubleword, even to the other word
+        exec_word(&mut core, amo_word(LR, 2, 7, 5, 0)).unwrap();
+        exec_word(&mut core, (8 << 20) | (8 << 15) | (2 << 12) | 0x23).unwrap();
+        assert_eq!(sc(&mut core), 1);
+        // NOTE: a store elsewhere keeps the reservation
+        exec_word(&mut core, amo_word(LR, 2, 7, 5, 0)).unwrap();
+        core.store_mem(0x8000_1008, 8, 0, true).unwrap();
+        assert_eq!(sc(&mut core), 0);
+
+        exec_word(&mut core, amo_word(LR, 2, 7, 5, 0)).unwrap();
+        core.handle_trap(Exception {
+            excpt_type: ExceptionType::Breakpoint,
+            addr: 0,
+        });
+        assert_eq!(sc(&mut core), 1);
+
+        for word in [0x3020_0073, 0x1020_0073] {
+            core.priv_mode = PrivMode::Machine;
+            core.csr.set(csr::CSR_MSTATUS_ADDR, 3 << 11 | csr::MSTATUS_SPP);
+            exec_word(&mut core, amo_word(LR, 2, 7, 5, 0)).unwrap();
+            exec_word(&mut core, word).unwrap();
+            assert_eq!(sc(&mut core), 1);
+        }
+    }
+
+    #[test]
+    fn amo() {
+        let mut core = new_core();
+        core.regfile.x[5] = 0x8000_1000;
+        core.bus.write(0x8000_1000, 8, 0xAAAA_AAAA_7FFF_FFFF).unwrap();
+        core.regfile.x[6] = 1;
+        // NOTE: the old word and the result of amo*.w are sign-extended on rv64
+        exec_word(&mut core, amo_word(0b00000, 2, 7, 5, 6)).unwrap();
+        assert_eq!(core.regfile.x[7], 0x7FFF_FFFF);
+        assert_eq!(core.bus.read(0x8000_1000, 8).unwrap(), 0xAAAA_AAAA_8000_0000);
+        exec_word(&mut core, amo_word(0b00001, 2, 7, 5, 6)).unwrap();
+        assert_eq!(core.regfile.x[7], 0xFFFF_FFFF_8000_0000u64 as i64);
+        // NOTE: signed and unsigned min of 1 and 0x8000_0000
+        core.bus.write(0x8000_1000, 4, 0x8000_0000).unwrap();
+        exec_word(&mut core, amo_word(0b10000, 2, 7, 5, 6)).unwrap();
+        assert_eq!(core.bus.read(0x8000_1000, 4).unwrap(), 0x8000_0000);
+        exec_word(&mut core, amo_word(0b11000, 2, 7, 5, 6)).unwrap();
+        assert_eq!(core.bus.read(0x8000_1000, 4).unwrap(), 1);
+        exec_word(&mut core, amo_word(0b00000, 3, 7, 5, 6)).unwrap();
+        assert_eq!(core.regfile.x[7], 0xAAAA_AAAA_0000_0001u64 as i64);
+        assert_eq!(core.bus.read(0x8000_1000, 8).unwrap(), 0xAAAA_AAAA_0000_0002);
+    }
+
+    #[test]
+    fn amo_paged_watch() {
+        // NOTE: the watchpoint is on the va, the amo and the lr hit it under paging
+        let mut core = new_core();
+        map_page(&mut core, 3, 0x1000, 0x8000_3000, PTE_R | PTE_W | PTE_A | PTE_D);
+        core.write_csr(csr::CSR_SATP_ADDR, (8 << 60) | 0x80100).unwrap();
+        core.priv_mode = PrivMode::Supervisor;
+        core.watchpoints_mut().push(Watchpoint {
+            addr: 0x1008,
+            len: 8,
+            kind: WatchKind::Access,
+        });
+        core.regfile.x[5] = 0x1008;
+        core.regfile.x[6] = 3;
+        exec_word(&mut core, amo_word(0b00000, 3, 7, 5, 6)).unwrap();
+        assert_eq!(core.take_watch_hit(), Some((WatchKind::Access, 0x1008)));
+        assert_eq!(core.bus.read(0x8000_3008, 8).unwrap(), 3);
+        exec_word(&mut core, amo_word(LR, 3, 7, 5, 0)).unwrap();
+        assert_eq!(core.take_watch_hit(), Some((WatchKind::Access, 0x1008)));
+        assert_eq!(core.reservation, Some(0x8000_3008));
+        core.regfile.x[5] = 0x1010;
+        exec_word(&mut core, amo_word(0b00000, 3, 7, 5, 6)).unwrap();
+        assert_eq!(core.take_watch_hit(), None);
+    }
+
+    #[test]
+    fn amo_misaligned() {
+        // NOTE: the misaligned amo traps even if the misaligned access is
+        // supported, the lr takes the load cause
+        let mut core = new_core();
+        core.regfile.x[5] = 0x8000_1004;
+        core.regfile.x[7] = 0x33;
+        let res = exec_word(&mut core, amo_word(0b00000, 3, 7, 5, 6));
+        assert_eq!(res, Err(ExceptionType::StoreAddrMisaligned));
+        assert_eq!(core.regfile.x[7], 0x33);
+        core.regfile.x[5] = 0x8000_1002;
+        for (func5, rs2, excpt) in [
+            (0b00001, 6, ExceptionType::StoreAddrMisaligned),
+            (SC, 6, ExceptionType::StoreAddrMisaligned),
+            (LR, 0, ExceptionType::LoadAddrMisaligned),
+        ] {
+            assert_eq!(exec_word(&mut core, amo_word(func5, 2, 7, 5, rs2)), Err(excpt));
+        }
+        assert_eq!(core.reservation, None);
+    }
+}
@@ -1,19 +1,571 @@
+use crate::config::XLen;
+use crate::pmp::{PMP_A_MASK, PMP_A_TOR, PMP_L, PMP_R, PMP_W};
+
+pub const CSR_CAPACITY: usize = 4096;
+
+pub const CSR_FFLAGS_ADDR: u16 = 0x001;
+pub const CSR_FRM_ADDR: u16 = 0x002;
+pub const CSR_FCSR_ADDR: u16 = 0x003;
 pub const CSR_UEPC_ADDR: u16 = 0x41;
+pub const CSR_CYCLE_ADDR: u16 = 0xc00;
+pub const CSR_TIME_ADDR: u16 = 0xc01;
+pub const CSR_INSTRET_ADDR: u16 = 0xc02;
+pub const CSR_HPMCOUNTER3_ADDR: u16 = 0xc03;
+pub const CSR_CYCLEH_ADDR: u16 = 0xc80;
+pub const CSR_TIMEH_ADDR: u16 = 0xc81;
+pub const CSR_INSTRETH_ADDR: u16 = 0xc82;
 pub const CSR_SSTATUS_ADDR: u16 = 0x100;
+pub const CSR_SIE_ADDR: u16 = 0x104;
 pub const CSR_STVEC_ADDR: u16 = 0x105;
+pub const CSR_SCOUNTEREN_ADDR: u16 = 0x106;
 pub const CSR_SSCRATCH_ADDR: u16 = 0x140;
 pub const CSR_SEPC_ADDR: u16 = 0x141;
 pub const CSR_SCAUSE_ADDR: u16 = 0x142;
 pub const CSR_STVAL_ADDR: u16 = 0x143;
+pub const CSR_SIP_ADDR: u16 = 0x144;
 pub const CSR_SATP_ADDR: u16 = 0x180;
 pub const CSR_MSTATUS_ADDR: u16 = 0x300;
+pub const CSR_MISA_ADDR: u16 = 0x301;
 pub const CSR_MEDELEG_ADDR: u16 = 0x302;
+pub const CSR_MIDELEG_ADDR: u16 = 0x303;
 pub const CSR_MIE_ADDR: u16 = 0x304;
 pub const CSR_MTVEC_ADDR: u16 = 0x305;
+pub const CSR_MCOUNTEREN_ADDR: u16 = 0x306;
+pub const CSR_MSTATUSH_ADDR: u16 = 0x310;
+pub const CSR_MCOUNTINHIBIT_ADDR: u16 = 0x320;
+pub const CSR_MHPMEVENT3_ADDR: u16 = 0x323;
 pub const CSR_MSCRATCH_ADDR: u16 = 0x340;
 pub const CSR_MEPC_ADDR: u16 = 0x341;
 pub const CSR_MCAUSE_ADDR: u16 = 0x342;
 pub const CSR_MTVAL_ADDR: u16 = 0x343;
+pub const CSR_MIP_ADDR: u16 = 0x344;
 pub const CSR_PMPCFG0_ADDR: u16 = 0x3a0;
 pub const CSR_PMPADDR0_ADDR: u16 = 0x3b0;
+pub const CSR_MCYCLE_ADDR: u16 = 0xb00;
+pub const CSR_MINSTRET_ADDR: u16 = 0xb02;
+pub const CSR_MHPMCOUNTER3_ADDR: u16 = 0xb03;
+pub const CSR_MCYCLEH_ADDR: u16 = 0xb80;
+pub const CSR_MINSTRETH_ADDR: u16 = 0xb82;
+pub const CSR_MVENDORID_ADDR: u16 = 0xf11;
+pub const CSR_MARCHID_ADDR: u16 = 0xf12;
+pub const CSR_MIMPID_ADDR: u16 = 0xf13;
 pub const CSR_MHARTID_ADDR: u16 = 0xf14;
+
+// NOTE: the names of the csrs the debugger shows
+pub const CSR_NAME: [(u16, &str); 37] = [
+    (CSR_FFLAGS_ADDR, "fflags"),
+    (CSR_FRM_ADDR, "frm"),
+    (CSR_FCSR_ADDR, "fcsr"),
+    (CSR_CYCLE_ADDR, "cycle"),
+    (CSR_TIME_ADDR, "time"),
+    (CSR_INSTRET_ADDR, "instret"),
+    (CSR_SSTATUS_ADDR, "sstatus"),
+    (CSR_SIE_ADDR, "sie"),
+    (CSR_STVEC_ADDR, "stvec"),
+    (CSR_SCOUNTEREN_ADDR, "scounteren"),
+    (CSR_SSCRATCH_ADDR, "sscratch"),
+    (CSR_SEPC_ADDR, "sepc"),
+    (CSR_SCAUSE_ADDR, "scause"),
+    (CSR_STVAL_ADDR, "stval"),
+    (CSR_SIP_ADDR, "sip"),
+    (CSR_SATP_ADDR, "satp"),
+    (CSR_MSTATUS_ADDR, "mstatus"),
+    (CSR_MISA_ADDR, "misa"),
+    (CSR_MEDELEG_ADDR, "medeleg"),
+    (CSR_MIDELEG_ADDR, "mideleg"),
+    (CSR_MIE_ADDR, "mie"),
+    (CSR_MTVEC_ADDR, "mtvec"),
+    (CSR_MCOUNTEREN_ADDR, "mcounteren"),
+    (CSR_MCOUNTINHIBIT_ADDR, "mcountinhibit"),
+    (CSR_MSCRATCH_ADDR, "mscratch"),
+    (CSR_MEPC_ADDR, "mepc"),
+    (CSR_MCAUSE_ADDR, "mcause"),
+    (CSR_MTVAL_ADDR, "mtval"),
+    (CSR_MIP_ADDR, "mip"),
+    (CSR_PMPCFG0_ADDR, "pmpcfg0"),
+    (CSR_PMPADDR0_ADDR, "pmpaddr0"),
+    (CSR_MCYCLE_ADDR, "mcycle"),
+    (CSR_MINSTRET_ADDR, "minstret"),
+    (CSR_MVENDORID_ADDR, "mvendorid"),
+    (CSR_MARCHID_ADDR, "marchid"),
+    (CSR_MIMPID_ADDR, "mimpid"),
+    (CSR_MHARTID_ADDR, "mhartid"),
+];
+
+pub fn csr_addr(name: &str) -> Option<u16> {
+    CSR_NAME.iter().find(|v| v.1 == name).map(|v| v.0)
+}
+
+pub fn csr_name(addr: u16) -> Option<&'static str> {
+    CSR_NAME.iter().find(|v| v.0 == addr).map(|v| v.1)
+}
+
+// mstatus.FS[14:13]: 0 off, 1 initial, 2 clean, 3 dirty
+pub const MSTATUS_FS_MASK: u64 = 0x6000;
+pub const MSTATUS_FS_INITIAL: u64 = 0x2000;
+pub const MSTATUS_FS_DIRTY: u64 = 0x6000;
+
+pub const MSTATUS_SIE: u64 = 1 << 1;
+pub const MSTATUS_MIE: u64 = 1 << 3;
+pub const MSTATUS_SPIE: u64 = 1 << 5;
+pub const MSTATUS_MPIE: u64 = 1 << 7;
+pub const MSTATUS_SPP: u64 = 1 << 8;
+pub const MSTATUS_MPP_MASK: u64 = 0x3 << 11;
+pub const MSTATUS_MPRV: u64 = 1 << 17;
+pub const MSTATUS_SUM: u64 = 1 << 18;
+pub const MSTATUS_MXR: u64 = 1 << 19;
+pub const MSTATUS_TVM: u64 = 1 << 20;
+pub const MSTATUS_TW: u64 = 1 << 21;
+pub const MSTATUS_TSR: u64 = 1 << 22;
+// NOTE: UXL[33:32] and SXL[35:34] are fixed to 64-bit
+const MSTATUS_XL_64: u64 = (2 << 32) | (2 << 34);
+
+// SIE/MIE, SPIE/MPIE, SPP/MPP, FS, MPRV, SUM, MXR, TVM, TW, TSR
+const MSTATUS_WMASK: u64 = 0x7E79AA;
+// SIE, SPIE, SPP, FS, SUM, MXR
+const SSTATUS_WMASK: u64 = 0xC6122;
+// sstatus also shows XS[16:15] and UXL, SD is added by the read
+const SSTATUS_RMASK: u64 = SSTATUS_WMASK | 0x18000 | (0x3 << 32);
+
+pub const MIP_SSIP: u64 = 1 << 1;
+pub const MIP_MSIP: u64 = 1 << 3;
+pub const MIP_STIP: u64 = 1 << 5;
+pub const MIP_MTIP: u64 = 1 << 7;
+pub const MIP_SEIP: u64 = 1 << 9;
+pub const MIP_MEIP: u64 = 1 << 11;
+const MIP_S_MASK: u64 = MIP_SSIP | MIP_STIP | MIP_SEIP;
+const MIP_ALL_MASK: u64 = MIP_S_MASK | MIP_MSIP | MIP_MTIP | MIP_MEIP;
+
+// NOTE: cause 11(ecall from m-mode) can not be delegated, 10 and 14 are reserved
+const MEDELEG_WMASK: u64 = 0xB3FF;
+
+// NOTE: rv64imafdc with s-mode and u-mode
+const MISA_EXT: u64 = (1 << 0) // A
+    | (1 << 2) // C
+    | (1 << 3) // D
+    | (1 << 5) // F
+    | (1 << 8) // I
+    | (1 << 12) // M
+    | (1 << 18) // S
+    | (1 << 20); // U
+
+// NOTE: mcountinhibit.CY, IR and HPM3-31, bit1 is the time which can not be inhibited
+const MCOUNTINHIBIT_WMASK: u64 = 0xFFFF_FFFD;
+const COUNTER_CY: u64 = 1 << 0;
+const COUNTER_IR: u64 = 1 << 2;
+
+// NOTE: side effects the core has to apply after a csr write
+pub enum CsrHook {
+    None,
+    AddrMode, // satp is changed, refresh the translation mode
+    Pmp,      // pmpcfg or pmpaddr is changed, decode the pmp ranges again
+}
+
+#[derive(Clone)]
+pub struct CsrFile {
+    csr: [u64; CSR_CAPACITY],
+    hw_ip: u64, // pending bits driven by the clint and plic
+    xlen: XLen,
+    pmp_num: usize,   // the pmp entries beyond it are hardwired to zero
+    cnt_written: u64, // counters written by the inst in exec, in the mcountinhibit layout
+}
+
+impl CsrFile {
+    pub fn new(xlen: XLen) -> Self {
+        let mut res = CsrFile {
+            csr: [0; CSR_CAPACITY],
+            hw_ip: 0,
+            xlen,
+            pmp_num: 16,
+            cnt_written: 0,
+        };
+        res.reset();
+        res
+    }
+
+    pub fn reset(&mut self) {
+        self.csr = [0; CSR_CAPACITY];
+        self.hw_ip = 0;
+        self.cnt_written = 0;
+        // NOTE: fp unit is on after reset, so rv64gc binaries can run without crt setup
+        self.csr[CSR_MSTATUS_ADDR as usize] = MSTATUS_FS_INITIAL;
+        if let XLen::X64 = self.xlen {
+            self.csr[CSR_MSTATUS_ADDR as usize] |= MSTATUS_XL_64;
+        }
+        self.csr[CSR_MISA_ADDR as usize] = match self.xlen {
+            XLen::X32 => (1 << 30) | MISA_EXT,
+            XLen::X64 => (2 << 62) | MISA_EXT,
+        };
+    }
+
+    // NOTE: raw storage access for the core, no mask and view is applied
+    pub fn get(&self, addr: u16) -> u64 {
+        self.csr[addr as usize]
+    }
+
+    pub fn set(&mut self, addr: u16, val: u64) {
+        self.csr[addr as usize] = val;
+    }
+
+    pub fn set_pmp_num(&mut self, num: usize) {
+        self.pmp_num = num;
+    }
+
+    // NOTE: the cfg byte of the pmp entry, rv64 packs 8 of them in the even pmpcfg
+    pub fn pmp_cfg(&self, idx: usize) -> u64 {
+        let bytes = match self.xlen {
+            XLen::X32 => 4,
+            XLen::X64 => 8,
+        };
+        let addr = CSR_PMPCFG0_ADDR as usize + idx / bytes * (bytes / 4);
+        (self.csr[addr] >> (8 * (idx % bytes))) & 0xFF
+    }
+
+    // NOTE: the locked entry ignores the write, the reserved R=0 W=1 drops W
+    fn write_pmpcfg(&mut self, addr: u16, val: u64) {
+        let bytes = match self.xlen {
+            XLen::X32 => 4,
+            XLen::X64 => 8,
+        };
+        let base = (addr - CSR_PMPCFG0_ADDR) as usize * 4;
+        let mut res = self.csr[addr as usize];
+        for i in 0..bytes {
+            let old = (res >> (8 * i)) & 0xFF;
+            if base + i >= self.pmp_num || old & PMP_L != 0 {
+                continue;
+            }
+            let mut cfg = (val >> (8 * i)) & 0x9F;
+            if cfg & PMP_R == 0 {
+                cfg &= !PMP_W;
+            }
+            res = (res & !(0xFF << (8 * i))) | (cfg << (8 * i));
+        }
+        self.csr[addr as usize] = res;
+    }
+
+    // NOTE: pmpaddr is also locked by the next entry when it is a locked TOR
+    fn write_pmpaddr(&mut self, addr: u16, val: u64) {
+        let idx = (addr - CSR_PMPADDR0_ADDR) as usize;
+        if idx >= self.pmp_num {
+            return;
+        }
+        let next = match idx + 1 < self.pmp_num {
+            true => self.pmp_cfg(idx + 1),
+            false => 0,
+        };
+        if self.pmp_cfg(idx) & PMP_L != 0 || (next & PMP_L != 0 && next & PMP_A_MASK == PMP_A_TOR) {
+            return;
+        }
+        // NOTE: pmpaddr holds the phy addr[55:2] on rv64
+        self.csr[addr as usize] = match self.xlen {
+            XLen::X32 => val,
+            XLen::X64 => val & ((1 << 54) - 1),
+        };
+    }
+
+    // NOTE: SEIP is the OR of the software written bit and the plic output
+    pub fn set_hw_ip(&mut self, val: u64) {
+        self.hw_ip = val & (MIP_MSIP | MIP_MTIP | MIP_MEIP | MIP_SEIP);
+    }
+
+    // NOTE: time is the read-only shadow of the clint mtime
+    pub fn set_time(&mut self, val: u64) {
+        self.csr[CSR_TIME_ADDR as usize] = val;
+    }
+
+    // NOTE: mcycle advances by the cycles of the tick and minstret by the retired
+    // inst, the counter which is inhibited or just written by the inst keeps its value
+    pub fn count(&mut self, cycles: u64, retired: bool) {
+        let skip = self.csr[CSR_MCOUNTINHIBIT_ADDR as usize] | self.cnt_written;
+        self.cnt_written = 0;
+        if skip & COUNTER_CY == 0 {
+            let val = self.csr[CSR_MCYCLE_ADDR as usize].wrapping_add(cycles);
+            self.csr[CSR_MCYCLE_ADDR as usize] = val;
+        }
+        if retired && skip & COUNTER_IR == 0 {
+            let val = self.csr[CSR_MINSTRET_ADDR as usize].wrapping_add(1);
+            self.csr[CSR_MINSTRET_ADDR as usize] = val;
+        }
+    }
+
+    pub fn mip(&self) -> u64 {
+        self.csr[CSR_MIP_ADDR as usize] | self.hw_ip
+    }
+
+    // NOTE: unimplemented csr raises illegal inst when accessed
+    pub fn exists(&self, addr: u16) -> bool {
+        let is_rv32 = matches!(self.xlen, XLen::X32);
+        match addr {
+            CSR_FFLAGS_ADDR | CSR_FRM_ADDR | CSR_FCSR_ADDR => true,
+            // NOTE: cycle, time, instret and hpmcounter3-31, the high halves are rv32 only
+            0xc00..=0xc1f => true,
+            0xc80..=0xc9f => is_rv32,
+            CSR_SSTATUS_ADDR | CSR_SIE_ADDR | CSR_STVEC_ADDR | CSR_SCOUNTEREN_ADDR => true,
+            CSR_SSCRATCH_ADDR | CSR_SEPC_ADDR | CSR_SCAUSE_ADDR | CSR_STVAL_ADDR => true,
+            CSR_SIP_ADDR | CSR_SATP_ADDR => true,
+            CSR_MSTATUS_ADDR | CSR_MISA_ADDR | CSR_MEDELEG_ADDR | CSR_MIDELEG_ADDR => true,
+            CSR_MIE_ADDR | CSR_MTVEC_ADDR | CSR_MCOUNTEREN_ADDR => true,
+            CSR_MSTATUSH_ADDR => is_rv32,
+            CSR_MCOUNTINHIBIT_ADDR | 0x323..=0x33f => true,
+            CSR_MSCRATCH_ADDR | CSR_MEPC_ADDR | CSR_MCAUSE_ADDR | CSR_MTVAL_ADDR => true,
+            CSR_MIP_ADDR => true,
+            // NOTE: rv64 only has the even pmpcfg
+            0x3a0..=0x3af => is_rv32 || addr & 0x1 == 0,
+            0x3b0..=0x3ef => true,
+            // NOTE: 0xb01 and 0xb81 are absent, mtime is not a csr
+            CSR_MCYCLE_ADDR | 0xb02..=0xb1f => true,
+            CSR_MCYCLEH_ADDR | 0xb82..=0xb9f => is_rv32,
+            CSR_MVENDORID_ADDR | CSR_MARCHID_ADDR | CSR_MIMPID_ADDR | CSR_MHARTID_ADDR => true,
+            _ => false,
+        }
+    }
+
+    fn sd_bit(&self) -> u64 {
+        match self.xlen {
+            XLen::X32 => 1 << 31,
+            XLen::X64 => 1 << 63,
+        }
+    }
+
+    fn mstatus(&self) -> u64 {
+        let val = self.csr[CSR_MSTATUS_ADDR as usize];
+        // NOTE: SD summarizes the dirty state of FS(and XS, which is always off)
+        match val & MSTATUS_FS_MASK == MSTATUS_FS_DIRTY {
+            true => val | self.sd_bit(),
+            false => val,
+        }
+    }
+
+    fn xlen_mask(&self) -> u64 {
+        match self.xlen {
+            XLen::X32 => 0xFFFF_FFFF,
+            XLen::X64 => u64::MAX,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u64 {
+        let mideleg = self.csr[CSR_MIDELEG_ADDR as usize];
+        let val = match addr {
+            // NOTE: fflags and frm are the views of fcsr
+            CSR_FFLAGS_ADDR => self.csr[CSR_FCSR_ADDR as usize] & 0x1F,
+            CSR_FRM_ADDR => (self.csr[CSR_FCSR_ADDR as usize] >> 5) & 0x7,
+            // NOTE: user counters are the read-only shadows of the machine ones
+            CSR_TIME_ADDR => self.csr[CSR_TIME_ADDR as usize],
+            CSR_TIMEH_ADDR => self.csr[CSR_TIME_ADDR as usize] >> 32,
+            0xc00..=0xc1f => self.csr[(addr - CSR_CYCLE_ADDR + CSR_MCYCLE_ADDR) as usize],
+            0xc80..=0xc9f => self.csr[(addr - CSR_CYCLEH_ADDR + CSR_MCYCLE_ADDR) as usize] >> 32,
+            0xb80..=0xb9f => self.csr[(addr - CSR_MCYCLEH_ADDR + CSR_MCYCLE_ADDR) as usize] >> 32,
+            // NOTE: s-mode views of the m-mode regs
+            CSR_SSTATUS_ADDR => self.mstatus() & (SSTATUS_RMASK | self.sd_bit()),
+            CSR_SIE_ADDR => self.csr[CSR_MIE_ADDR as usize] & mideleg,
+            CSR_SIP_ADDR => self.mip() & mideleg,
+            CSR_MIP_ADDR => self.mip(),
+            CSR_MSTATUS_ADDR => self.mstatus(),
+            _ => self.csr[addr as usize],
+        };
+        val & self.xlen_mask()
+    }
+
+    // NOTE: fields not in the write mask keep their value(WARL)
+    fn write_masked(&mut self, addr: u16, val: u64, mask: u64) {
+        let old = self.csr[addr as usize];
+        self.csr[addr as usize] = (old & !mask) | (val & mask);
+    }
+
+    pub fn write(&mut self, addr: u16, val: u64) -> CsrHook {
+        let val = val & self.xlen_mask();
+        let mideleg = self.csr[CSR_MIDELEG_ADDR as usize];
+        match addr {
+            CSR_FFLAGS_ADDR => self.write_masked(CSR_FCSR_ADDR, val, 0x1F),
+            CSR_FRM_ADDR => self.write_masked(CSR_FCSR_ADDR, val << 5, 0xE0),
+            CSR_FCSR_ADDR => self.write_masked(CSR_FCSR_ADDR, val, 0xFF),
+            CSR_SSTATUS_ADDR => self.write_masked(CSR_MSTATUS_ADDR, val, SSTATUS_WMASK),
+            CSR_SIE_ADDR => self.write_masked(CSR_MIE_ADDR, val, mideleg),
+            CSR_SIP_ADDR => self.write_masked(CSR_MIP_ADDR, val, MIP_SSIP & mideleg),
+            CSR_MSTATUS_ADDR => {
+                // NOTE: MPP is WARL, the reserved encoding 2 keeps the old mode
+                let mask = match (val & MSTATUS_MPP_MASK) >> 11 {
+                    2 => MSTATUS_WMASK & !MSTATUS_MPP_MASK,
+                    _ => MSTATUS_WMASK,
+                };
+                self.write_masked(CSR_MSTATUS_ADDR, val, mask);
+            }
+            CSR_MEDELEG_ADDR => self.write_masked(addr, val, MEDELEG_WMASK),
+            CSR_MIDELEG_ADDR => self.write_masked(addr, val, MIP_S_MASK),
+            CSR_MIE_ADDR => self.write_masked(addr, val, MIP_ALL_MASK),
+            // NOTE: MSIP/MTIP/MEIP are driven by the clint and plic
+            CSR_MIP_ADDR => self.write_masked(addr, val, MIP_S_MASK),
+            // NOTE: only direct(0) and vectored(1) mode are legal
+            CSR_MTVEC_ADDR | CSR_STVEC_ADDR => self.write_masked(addr, val, !0x2),
+            // NOTE: ialign is 16 with rvc, so bit0 is always zero
+            CSR_MEPC_ADDR | CSR_SEPC_ADDR => self.write_masked(addr, val, !0x1),
+            CSR_MCOUNTEREN_ADDR | CSR_SCOUNTEREN_ADDR => {
+                self.write_masked(addr, val, 0xFFFF_FFFF)
+            }
+            CSR_SATP_ADDR => {
+                // NOTE: write with unsupported mode has no effect at all
+                let legal = match self.xlen {
+                    XLen::X32 => true,
+                    XLen::X64 => matches!(val >> 60, 0 | 8 | 9 | 10),
+                };
+                if !legal {
+                    return CsrHook::None;
+                }
+                self.csr[addr as usize] = val;
+                return CsrHook::AddrMode;
+            }
+            0xb80..=0xb9f => {
+                let low = addr - CSR_MCYCLEH_ADDR + CSR_MCYCLE_ADDR;
+                self.write_masked(low, val << 32, 0xFFFF_FFFF << 32);
+                self.cnt_written |= 1 << (low & 0x1F);
+            }
+            0xb00..=0xb1f => {
+                match self.xlen {
+                    XLen::X32 => self.write_masked(addr, val, 0xFFFF_FFFF),
+                    XLen::X64 => self.csr[addr as usize] = val,
+                }
+                self.cnt_written |= 1 << (addr & 0x1F);
+            }
+            CSR_MCOUNTINHIBIT_ADDR => self.write_masked(addr, val, MCOUNTINHIBIT_WMASK),
+            // NOTE: no event is implemented, mhpmevent is hardwired to zero and
+            // mhpmcounter only changes by the write
+            0x323..=0x33f => {}
+            0x3a0..=0x3af => {
+                self.write_pmpcfg(addr, val);
+                return CsrHook::Pmp;
+            }
+            0x3b0..=0x3ef => {
+                self.write_pmpaddr(addr, val);
+                return CsrHook::Pmp;
+            }
+            // NOTE: misa and mstatush are legal to be read-only
+            CSR_MISA_ADDR | CSR_MSTATUSH_ADDR => {}
+            _ => self.csr[addr as usize] = val,
+        }
+
+        if matches!(addr, CSR_FFLAGS_ADDR | CSR_FRM_ADDR | CSR_FCSR_ADDR) {
+            self.csr[CSR_MSTATUS_ADDR as usize] |= MSTATUS_FS_DIRTY;
+        }
+        CsrHook::None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::XLen;
+    use crate::csr::*;
+
+    #[test]
+    fn csr_sstatus_alias() {
+        let mut dut = CsrFile::new(XLen::X64);
+        dut.write(CSR_SSTATUS_ADDR, u64::MAX);
+        let mstatus = dut.read(CSR_MSTATUS_ADDR);
+        // NOTE: only the s-mode fields are changed through sstatus
+        assert_eq!(mstatus & MSTATUS_MIE, 0);
+        assert_eq!(mstatus & MSTATUS_MPP_MASK, 0);
+        assert_ne!(mstatus & MSTATUS_SIE, 0);
+        assert_ne!(mstatus & MSTATUS_SUM, 0);
+        // NOTE: FS is dirty, so SD is set in both views
+        assert_eq!(mstatus >> 63, 1);
+        assert_eq!(dut.read(CSR_SSTATUS_ADDR) >> 63, 1);
+        dut.write(CSR_MSTATUS_ADDR, MSTATUS_MIE);
+        assert_eq!(dut.read(CSR_SSTATUS_ADDR) & 0xFFFF_FFFF, 0);
+        assert_eq!(dut.read(CSR_MSTATUS_ADDR) >> 32, 0xA);
+    }
+
+    #[test]
+    fn csr_mstatus_warl() {
+        let mut dut = CsrFile::new(XLen::X64);
+        dut.write(CSR_MSTATUS_ADDR, 0x1800);
+        assert_eq!(dut.read(CSR_MSTATUS_ADDR) & MSTATUS_MPP_MASK, 0x1800);
+        // NOTE: reserved mpp encoding keeps the old value
+        dut.write(CSR_MSTATUS_ADDR, 0x1000);
+        assert_eq!(dut.read(CSR_MSTATUS_ADDR) & MSTATUS_MPP_MASK, 0x1800);
+    }
+
+    #[test]
+    fn csr_interrupt_view() {
+        let mut dut = CsrFile::new(XLen::X64);
+        dut.write(CSR_MIE_ADDR, u64::MAX);
+        assert_eq!(dut.read(CSR_MIE_ADDR), 0xAAA);
+        assert_eq!(dut.read(CSR_SIE_ADDR), 0);
+        dut.write(CSR_MIDELEG_ADDR, u64::MAX);
+        assert_eq!(dut.read(CSR_MIDELEG_ADDR), 0x222);
+        assert_eq!(dut.read(CSR_SIE_ADDR), 0x222);
+        dut.write(CSR_MIP_ADDR, u64::MAX);
+        assert_eq!(dut.read(CSR_MIP_ADDR), 0x222);
+        dut.write(CSR_MIP_ADDR, 0);
+        dut.write(CSR_SIP_ADDR, u64::MAX);
+        assert_eq!(dut.read(CSR_SIP_ADDR), MIP_SSIP);
+    }
+
+    #[test]
+    fn csr_misc_warl() {
+        let mut dut = CsrFile::new(XLen::X64);
+        let misa = dut.read(CSR_MISA_ADDR);
+        dut.write(CSR_MISA_ADDR, 0);
+        assert_eq!(dut.read(CSR_MISA_ADDR), misa);
+        assert_eq!(misa >> 62, 2);
+        dut.write(CSR_MEDELEG_ADDR, u64::MAX);
+        assert_eq!(dut.read(CSR_MEDELEG_ADDR) & (1 << 11), 0);
+        dut.write(CSR_MTVEC_ADDR, 0x8000_0003);
+        assert_eq!(dut.read(CSR_MTVEC_ADDR), 0x8000_0001);
+        dut.write(CSR_MEPC_ADDR, 0x8000_0003);
+        assert_eq!(dut.read(CSR_MEPC_ADDR), 0x8000_0002);
+        // NOTE: satp ignores the write with unsupported mode
+        assert!(matches!(dut.write(CSR_SATP_ADDR, 8 << 60 | 0x1), CsrHook::AddrMode));
+        assert!(matches!(dut.write(CSR_SATP_ADDR, 5 << 60), CsrHook::None));
+        assert_eq!(dut.read(CSR_SATP_ADDR), 8 << 60 | 0x1);
+    }
+
+    #[test]
+    fn csr_fp_view() {
+        let mut dut = CsrFile::new(XLen::X64);
+        dut.write(CSR_FCSR_ADDR, 0xFFF);
+        assert_eq!(dut.read(CSR_FCSR_ADDR), 0xFF);
+        dut.write(CSR_FRM_ADDR, 0x2);
+        assert_eq!(dut.read(CSR_FCSR_ADDR), 0x5F);
+        dut.write(CSR_FFLAGS_ADDR, 0x1);
+        assert_eq!(dut.read(CSR_FCSR_ADDR), 0x41);
+        assert_eq!(dut.read(CSR_FRM_ADDR), 0x2);
+    }
+
+    #[test]
+    fn csr_rv32() {
+        let mut dut = CsrFile::new(XLen::X32);
+        assert_eq!(dut.read(CSR_MISA_ADDR) >> 30, 1);
+        dut.write(CSR_MCYCLE_ADDR, u64::MAX);
+        dut.write(CSR_MCYCLEH_ADDR, 0x12);
+        assert_eq!(dut.read(CSR_MCYCLE_ADDR), 0xFFFF_FFFF);
+        assert_eq!(dut.read(CSR_CYCLEH_ADDR), 0x12);
+        assert!(dut.exists(CSR_MSTATUSH_ADDR));
+        assert!(!CsrFile::new(XLen::X64).exists(CSR_MSTATUSH_ADDR));
+    }
+
+    #[test]
+    fn csr_counters() {
+        let mut dut = CsrFile::new(XLen::X64);
+        dut.count(3, true);
+        dut.count(1, false);
+        assert_eq!(dut.read(CSR_CYCLE_ADDR), 4);
+        assert_eq!(dut.read(CSR_INSTRET_ADDR), 1);
+        // NOTE: the counter written by the inst does not count it
+        dut.write(CSR_MINSTRET_ADDR, 100);
+        dut.count(1, true);
+        assert_eq!(dut.read(CSR_INSTRET_ADDR), 100);
+        dut.write(CSR_MCOUNTINHIBIT_ADDR, u64::MAX);
+        assert_eq!(dut.read(CSR_MCOUNTINHIBIT_ADDR), 0xFFFF_FFFD);
+        dut.count(1, true);
+        assert_eq!(dut.read(CSR_MCYCLE_ADDR), 5);
+        assert_eq!(dut.read(CSR_MINSTRET_ADDR), 100);
+        // NOTE: hpmcounter only changes by the write, no event is counted
+        dut.write(CSR_MHPMCOUNTER3_ADDR + 2, 0x55);
+        dut.write(CSR_MHPMEVENT3_ADDR + 2, 0x1);
+        assert_eq!(dut.read(CSR_HPMCOUNTER3_ADDR + 2), 0x55);
+        assert_eq!(dut.read(CSR_MHPMEVENT3_ADDR + 2), 0);
+        dut.set_time(0x1_0000_0002);
+        assert_eq!(dut.read(CSR_TIME_ADDR), 0x1_0000_0002);
+        assert!(dut.exists(CSR_HPMCOUNTER3_ADDR + 28));
+        assert!(!dut.exists(CSR_MCYCLE_ADDR + 1));
+    }
+}
@@ -0,0 +1,140 @@
+use crate::core::Core;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchKind {
+    Write,
+    Read,
+    Access, // read or write
+}
+
+// NOTE: the watchpoint is on the va of the load/store, the fetch and the
+// page walk never hit it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Watchpoint {
+    pub addr: u64,
+    pub len: u64,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    pub fn hit(&self, addr: u64, size: u64, write: bool) -> bool {
+        let kind = match self.kind {
+            WatchKind::Write => write,
+            WatchKind::Read => !write,
+            WatchKind::Access => true,
+        };
+        kind && addr < self.addr.wrapping_add(self.len) && self.addr < addr.wrapping_add(size)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    Step,
+    Breakpoint,
+    Watch(WatchKind, u64), // the kind and the addr of the hit watchpoint
+    Exited(u64),           // a0 at the end inst
+    Interrupt,
+    HistoryBegin, // the reverse run reaches the oldest snapshot
+}
+
+// NOTE: the interrupt is polled every POLL_INSTS insts, the poll is slow
+const POLL_INSTS: u64 = 0x1000;
+
+// NOTE: the breakpoints are not patched into the mem, the pc is compared
+// after each inst, so the sw and the hw ones are the same
+#[derive(Default)]
+pub struct Debugger {
+    pub breakpoints: Vec<u64>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger::default()
+    }
+
+    // NOTE: run until it stops, the breakpoint at the start pc is stepped
+    // over. 'poll' returns true to interrupt the run
+    pub fn resume(
+        &self,
+        core: &mut Core,
+        single: bool,
+        mut poll: impl FnMut() -> bool,
+    ) -> StopReason {
+        let mut cnt = 0u64;
+        loop {
+            if core.check_end() {
+                return StopReason::Exited(core.reg().x[10] as u64);
+            }
+            core.step();
+            if let Some((kind, addr)) = core.take_watch_hit() {
+                return StopReason::Watch(kind, addr);
+            }
+            if single {
+                return StopReason::Step;
+            }
+            if self.breakpoints.contains(&core.pc()) {
+                return StopReason::Breakpoint;
+            }
+            cnt += 1;
+            if cnt & (POLL_INSTS - 1) == 0 && poll() {
+                return StopReason::Interrupt;
+            }
+        }
+    }
+
+    fn stop_of(&self, core: &mut Core) -> Option<StopReason> {
+        if let Some((kind, addr)) = core.take_watch_hit() {
+            return Some(StopReason::Watch(kind, addr));
+        }
+        match self.breakpoints.contains(&core.pc()) {
+            true => Some(StopReason::Breakpoint),
+            false => None,
+        }
+    }
+
+    // NOTE: step back 'steps' insts, or run back to the last stop before the
+    // current inst. Each interval of the history is replayed from its
+    // snapshot to find the stop, the latest interval first
+    pub fn reverse(&self, core: &mut Core, steps: Option<u64>) -> StopReason {
+        let start = match core.history().and_then(|v| v.start()) {
+            Some(v) => v,
+            None => return StopReason::HistoryBegin,
+        };
+        let cur = core.inst_num();
+        if let Some(steps) = steps {
+            return match cur.saturating_sub(steps) {
+                v if v < start => {
+                    core.reverse_to(start);
+                    StopReason::HistoryBegin
+                }
+                v => {
+                    core.reverse_to(v);
+                    StopReason::Step
+                }
+            };
+        }
+        // NOTE: the stops after the insts in (lo, hi] are searched
+        let mut hi = cur.saturating_sub(1);
+        loop {
+            let lo = match core.history().and_then(|v| v.before(hi)) {
+                Some(v) => v,
+                None => {
+                    core.reverse_to(start);
+                    return StopReason::HistoryBegin;
+                }
+            };
+            core.reverse_to(lo);
+            let mut found = None;
+            core.replay(hi, |core| {
+                if let Some(v) = self.stop_of(core) {
+                    found = Some((core.inst_num(), v));
+                }
+            });
+            if let Some((inst_num, reason)) = found {
+                core.reverse_to(inst_num);
+                return reason;
+            }
+            hi = lo;
+        }
+    }
+}
@@ -1,41 +1,55 @@
 use crate::data::Word;
 use crate::inst::Inst;
 use crate::config::XLen;
-use crate::trace;
+use crate::privilege::{Exception, ExceptionType};
 
 pub struct Decode {}
 
 impl Decode {
-    pub fn decode(pc: u64, word: u32, xlen: &XLen) -> Inst {
+    pub fn decode(word: u32, xlen: &XLen) -> Result<Inst, Exception> {
         let inst = Word::new(word);
         let opcode = inst.val(6, 0);
         let func3 = inst.val(14, 12);
         let func7 = inst.val(31, 25);
         let func6 = inst.val(31, 26);
-        match opcode {
+        let is_rv64 = matches!(xlen, XLen::X64);
+        let res = match opcode {
             0x03 => {
-                return match func3 {
+                match func3 {
                     0 => Inst::LB,
                     1 => Inst::LH,
                     2 => Inst::LW,
-                    3 => Inst::LD,
+                    3 if is_rv64 => Inst::LD,
                     4 => Inst::LBU,
                     5 => Inst::LHU,
-                    6 => Inst::LWU,
-                    _ => panic!(),
+                    6 if is_rv64 => Inst::LWU,
+                    _ => return Err(Decode::illegal(word)),
+                }
+            }
+            0x07 => {
+                match func3 {
+                    2 => Inst::FLW,
+                    3 => Inst::FLD,
+                    _ => return Err(Decode::illegal(word)),
                 }
             }
             0x0F => {
-                return match func3 {
+                match func3 {
                     0 => Inst::FENCE,
-                    _ => panic!(),
-                };
+                    1 => Inst::FENCEI,
+                    _ => return Err(Decode::illegal(word)),
+                }
             }
             0x13 => {
-                return match func3 {
+                match func3 {
                     // NOTE: different between 32 and 64 bit ISA
                     0 => Inst::ADDI,
-                    1 => Inst::SLLI,
+                    // NOTE: shamt[5] is reserved on rv32
+                    1 => match xlen {
+                        XLen::X32 if func7 == 0x00 => Inst::SLLI,
+                        XLen::X64 if func6 == 0x00 => Inst::SLLI,
+                        _ => return Err(Decode::illegal(word)),
+                    },
                     2 => Inst::SLTI,
                     3 => Inst::SLTIU,
                     4 => Inst::XORI,
@@ -43,171 +57,678 @@ impl Decode {
                         XLen::X32 => match func7 {
                             0x00 => Inst::SRLI,
                             0x20 => Inst::SRAI,
-                            _ => panic!(),
+                            _ => return Err(Decode::illegal(word)),
                         },
                         XLen::X64 => match func6 {
                             0x00 => Inst::SRLI,
                             0x10 => Inst::SRAI,
-                            _ => panic!(),
+                            _ => return Err(Decode::illegal(word)),
                         },
                     },
                     6 => Inst::ORI,
                     7 => Inst::ANDI,
-                    _ => {
-                        trace::execpt_handle(pc, word);
-                        panic!();
-                    }
-                };
-            }
-            0x17 => {
-                return Inst::AUIPC;
+                    _ => return Err(Decode::illegal(word)),
+                }
             }
-            0x1B => {
-                return match func3 {
+            0x17 => Inst::AUIPC,
+            0x1B if is_rv64 => {
+                match func3 {
                     0 => Inst::ADDIW,
-                    1 => Inst::SLLIW,
+                    1 if func7 == 0x00 => Inst::SLLIW,
                     5 => match func7 {
                         0x00 => Inst::SRLIW,
                         0x20 => Inst::SRAIW,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
-                    _ => panic!(),
+                    _ => return Err(Decode::illegal(word)),
                 }
             }
             0x23 => {
-                return match func3 {
+                match func3 {
                     0 => Inst::SB,
                     1 => Inst::SH,
                     2 => Inst::SW,
-                    3 => Inst::SD,
-                    _ => panic!(),
+                    3 if is_rv64 => Inst::SD,
+                    _ => return Err(Decode::illegal(word)),
+                }
+            }
+            0x27 => {
+                match func3 {
+                    2 => Inst::FSW,
+                    3 => Inst::FSD,
+                    _ => return Err(Decode::illegal(word)),
+                }
+            }
+            0x2F => {
+                let func5 = inst.val(31, 27);
+                let rs2 = inst.val(24, 20);
+                match (func3, xlen) {
+                    (2, _) => match func5 {
+                        0x02 if rs2 == 0 => Inst::LRW,
+                        0x03 => Inst::SCW,
+                        0x01 => Inst::AMOSWAPW,
+                        0x00 => Inst::AMOADDW,
+                        0x04 => Inst::AMOXORW,
+                        0x0C => Inst::AMOANDW,
+                        0x08 => Inst::AMOORW,
+                        0x10 => Inst::AMOMINW,
+                        0x14 => Inst::AMOMAXW,
+                        0x18 => Inst::AMOMINUW,
+                        0x1C => Inst::AMOMAXUW,
+                        _ => return Err(Decode::illegal(word)),
+                    },
+                    (3, XLen::X64) => match func5 {
+                        0x02 if rs2 == 0 => Inst::LRD,
+                        0x03 => Inst::SCD,
+                        0x01 => Inst::AMOSWAPD,
+                        0x00 => Inst::AMOADDD,
+                        0x04 => Inst::AMOXORD,
+                        0x0C => Inst::AMOANDD,
+                        0x08 => Inst::AMOORD,
+                        0x10 => Inst::AMOMIND,
+                        0x14 => Inst::AMOMAXD,
+                        0x18 => Inst::AMOMINUD,
+                        0x1C => Inst::AMOMAXUD,
+                        _ => return Err(Decode::illegal(word)),
+                    },
+                    _ => return Err(Decode::illegal(word)),
                 }
             }
             0x33 => {
-                return match func3 {
+                match func3 {
                     0 => match func7 {
                         0x00 => Inst::ADD,
                         0x01 => Inst::MUL,
                         0x20 => Inst::SUB,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
                     1 => match func7 {
                         0x00 => Inst::SLL,
                         0x01 => Inst::MULH,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
                     2 => match func7 {
                         0x00 => Inst::SLT,
                         0x01 => Inst::MULHSU,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
                     3 => match func7 {
                         0x00 => Inst::SLTU,
                         0x01 => Inst::MULHU,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
                     4 => match func7 {
                         0x00 => Inst::XOR,
                         0x01 => Inst::DIV,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
                     5 => match func7 {
                         0x00 => Inst::SRL,
                         0x01 => Inst::DIVU,
                         0x20 => Inst::SRA,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
                     6 => match func7 {
                         0x00 => Inst::OR,
                         0x01 => Inst::REM,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
                     7 => match func7 {
                         0x00 => Inst::AND,
                         0x01 => Inst::REMU,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
-                    _ => panic!(),
+                    _ => return Err(Decode::illegal(word)),
                 }
             }
-            0x37 => {
-                return Inst::LUI;
-            }
-            0x3B => {
-                return match func3 {
+            0x37 => Inst::LUI,
+            0x3B if is_rv64 => {
+                match func3 {
                     0 => match func7 {
                         0x00 => Inst::ADDW,
                         0x01 => Inst::MULW,
                         0x20 => Inst::SUBW,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
-                    1 => return Inst::SLLW,
-                    4 => return Inst::DIVW,
+                    1 if func7 == 0x00 => Inst::SLLW,
+                    4 if func7 == 0x01 => Inst::DIVW,
                     5 => match func7 {
                         0x00 => Inst::SRLW,
                         0x01 => Inst::DIVUW,
                         0x20 => Inst::SRAW,
-                        _ => panic!(),
+                        _ => return Err(Decode::illegal(word)),
                     },
-                    6 => return Inst::REMW,
-                    7 => return Inst::REMUW,
-                    _ => panic!(),
+                    6 if func7 == 0x01 => Inst::REMW,
+                    7 if func7 == 0x01 => Inst::REMUW,
+                    _ => return Err(Decode::illegal(word)),
+                }
+            }
+            0x43 | 0x47 | 0x4B | 0x4F => {
+                // NOTE: fmt field inst[26:25], 0 is single and 1 is double
+                match (opcode, inst.val(26, 25)) {
+                    (0x43, 0) => Inst::FMADDS,
+                    (0x47, 0) => Inst::FMSUBS,
+                    (0x4B, 0) => Inst::FNMSUBS,
+                    (0x4F, 0) => Inst::FNMADDS,
+                    (0x43, 1) => Inst::FMADDD,
+                    (0x47, 1) => Inst::FMSUBD,
+                    (0x4B, 1) => Inst::FNMSUBD,
+                    (0x4F, 1) => Inst::FNMADDD,
+                    _ => return Err(Decode::illegal(word)),
+                }
+            }
+            0x53 => {
+                let rs2 = inst.val(24, 20);
+                match (func7, func3, rs2) {
+                    (0x00, _, _) => Inst::FADDS,
+                    (0x04, _, _) => Inst::FSUBS,
+                    (0x08, _, _) => Inst::FMULS,
+                    (0x0C, _, _) => Inst::FDIVS,
+                    (0x2C, _, 0) => Inst::FSQRTS,
+                    (0x10, 0, _) => Inst::FSGNJS,
+                    (0x10, 1, _) => Inst::FSGNJNS,
+                    (0x10, 2, _) => Inst::FSGNJXS,
+                    (0x14, 0, _) => Inst::FMINS,
+                    (0x14, 1, _) => Inst::FMAXS,
+                    (0x50, 0, _) => Inst::FLES,
+                    (0x50, 1, _) => Inst::FLTS,
+                    (0x50, 2, _) => Inst::FEQS,
+                    (0x60, _, 0) => Inst::FCVTWS,
+                    (0x60, _, 1) => Inst::FCVTWUS,
+                    (0x60, _, 2) if is_rv64 => Inst::FCVTLS,
+                    (0x60, _, 3) if is_rv64 => Inst::FCVTLUS,
+                    (0x68, _, 0) => Inst::FCVTSW,
+                    (0x68, _, 1) => Inst::FCVTSWU,
+                    (0x68, _, 2) if is_rv64 => Inst::FCVTSL,
+                    (0x68, _, 3) if is_rv64 => Inst::FCVTSLU,
+                    (0x70, 0, 0) => Inst::FMVXW,
+                    (0x70, 1, 0) => Inst::FCLASSS,
+                    (0x78, 0, 0) => Inst::FMVWX,
+                    (0x01, _, _) => Inst::FADDD,
+                    (0x05, _, _) => Inst::FSUBD,
+                    (0x09, _, _) => Inst::FMULD,
+                    (0x0D, _, _) => Inst::FDIVD,
+                    (0x2D, _, 0) => Inst::FSQRTD,
+                    (0x11, 0, _) => Inst::FSGNJD,
+                    (0x11, 1, _) => Inst::FSGNJND,
+                    (0x11, 2, _) => Inst::FSGNJXD,
+                    (0x15, 0, _) => Inst::FMIND,
+                    (0x15, 1, _) => Inst::FMAXD,
+                    (0x20, _, 1) => Inst::FCVTSD,
+                    (0x21, _, 0) => Inst::FCVTDS,
+                    (0x51, 0, _) => Inst::FLED,
+                    (0x51, 1, _) => Inst::FLTD,
+                    (0x51, 2, _) => Inst::FEQD,
+                    (0x61, _, 0) => Inst::FCVTWD,
+                    (0x61, _, 1) => Inst::FCVTWUD,
+                    (0x61, _, 2) if is_rv64 => Inst::FCVTLD,
+                    (0x61, _, 3) if is_rv64 => Inst::FCVTLUD,
+                    (0x69, _, 0) => Inst::FCVTDW,
+                    (0x69, _, 1) => Inst::FCVTDWU,
+                    (0x69, _, 2) if is_rv64 => Inst::FCVTDL,
+                    (0x69, _, 3) if is_rv64 => Inst::FCVTDLU,
+                    (0x71, 0, 0) if is_rv64 => Inst::FMVXD,
+                    (0x71, 1, 0) => Inst::FCLASSD,
+                    (0x79, 0, 0) if is_rv64 => Inst::FMVDX,
+                    _ => return Err(Decode::illegal(word)),
                 }
             }
             0x63 => {
-                return match func3 {
+                match func3 {
                     0 => Inst::BEQ,
                     1 => Inst::BNE,
                     4 => Inst::BLT,
                     5 => Inst::BGE,
                     6 => Inst::BLTU,
                     7 => Inst::BGEU,
-                    _ => {
-                        trace::execpt_handle(pc, word);
-                        panic!();
-                    }
+                    _ => return Err(Decode::illegal(word)),
                 }
             }
-            0x67 => {
-                return Inst::JALR;
-            }
-            0x6F => {
-                return Inst::JAL;
-            }
+            0x67 if func3 == 0 => Inst::JALR,
+            0x6F => Inst::JAL,
             0x73 => {
                 let rs2 = inst.val(24, 20);
-                return match func3 {
+                match func3 {
                     0 => match func7 {
                         0 => match rs2 {
                             0x00 => Inst::ECALL,
                             0x01 => Inst::EBREAK,
                             0x02 => Inst::URET,
-                            _ => panic!(),
+                            _ => return Err(Decode::illegal(word)),
                         },
-                        0x08 => Inst::SRET,
+                        0x08 if rs2 == 0x02 => Inst::SRET,
+                        0x08 if rs2 == 0x05 => Inst::WFI,
                         0x09 => Inst::SFENCEVMA,
-                        0x18 => Inst::MRET,
-                        _ => {
-                            println!(
-                                "Priviledged instruction 0x{:08x} is not supported yet",
-                                word
-                            );
-                            panic!()
-                        }
+                        0x18 if rs2 == 0x02 => Inst::MRET,
+                        _ => return Err(Decode::illegal(word)),
                     },
                     1 => Inst::CSRRW,
                     2 => Inst::CSRRS,
+                    3 => Inst::CSRRC,
                     5 => Inst::CSRRWI,
-                    _ => {
-                        trace::execpt_handle(pc, word);
-                        panic!()
+                    6 => Inst::CSRRSI,
+                    7 => Inst::CSRRCI,
+                    _ => return Err(Decode::illegal(word)),
+                }
+            }
+            _ => return Err(Decode::illegal(word)),
+        };
+        Ok(res)
+    }
+
+    // NOTE: mtval holds the offending inst bits
+    fn illegal(word: u32) -> Exception {
+        Exception {
+            excpt_type: ExceptionType::IllegalInst,
+            addr: word as u64,
+        }
+    }
+}
+
+// NOTE: helpers to build the 32-bit equivalent of a rvc inst
+fn enc_r(func7: u32, rs2: u32, rs1: u32, func3: u32, rd: u32, opcode: u32) -> u32 {
+    (func7 << 25) | (rs2 << 20) | (rs1 << 15) | (func3 << 12) | (rd << 7) | opcode
+}
+
+fn enc_i(imm: i32, rs1: u32, func3: u32, rd: u32, opcode: u32) -> u32 {
+    (((imm as u32) & 0xFFF) << 20) | (rs1 << 15) | (func3 << 12) | (rd << 7) | opcode
+}
+
+fn enc_s(imm: i32, rs2: u32, rs1: u32, func3: u32, opcode: u32) -> u32 {
+    let imm = imm as u32;
+    (((imm >> 5) & 0x7F) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (func3 << 12)
+        | ((imm & 0x1F) << 7)
+        | opcode
+}
+
+fn enc_b(imm: i32, rs2: u32, rs1: u32, func3: u32) -> u32 {
+    let imm = imm as u32;
+    (((imm >> 12) & 0x1) << 31)
+        | (((imm >> 5) & 0x3F) << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (func3 << 12)
+        | (((imm >> 1) & 0xF) << 8)
+        | (((imm >> 11) & 0x1) << 7)
+        | 0x63
+}
+
+fn enc_j(imm: i32, rd: u32) -> u32 {
+    let imm = imm as u32;
+    (((imm >> 20) & 0x1) << 31)
+        | (((imm >> 1) & 0x3FF) << 21)
+        | (((imm >> 11) & 0x1) << 20)
+        | (((imm >> 12) & 0xFF) << 12)
+        | (rd << 7)
+        | 0x6F
+}
+
+// sign extend the low 'bits' bits of val
+fn sext(val: u32, bits: u32) -> i32 {
+    ((val << (32 - bits)) as i32) >> (32 - bits)
+}
+
+impl Decode {
+    // expand a 16-bit rvc inst into the equivalent 32-bit encoding
+    // NOTE: return 0(an illegal inst) for reserved or illegal encodings
+    pub fn expand_compressed(half: u16, xlen: &XLen) -> u32 {
+        let inst = Word::new(half as u32);
+        let op = inst.val(1, 0);
+        let func3 = inst.val(15, 13);
+        let rd = inst.val(11, 7); // also rs1 in the CI/CR format
+        let rs2 = inst.val(6, 2);
+        let rdp = inst.val(4, 2) + 8; // rd'/rs2'
+        let rs1p = inst.val(9, 7) + 8; // rs1'/rd'
+        let is_rv64 = matches!(xlen, XLen::X64);
+
+        // uimm for C.LW/C.SW/C.FLW/C.FSW and C.LD/C.SD/C.FLD/C.FSD
+        let uimm_w = inst.pos(12, 10, 3) | inst.pos(6, 6, 2) | inst.pos(5, 5, 6);
+        let uimm_d = inst.pos(12, 10, 3) | inst.pos(6, 5, 6);
+        // sign-extended imm of the CI format
+        let imm_ci = sext(inst.pos(12, 12, 5) | inst.val(6, 2), 6);
+
+        match (op, func3) {
+            // quadrant 0
+            (0, 0) => {
+                // C.ADDI4SPN
+                let nzuimm = inst.pos(12, 11, 4)
+                    | inst.pos(10, 7, 6)
+                    | inst.pos(6, 6, 2)
+                    | inst.pos(5, 5, 3);
+                match nzuimm {
+                    0 => 0,
+                    _ => enc_i(nzuimm as i32, 2, 0, rdp, 0x13),
+                }
+            }
+            (0, 1) => enc_i(uimm_d as i32, rs1p, 3, rdp, 0x07), // C.FLD
+            (0, 2) => enc_i(uimm_w as i32, rs1p, 2, rdp, 0x03), // C.LW
+            (0, 3) => match is_rv64 {
+                true => enc_i(uimm_d as i32, rs1p, 3, rdp, 0x03),  // C.LD
+                false => enc_i(uimm_w as i32, rs1p, 2, rdp, 0x07), // C.FLW
+            },
+            (0, 5) => enc_s(uimm_d as i32, rdp, rs1p, 3, 0x27), // C.FSD
+            (0, 6) => enc_s(uimm_w as i32, rdp, rs1p, 2, 0x23), // C.SW
+            (0, 7) => match is_rv64 {
+                true => enc_s(uimm_d as i32, rdp, rs1p, 3, 0x23),  // C.SD
+                false => enc_s(uimm_w as i32, rdp, rs1p, 2, 0x27), // C.FSW
+            },
+            // quadrant 1
+            (1, 0) => enc_i(imm_ci, rd, 0, rd, 0x13), // C.ADDI, C.NOP
+            (1, 1) => match is_rv64 {
+                true => match rd {
+                    0 => 0,
+                    _ => enc_i(imm_ci, rd, 0, rd, 0x1B), // C.ADDIW
+                },
+                false => enc_j(Decode::cj_offset(&inst), 1), // C.JAL
+            },
+            (1, 2) => enc_i(imm_ci, 0, 0, rd, 0x13), // C.LI
+            (1, 3) => match rd {
+                2 => {
+                    // C.ADDI16SP
+                    let nzimm = sext(
+                        inst.pos(12, 12, 9)
+                            | inst.pos(6, 6, 4)
+                            | inst.pos(5, 5, 6)
+                            | inst.pos(4, 3, 7)
+                            | inst.pos(2, 2, 5),
+                        10,
+                    );
+                    match nzimm {
+                        0 => 0,
+                        _ => enc_i(nzimm, 2, 0, 2, 0x13),
                     }
-                };
+                }
+                _ => match imm_ci {
+                    0 => 0,
+                    _ => ((imm_ci as u32) << 12) | (rd << 7) | 0x37, // C.LUI
+                },
+            },
+            (1, 4) => {
+                let shamt = inst.pos(12, 12, 5) | inst.val(6, 2);
+                match inst.val(11, 10) {
+                    0 | 1 if !is_rv64 && shamt >= 32 => 0,
+                    0 => enc_i(shamt as i32, rs1p, 5, rs1p, 0x13), // C.SRLI
+                    1 => enc_i((shamt | 0x400) as i32, rs1p, 5, rs1p, 0x13), // C.SRAI
+                    2 => enc_i(imm_ci, rs1p, 7, rs1p, 0x13),       // C.ANDI
+                    _ => match (inst.val(12, 12), inst.val(6, 5)) {
+                        (0, 0) => enc_r(0x20, rdp, rs1p, 0, rs1p, 0x33), // C.SUB
+                        (0, 1) => enc_r(0x00, rdp, rs1p, 4, rs1p, 0x33), // C.XOR
+                        (0, 2) => enc_r(0x00, rdp, rs1p, 6, rs1p, 0x33), // C.OR
+                        (0, 3) => enc_r(0x00, rdp, rs1p, 7, rs1p, 0x33), // C.AND
+                        (1, 0) if is_rv64 => enc_r(0x20, rdp, rs1p, 0, rs1p, 0x3B), // C.SUBW
+                        (1, 1) if is_rv64 => enc_r(0x00, rdp, rs1p, 0, rs1p, 0x3B), // C.ADDW
+                        _ => 0,
+                    },
+                }
+            }
+            (1, 5) => enc_j(Decode::cj_offset(&inst), 0), // C.J
+            (1, 6) | (1, 7) => {
+                // C.BEQZ, C.BNEZ
+                let offset = sext(
+                    inst.pos(12, 12, 8)
+                        | inst.pos(11, 10, 3)
+                        | inst.pos(6, 5, 6)
+                        | inst.pos(4, 3, 1)
+                        | inst.pos(2, 2, 5),
+                    9,
+                );
+                enc_b(offset, 0, rs1p, func3 - 6)
+            }
+            // quadrant 2
+            (2, 0) => {
+                let shamt = inst.pos(12, 12, 5) | inst.val(6, 2);
+                match !is_rv64 && shamt >= 32 {
+                    true => 0,
+                    false => enc_i(shamt as i32, rd, 1, rd, 0x13), // C.SLLI
+                }
+            }
+            (2, 1) => {
+                // C.FLDSP
+                let uimm = inst.pos(12, 12, 5) | inst.pos(6, 5, 3) | inst.pos(4, 2, 6);
+                enc_i(uimm as i32, 2, 3, rd, 0x07)
+            }
+            (2, 2) => {
+                // C.LWSP
+                let uimm = inst.pos(12, 12, 5) | inst.pos(6, 4, 2) | inst.pos(3, 2, 6);
+                match rd {
+                    0 => 0,
+                    _ => enc_i(uimm as i32, 2, 2, rd, 0x03),
+                }
+            }
+            (2, 3) => match is_rv64 {
+                true => {
+                    // C.LDSP
+                    let uimm = inst.pos(12, 12, 5) | inst.pos(6, 5, 3) | inst.pos(4, 2, 6);
+                    match rd {
+                        0 => 0,
+                        _ => enc_i(uimm as i32, 2, 3, rd, 0x03),
+                    }
+                }
+                false => {
+                    // C.FLWSP
+                    let uimm = inst.pos(12, 12, 5) | inst.pos(6, 4, 2) | inst.pos(3, 2, 6);
+                    enc_i(uimm as i32, 2, 2, rd, 0x07)
+                }
+            },
+            (2, 4) => match (inst.val(12, 12), rd, rs2) {
+                (0, 0, 0) => 0,
+                (0, _, 0) => enc_i(0, rd, 0, 0, 0x67),          // C.JR
+                (0, _, _) => enc_r(0x00, rs2, 0, 0, rd, 0x33),  // C.MV
+                (_, 0, 0) => 0x0010_0073,                       // C.EBREAK
+                (_, _, 0) => enc_i(0, rd, 0, 1, 0x67),          // C.JALR
+                (_, _, _) => enc_r(0x00, rs2, rd, 0, rd, 0x33), // C.ADD
+            },
+            (2, 5) => {
+                // C.FSDSP
+                let uimm = inst.pos(12, 10, 3) | inst.pos(9, 7, 6);
+                enc_s(uimm as i32, rs2, 2, 3, 0x27)
+            }
+            (2, 6) => {
+                // C.SWSP
+                let uimm = inst.pos(12, 9, 2) | inst.pos(8, 7, 6);
+                enc_s(uimm as i32, rs2, 2, 2, 0x23)
+            }
+            (2, 7) => match is_rv64 {
+                true => {
+                    // C.SDSP
+                    let uimm = inst.pos(12, 10, 3) | inst.pos(9, 7, 6);
+                    enc_s(uimm as i32, rs2, 2, 3, 0x23)
+                }
+                false => {
+                    // C.FSWSP
+                    let uimm = inst.pos(12, 9, 2) | inst.pos(8, 7, 6);
+                    enc_s(uimm as i32, rs2, 2, 2, 0x27)
+                }
+            },
+            _ => 0,
+        }
+    }
+
+    // offset[11|4|9:8|10|6|7|3:1|5] of C.J and C.JAL
+    fn cj_offset(inst: &Word) -> i32 {
+        sext(
+            inst.pos(12, 12, 11)
+                | inst.pos(11, 11, 4)
+                | inst.pos(10, 9, 8)
+                | inst.pos(8, 8, 10)
+                | inst.pos(7, 7, 6)
+                | inst.pos(6, 6, 7)
+                | inst.pos(5, 3, 1)
+                | inst.pos(2, 2, 5),
+            12,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::decode::Decode;
+    use crate::inst::{get_inst_name, Inst};
+    use crate::privilege::ExceptionType;
+    use crate::config::XLen;
+
+    #[test]
+    fn decode_legal() {
+        // addi a0, a0, 1
+        assert!(matches!(Decode::decode(0x00150513, &XLen::X64), Ok(Inst::ADDI)));
+        // ld a0, 0(sp)
+        assert!(matches!(Decode::decode(0x00013503, &XLen::X64), Ok(Inst::LD)));
+        // fence.i
+        assert!(matches!(Decode::decode(0x0000100f, &XLen::X64), Ok(Inst::FENCEI)));
+        assert!(matches!(Decode::decode(0x0000100f, &XLen::X32), Ok(Inst::FENCEI)));
+    }
+
+    #[test]
+    fn decode_rv32() {
+        // NOTE: the same words decode on rv64 but are illegal on rv32
+        for (word, inst) in [
+            (0x00013503u32, Inst::LD),     // ld a0, 0(sp)
+            (0x00016503, Inst::LWU),       // lwu a0, 0(sp)
+            (0x00a13023, Inst::SD),        // sd a0, 0(sp)
+            (0x0015051b, Inst::ADDIW),     // addiw a0, a0, 1
+            (0x00b5053b, Inst::ADDW),      // addw a0, a0, a1
+            (0x02b5053b, Inst::MULW),      // mulw a0, a0, a1
+            (0x02051513, Inst::SLLI),      // slli a0, a0, 32
+            (0x02055513, Inst::SRLI),      // srli a0, a0, 32
+            (0x42055513, Inst::SRAI),      // srai a0, a0, 32
+        ] {
+            let res = Decode::decode(word, &XLen::X64).unwrap();
+            assert_eq!(get_inst_name(&res), get_inst_name(&inst));
+            match Decode::decode(word, &XLen::X32) {
+                Ok(_) => panic!("{:08x} should be illegal on rv32", word),
+                Err(e) => {
+                    assert!(matches!(e.excpt_type, ExceptionType::IllegalInst));
+                    assert_eq!(e.addr, word as u64);
+                }
+            }
+        }
+        // NOTE: shamt < 32 is legal on rv32, the upper func bits must be 0 on both
+        for (word, inst) in [
+            (0x01f51513u32, Inst::SLLI), // slli a0, a0, 31
+            (0x01f55513, Inst::SRLI),    // srli a0, a0, 31
+            (0x41f55513, Inst::SRAI),    // srai a0, a0, 31
+            (0x00052503, Inst::LW),      // lw a0, 0(a0)
+        ] {
+            let res = Decode::decode(word, &XLen::X32).unwrap();
+            assert_eq!(get_inst_name(&res), get_inst_name(&inst));
+        }
+        for word in [0x04051513u32, 0x80051513, 0x04055513, 0x60055513] {
+            assert!(Decode::decode(word, &XLen::X32).is_err(), "{:08x}", word);
+            assert!(Decode::decode(word, &XLen::X64).is_err(), "{:08x}", word);
+        }
+    }
+
+    #[test]
+    fn decode_illegal() {
+        for word in [0x00000000u32, 0xFFFFFFFF, 0x00007003, 0x30500073, 0x00001067] {
+            match Decode::decode(word, &XLen::X64) {
+                Ok(_) => panic!("{:08x} should be illegal", word),
+                Err(e) => {
+                    assert!(matches!(e.excpt_type, ExceptionType::IllegalInst));
+                    assert_eq!(e.addr, word as u64);
+                }
+            }
+        }
+        // NOTE: amo.d and fmv.x.d are rv64 only
+        assert!(Decode::decode(0x0005352F, &XLen::X32).is_err());
+        assert!(Decode::decode(0xE2050553, &XLen::X32).is_err());
+    }
+
+    // NOTE: the rvc inst and the 32-bit encoding of its expansion, c.mv
+    // expands to add with rs1=x0
+    const RVC_COMMON: [(u16, u32); 29] = [
+        (0x0808, 0x01010513), // c.addi4spn a0, sp, 16
+        (0x2588, 0x0085b507), // c.fld fa0, 8(a1)
+        (0x41c8, 0x0045a503), // c.lw a0, 4(a1)
+        (0xa588, 0x00a5b427), // c.fsd fa0, 8(a1)
+        (0xc1c8, 0x00a5a223), // c.sw a0, 4(a1)
+        (0x0001, 0x00000013), // c.nop
+        (0x157d, 0xfff50513), // c.addi a0, -1
+        (0x5501, 0xfe000513), // c.li a0, -32
+        (0x7139, 0xfc010113), // c.addi16sp sp, -64
+        (0x757d, 0xfffff537), // c.lui a0, 0xfffff
+        (0x8505, 0x40155513), // c.srai a0, 1
+        (0x9979, 0xffe57513), // c.andi a0, -2
+        (0x8d0d, 0x40b50533), // c.sub a0, a1
+        (0x8d2d, 0x00b54533), // c.xor a0, a1
+        (0x8d4d, 0x00b56533), // c.or a0, a1
+        (0x8d6d, 0x00b57533), // c.and a0, a1
+        (0xbffd, 0xfffff06f), // c.j -2
+        (0xc901, 0x00050863), // c.beqz a0, 16
+        (0xf101, 0xf00510e3), // c.bnez a0, -256
+        (0x057e, 0x01f51513), // c.slli a0, 31
+        (0x2522, 0x00813507), // c.fldsp fa0, 8(sp)
+        (0x4512, 0x00412503), // c.lwsp a0, 4(sp)
+        (0x8502, 0x00050067), // c.jr a0
+        (0x852e, 0x00b00533), // c.mv a0, a1
+        (0x9002, 0x00100073), // c.ebreak
+        (0x9502, 0x000500e7), // c.jalr a0
+        (0x952e, 0x00b50533), // c.add a0, a1
+        (0xa42a, 0x00a13427), // c.fsdsp fa0, 8(sp)
+        (0xc22a, 0x00a12223), // c.swsp a0, 4(sp)
+    ];
+
+    // NOTE: c.addi4spn/c.addi16sp/c.lui with a zero imm, c.lwsp/c.jr with
+    // rd=x0, the reserved quadrant 0 func3=4 and the reserved c.subw slot
+    const RVC_RESERVED: [u16; 7] = [0x0000, 0x6101, 0x6501, 0x4002, 0x8002, 0x8000, 0x9d4d];
+
+    #[test]
+    fn expand_compressed() {
+        for xlen in [XLen::X32, XLen::X64] {
+            for (half, word) in RVC_COMMON {
+                assert_eq!(Decode::expand_compressed(half, &xlen), word, "{:04x}", half);
             }
-            _ => {
-                trace::execpt_handle(pc, word);
-                panic!()
+            for half in RVC_RESERVED {
+                assert_eq!(Decode::expand_compressed(half, &xlen), 0, "{:04x}", half);
             }
         }
     }
+
+    #[test]
+    fn expand_compressed_rv64() {
+        for (half, word) in [
+            (0x6588, 0x0085b503), // c.ld a0, 8(a1)
+            (0xe588, 0x00a5b423), // c.sd a0, 8(a1)
+            (0x2505, 0x0015051b), // c.addiw a0, 1
+            (0x9105, 0x02155513), // c.srli a0, 33
+            (0x9d0d, 0x40b5053b), // c.subw a0, a1
+            (0x9d2d, 0x00b5053b), // c.addw a0, a1
+            (0x1506, 0x02151513), // c.slli a0, 33
+            (0x6522, 0x00813503), // c.ldsp a0, 8(sp)
+            (0xe42a, 0x00a13423), // c.sdsp a0, 8(sp)
+        ] {
+            assert_eq!(Decode::expand_compressed(half, &XLen::X64), word, "{:04x}", half);
+        }
+        // NOTE: c.addiw and c.ldsp with rd=x0
+        for half in [0x2001, 0x6002] {
+            assert_eq!(Decode::expand_compressed(half, &XLen::X64), 0, "{:04x}", half);
+        }
+    }
+
+    #[test]
+    fn expand_compressed_rv32() {
+        // NOTE: the c.ld/c.sd/c.ldsp/c.sdsp slots are c.flw/c.fsw/c.flwsp/c.fswsp
+        // and the c.addiw one is c.jal
+        for (half, word) in [
+            (0x6588, 0x0085a507), // c.flw fa0, 8(a1)
+            (0xe588, 0x00a5a427), // c.fsw fa0, 8(a1)
+            (0x61c8, 0x0045a507), // c.flw fa0, 4(a1)
+            (0xe1c8, 0x00a5a227), // c.fsw fa0, 4(a1)
+            (0x6512, 0x00412507), // c.flwsp fa0, 4(sp)
+            (0xe22a, 0x00a12227), // c.fswsp fa0, 4(sp)
+            (0x2ffd, 0x7fe000ef), // c.jal 2046
+            (0x2001, 0x000000ef), // c.jal 0
+            (0x857d, 0x41f55513), // c.srai a0, 31
+        ] {
+            assert_eq!(Decode::expand_compressed(half, &XLen::X32), word, "{:04x}", half);
+        }
+        // NOTE: the shamt[5] of c.srli/c.srai/c.slli is reserved and c.subw/c.addw
+        // are rv64 only
+        for half in [0x9105, 0x8505 | 0x1000, 0x1506, 0x9d0d, 0x9d2d] {
+            assert_eq!(Decode::expand_compressed(half, &XLen::X32), 0, "{:04x}", half);
+        }
+    }
 }
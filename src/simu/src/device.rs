@@ -1,14 +1,61 @@
+use crate::bus::{Bus, MmioDevice};
+use std::cell::RefCell;
 use std::io::{stdout, Write};
+use std::rc::Rc;
 use std::time::Instant;
 
-pub struct Uart {}
+// NOTE: the board address map, every device is one region on the bus
+pub const CLINT_START_ADDR: u64 = 0x0200_0000u64;
+pub const CLINT_ADDR_SIZE: u64 = 0x10000u64;
+pub const PLIC_START_ADDR: u64 = 0x0C00_0000u64;
+pub const PLIC_ADDR_SIZE: u64 = 0x400_0000u64;
+pub const VGA_FRAME_BUF_ADDR_START: u64 = 0xa0000000u64;
+pub const VGA_FRAME_BUF_ADDR_SIZE: u64 = 0x200000u64;
+pub const PERIF_START_ADDR: u64 = 0xa1000000u64;
+const SERIAL_START_OFFSET: u64 = 0x3F8u64;
+const SERIAL_ADDR_SIZE: u64 = 0x8u64;
+const RTC_START_OFFSET: u64 = 0x48u64;
+const RTC_ADDR_SIZE: u64 = 0x08u64;
+const KDB_START_OFFSET: u64 = 0x60u64;
+const KDB_ADDR_SIZE: u64 = 0x02u64; // only device -> core
+const VGA_VGACTL_START_OFFSET: u64 = 0x100u64;
+const VGA_VGACTL_ADDR_SIZE: u64 = 0x8u64; // size reg and sync reg
+
+// NOTE: the bytes [offset, offset + size) of a register, for the narrow access
+fn reg_slice(reg: u64, offset: u64, size: u64) -> u64 {
+    let res = reg >> (8 * offset);
+    match size {
+        8 => res,
+        _ => res & ((1u64 << (8 * size)) - 1),
+    }
+}
+
+// NOTE: write the bytes [offset, offset + size) of a register, the others are kept
+fn reg_merge(reg: u64, offset: u64, size: u64, val: u64) -> u64 {
+    let mask = match size {
+        8 => !0u64,
+        _ => (1u64 << (8 * size)) - 1,
+    } << (8 * offset);
+    (reg & !mask) | ((val << (8 * offset)) & mask)
+}
+
+pub struct Uart {
+    mute: bool, // the replay of the reverse exec does not print again
+}
 
 impl Uart {
     pub fn new() -> Self {
-        Uart {}
+        Uart { mute: false }
+    }
+
+    pub fn set_mute(&mut self, mute: bool) {
+        self.mute = mute;
     }
 
     pub fn out(&self, dat: u8) {
+        if self.mute {
+            return;
+        }
         print!("{}", dat as char);
         match stdout().flush() {
             Ok(()) => {}
@@ -17,11 +64,21 @@ impl Uart {
     }
 }
 
+impl MmioDevice for Uart {
+    fn read(&mut self, _offset: u64, _size: u64) -> u64 {
+        0u64 // NOTE: no rx, the line status always reads zero
+    }
+
+    // NOTE: only the tx reg at offset 0 is implemented
+    fn write(&mut self, offset: u64, _size: u64, val: u64) {
+        if offset == 0 {
+            self.out(val as u8);
+        }
+    }
+}
+
 pub struct Rtc {
     cur_t: Instant,
-    buf: u32,
-    cnt: u8,
-    loading_flag: bool,
     load: u128,
 }
 
@@ -29,34 +86,17 @@ impl Rtc {
     pub fn new() -> Self {
         Rtc {
             cur_t: Instant::now(),
-            buf: 0u32,
-            cnt: 0u8,
-            loading_flag: false,
             load: 0u128,
         }
     }
 
     pub fn reset(&mut self) {
         self.cur_t = Instant::now();
-        self.buf = 0u32;
-        self.cnt = 0u8;
-        self.loading_flag = false;
-    }
-
-    pub fn val(&mut self) -> u8 {
-        if !self.loading_flag {
-            self.buf = self.cur_t.elapsed().as_micros() as u32;
-            self.loading_flag = true;
-            self.cnt = 0;
-        } else {
-            self.cnt += 1;
-            if self.cnt == 3 {
-                self.loading_flag = false;
-            }
-        }
-        // println!("elapsed: {:08x}", self.buf);
-        // println!("cnt: {}, data: {:08x}\n", self.cnt, (self.buf >> (self.cnt * 8)) as u8);
-        (self.buf >> (self.cnt * 8)) as u8
+    }
+
+    // NOTE: elapsed time in us, the low word at offset 0 and the high one at 4
+    pub fn val(&self) -> u64 {
+        self.cur_t.elapsed().as_micros() as u64
     }
 
     pub fn val_set_load(&mut self) {
@@ -72,9 +112,19 @@ impl Rtc {
     }
 }
 
+impl MmioDevice for Rtc {
+    fn read(&mut self, offset: u64, size: u64) -> u64 {
+        reg_slice(self.val(), offset, size)
+    }
+
+    fn write(&mut self, _offset: u64, _size: u64, _val: u64) {}
+}
+
+#[derive(Clone)]
 pub struct Keyboard {
     press: u8,
     code: u8,
+    irq: bool,
 }
 
 impl Keyboard {
@@ -82,36 +132,51 @@ impl Keyboard {
         Keyboard {
             press: 0u8,
             code: 0u8,
+            irq: false,
         }
     }
 
     pub fn reset(&mut self) {
         self.press = 0u8;
         self.code = 0u8;
+        self.irq = false;
     }
 
-    pub fn val(&self, offset: bool) -> u8 {
-        if !offset {
-            self.press
-        } else {
-            self.code
-        }
+    // NOTE: the press flag at offset 0 and the key code at 1
+    pub fn val(&self) -> u64 {
+        (self.press as u64) | ((self.code as u64) << 8)
     }
 
     pub fn det(&mut self, press: u8, code: u8) {
         self.press = press;
         self.code = code;
+        self.irq = true;
         // println!("[det]: pre: {}, code: {}", self.press, self.code);
     }
+
+    // NOTE: one key event raises the irq line once
+    pub fn take_irq(&mut self) -> bool {
+        let res = self.irq;
+        self.irq = false;
+        res
+    }
+}
+
+impl MmioDevice for Keyboard {
+    fn read(&mut self, offset: u64, size: u64) -> u64 {
+        reg_slice(self.val(), offset, size)
+    }
+
+    fn write(&mut self, _offset: u64, _size: u64, _val: u64) {}
 }
 
 const VGA_BUF_SIZE: usize = 200 * 180 * 4;
 
+#[derive(Clone)]
 pub struct Vga {
     width: u16,
     height: u16,
     pub sync: bool,
-    cnt: u8,
     buf: [u8; VGA_BUF_SIZE],
 }
 
@@ -121,36 +186,27 @@ impl Vga {
             width: 192,
             height: 128,
             sync: false,
-            cnt: 0,
             buf: [0; VGA_BUF_SIZE],
         }
     }
 
     pub fn reset(&mut self) {
         self.sync = false;
-        self.cnt = 0;
         self.buf = [0; VGA_BUF_SIZE];
     }
 
-    pub fn val(&self, addr: u64) -> u8 {
-        self.buf[(addr - 0xa0000000u64) as usize]
-    }
-
-    pub fn store(&mut self, addr: u64, val: u8) {
-        self.buf[(addr - 0xa0000000u64) as usize] = val;
-    }
-
-    pub fn set_sync(&mut self, val: u8) -> bool {
-        if self.cnt == 0 {
-            self.sync = val == 1u8;
-            // println!("self.sync: {}", self.sync);
+    // NOTE: the region is larger than the buf, the rest reads zero
+    pub fn val(&self, offset: u64) -> u8 {
+        match self.buf.get(offset as usize) {
+            Some(v) => *v,
+            None => 0u8,
         }
+    }
 
-        self.cnt += 1;
-        if self.cnt == 4 {
-            self.cnt = 0;
+    pub fn store(&mut self, offset: u64, val: u8) {
+        if let Some(v) = self.buf.get_mut(offset as usize) {
+            *v = val;
         }
-        self.sync
     }
 
     pub fn send_dat(&mut self) -> String {
@@ -171,42 +227,505 @@ impl Vga {
     }
 }
 
+impl MmioDevice for Vga {
+    fn read(&mut self, offset: u64, size: u64) -> u64 {
+        let mut res = 0u64;
+        for i in 0..size {
+            res |= (self.val(offset + i) as u64) << (8 * i);
+        }
+        res
+    }
+
+    fn write(&mut self, offset: u64, size: u64, val: u64) {
+        for i in 0..size {
+            self.store(offset + i, (val >> (8 * i)) as u8);
+        }
+    }
+}
+
+// NOTE: the ctl regs of vga live in the perif space, apart from the frame buf
+pub struct VgaCtl {
+    vga: Rc<RefCell<Vga>>,
+}
+
+impl VgaCtl {
+    // NOTE: the size reg(width << 16 | height) at offset 0 and the sync reg at 4
+    fn val(vga: &Vga) -> u64 {
+        ((vga.width as u64) << 16) | (vga.height as u64) | ((vga.sync as u64) << 32)
+    }
+}
+
+impl MmioDevice for VgaCtl {
+    fn read(&mut self, offset: u64, size: u64) -> u64 {
+        reg_slice(VgaCtl::val(&self.vga.borrow()), offset, size)
+    }
+
+    // NOTE: the size reg is read-only, writing 1 to the sync reg sends the frame
+    fn write(&mut self, offset: u64, size: u64, val: u64) {
+        let mut vga = self.vga.borrow_mut();
+        if offset + size > 4 {
+            let reg = reg_merge(VgaCtl::val(&vga), offset, size, val);
+            vga.sync = (reg >> 32) & 0x1 == 0x1;
+        }
+    }
+}
+
+// NOTE: offsets in the standard sifive clint layout
+const CLINT_MSIP_OFFSET: u64 = 0x0;
+const CLINT_MTIMECMP_OFFSET: u64 = 0x4000;
+const CLINT_MTIME_OFFSET: u64 = 0xBFF8;
+
+#[derive(Clone)]
 pub struct Clint {
-    _mtime: u64,
-    _mtimecmp: u64,
+    msip: u32,
+    mtime: u64,
+    mtimecmp: u64,
 }
 
 impl Clint {
+    // NOTE: mtimecmp resets to the max so no timer irq is pending before the
+    // software programs it
     pub fn new() -> Self {
         Clint {
-            _mtime: 0u64,
-            _mtimecmp: 0u64,
+            msip: 0u32,
+            mtime: 0u64,
+            mtimecmp: u64::MAX,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.msip = 0u32;
+        self.mtime = 0u64;
+        self.mtimecmp = u64::MAX;
+    }
+
+    // NOTE: mtime advances once per tick, so the timer is deterministic
+    pub fn update_time(&mut self) {
+        self.mtime = self.mtime.wrapping_add(1);
+    }
+
+    pub fn mtime(&self) -> u64 {
+        self.mtime
+    }
+
+    pub fn msip(&self) -> bool {
+        self.msip & 0x1 == 0x1
+    }
+
+    pub fn mtip(&self) -> bool {
+        self.mtime >= self.mtimecmp
+    }
+}
+
+impl MmioDevice for Clint {
+    fn read(&mut self, offset: u64, size: u64) -> u64 {
+        match offset {
+            CLINT_MSIP_OFFSET..=0x3 => reg_slice(self.msip as u64, offset, size),
+            CLINT_MTIMECMP_OFFSET..=0x4007 => {
+                reg_slice(self.mtimecmp, offset - CLINT_MTIMECMP_OFFSET, size)
+            }
+            CLINT_MTIME_OFFSET..=0xBFFF => reg_slice(self.mtime, offset - CLINT_MTIME_OFFSET, size),
+            _ => 0u64,
+        }
+    }
+
+    // NOTE: the 32-bit halves of mtimecmp and mtime can be written apart for rv32
+    fn write(&mut self, offset: u64, size: u64, val: u64) {
+        match offset {
+            // NOTE: only bit0 of msip is implemented
+            CLINT_MSIP_OFFSET => self.msip = (val & 0x1) as u32,
+            CLINT_MTIMECMP_OFFSET..=0x4007 => {
+                self.mtimecmp =
+                    reg_merge(self.mtimecmp, offset - CLINT_MTIMECMP_OFFSET, size, val)
+            }
+            CLINT_MTIME_OFFSET..=0xBFFF => {
+                self.mtime = reg_merge(self.mtime, offset - CLINT_MTIME_OFFSET, size, val)
+            }
+            _ => {}
         }
     }
+}
+
+pub const PLIC_SRC_NUM: usize = 32; // src 0 is reserved
+pub const PLIC_CTX_NUM: usize = 2; // hart0 m-mode and s-mode
+pub const PLIC_CTX_MMODE: usize = 0;
+pub const PLIC_CTX_SMODE: usize = 1;
+pub const KDB_IRQ: usize = 1;
+
+// NOTE: offsets in the standard sifive plic layout
+const PLIC_PRIORITY_OFFSET: u64 = 0x0;
+const PLIC_PENDING_OFFSET: u64 = 0x1000;
+const PLIC_ENABLE_OFFSET: u64 = 0x2000;
+const PLIC_ENABLE_STRIDE: u64 = 0x80;
+const PLIC_CONTEXT_OFFSET: u64 = 0x20_0000;
+const PLIC_CONTEXT_STRIDE: u64 = 0x1000;
 
-    pub fn update_time() {}
+#[derive(Clone)]
+pub struct Plic {
+    priority: [u32; PLIC_SRC_NUM],
+    pending: u32,
+    in_service: u32, // claimed but not completed, the gateway holds new requests
+    enable: [u32; PLIC_CTX_NUM],
+    threshold: [u32; PLIC_CTX_NUM],
 }
 
+impl Plic {
+    pub fn new() -> Self {
+        Plic {
+            priority: [0u32; PLIC_SRC_NUM],
+            pending: 0u32,
+            in_service: 0u32,
+            enable: [0u32; PLIC_CTX_NUM],
+            threshold: [0u32; PLIC_CTX_NUM],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.priority = [0u32; PLIC_SRC_NUM];
+        self.pending = 0u32;
+        self.in_service = 0u32;
+        self.enable = [0u32; PLIC_CTX_NUM];
+        self.threshold = [0u32; PLIC_CTX_NUM];
+    }
+
+    // NOTE: the gateway latches the request until it is claimed and completed
+    pub fn raise(&mut self, src: usize) {
+        if src == 0 || src >= PLIC_SRC_NUM || (self.in_service >> src) & 1 == 1 {
+            return;
+        }
+        self.pending |= 1 << src;
+    }
+
+    // NOTE: highest priority pending and enabled src, the lower id wins the tie
+    fn best(&self, ctx: usize) -> (usize, u32) {
+        let cand = self.pending & self.enable[ctx];
+        let mut res = (0usize, 0u32);
+        for src in 1..PLIC_SRC_NUM {
+            if (cand >> src) & 1 == 1 && self.priority[src] > res.1 {
+                res = (src, self.priority[src]);
+            }
+        }
+        res
+    }
+
+    // NOTE: the irq output of ctx, which drives mip.MEIP/SEIP
+    pub fn irq(&self, ctx: usize) -> bool {
+        let (src, prio) = self.best(ctx);
+        src != 0 && prio > self.threshold[ctx]
+    }
+
+    pub fn claim(&mut self, ctx: usize) -> u32 {
+        let (src, _) = self.best(ctx);
+        if src != 0 {
+            self.pending &= !(1 << src);
+            self.in_service |= 1 << src;
+        }
+        src as u32
+    }
+
+    pub fn complete(&mut self, ctx: usize, src: u32) {
+        let src = src as usize;
+        if src < PLIC_SRC_NUM && (self.enable[ctx] >> src) & 1 == 1 {
+            self.in_service &= !(1 << src);
+        }
+    }
+
+    fn reg(&mut self, offset: u64) -> Option<&mut u32> {
+        if offset < PLIC_PENDING_OFFSET {
+            let src = ((offset - PLIC_PRIORITY_OFFSET) / 4) as usize;
+            return match src > 0 && src < PLIC_SRC_NUM {
+                true => Some(&mut self.priority[src]),
+                false => None,
+            };
+        }
+        if offset == PLIC_PENDING_OFFSET {
+            return Some(&mut self.pending);
+        }
+        if (PLIC_ENABLE_OFFSET..PLIC_CONTEXT_OFFSET).contains(&offset) {
+            let ctx = ((offset - PLIC_ENABLE_OFFSET) / PLIC_ENABLE_STRIDE) as usize;
+            let aligned = (offset - PLIC_ENABLE_OFFSET) & (PLIC_ENABLE_STRIDE - 1) == 0;
+            return match ctx < PLIC_CTX_NUM && aligned {
+                true => Some(&mut self.enable[ctx]),
+                false => None,
+            };
+        }
+        let ctx = ((offset - PLIC_CONTEXT_OFFSET) / PLIC_CONTEXT_STRIDE) as usize;
+        match (ctx < PLIC_CTX_NUM, (offset - PLIC_CONTEXT_OFFSET) % PLIC_CONTEXT_STRIDE) {
+            (true, 0) => Some(&mut self.threshold[ctx]),
+            _ => None,
+        }
+    }
+
+    fn claim_ctx(offset: u64) -> Option<usize> {
+        if offset < PLIC_CONTEXT_OFFSET {
+            return None;
+        }
+        let ctx = ((offset - PLIC_CONTEXT_OFFSET) / PLIC_CONTEXT_STRIDE) as usize;
+        match ctx < PLIC_CTX_NUM && (offset - PLIC_CONTEXT_OFFSET) % PLIC_CONTEXT_STRIDE == 4 {
+            true => Some(ctx),
+            false => None,
+        }
+    }
+
+    // NOTE: reading the claim reg claims the irq
+    fn read_reg(&mut self, offset: u64) -> u32 {
+        if let Some(ctx) = Plic::claim_ctx(offset) {
+            return self.claim(ctx);
+        }
+        match self.reg(offset) {
+            Some(v) => *v,
+            None => 0u32,
+        }
+    }
+
+    // NOTE: writing the claim reg completes the irq
+    fn write_reg(&mut self, offset: u64, val: u32) {
+        if let Some(ctx) = Plic::claim_ctx(offset) {
+            self.complete(ctx, val);
+            return;
+        }
+        if offset == PLIC_PENDING_OFFSET {
+            return; // NOTE: pending bits are read-only
+        }
+        // NOTE: 3-bit priority and threshold, src 0 can not be enabled
+        let mask = match offset {
+            PLIC_ENABLE_OFFSET..=0x1F_FFFF => !0x1u32,
+            _ => 0x7u32,
+        };
+        if let Some(v) = self.reg(offset) {
+            *v = val & mask;
+        }
+    }
+}
+
+// NOTE: the regs are 32-bit, a 64-bit access covers two regs and a narrow
+// one accesses a part of the reg
+impl MmioDevice for Plic {
+    fn read(&mut self, offset: u64, size: u64) -> u64 {
+        match size {
+            8 => (self.read_reg(offset) as u64) | ((self.read_reg(offset + 4) as u64) << 32),
+            _ => reg_slice(self.read_reg(offset & !0x3) as u64, offset & 0x3, size),
+        }
+    }
+
+    fn write(&mut self, offset: u64, size: u64, val: u64) {
+        match size {
+            8 => {
+                self.write_reg(offset, val as u32);
+                self.write_reg(offset + 4, (val >> 32) as u32);
+            }
+            _ => {
+                let base = offset & !0x3;
+                let old = match self.reg(base) {
+                    Some(v) => *v as u64,
+                    None => 0u64,
+                };
+                self.write_reg(base, reg_merge(old, offset & 0x3, size, val) as u32);
+            }
+        }
+    }
+}
+
+// NOTE: the device state of the snapshot for the reverse exec. The rtc follows
+// the wall clock and the uart output is not taken back
+#[derive(Clone)]
+pub struct DevState {
+    kdb: Keyboard,
+    vga: Box<Vga>,
+    clint: Clint,
+    plic: Plic,
+}
+
+// NOTE: the core keeps a handle of every device for the side channels(irq,
+// kdb input, vga output), the loads and stores go through the bus
 pub struct Device {
-    pub uart: Uart,
-    pub rtc: Rtc,
-    pub kdb: Keyboard,
-    pub vga: Vga,
+    pub uart: Rc<RefCell<Uart>>,
+    pub rtc: Rc<RefCell<Rtc>>,
+    pub kdb: Rc<RefCell<Keyboard>>,
+    pub vga: Rc<RefCell<Vga>>,
+    pub clint: Rc<RefCell<Clint>>,
+    pub plic: Rc<RefCell<Plic>>,
 }
 
 impl Device {
     pub fn new() -> Self {
         Device {
-            uart: Uart::new(),
-            rtc: Rtc::new(),
-            kdb: Keyboard::new(),
-            vga: Vga::new(),
+            uart: Rc::new(RefCell::new(Uart::new())),
+            rtc: Rc::new(RefCell::new(Rtc::new())),
+            kdb: Rc::new(RefCell::new(Keyboard::new())),
+            vga: Rc::new(RefCell::new(Vga::new())),
+            clint: Rc::new(RefCell::new(Clint::new())),
+            plic: Rc::new(RefCell::new(Plic::new())),
         }
     }
 
+    // NOTE: map all devices of the board into the bus
+    pub fn attach(&self, bus: &mut Bus) -> Result<(), String> {
+        let vgactl = Rc::new(RefCell::new(VgaCtl {
+            vga: self.vga.clone(),
+        }));
+        bus.add_device("clint", CLINT_START_ADDR, CLINT_ADDR_SIZE, self.clint.clone())?;
+        bus.add_device("plic", PLIC_START_ADDR, PLIC_ADDR_SIZE, self.plic.clone())?;
+        bus.add_device(
+            "vga",
+            VGA_FRAME_BUF_ADDR_START,
+            VGA_FRAME_BUF_ADDR_SIZE,
+            self.vga.clone(),
+        )?;
+        bus.add_device(
+            "serial",
+            PERIF_START_ADDR + SERIAL_START_OFFSET,
+            SERIAL_ADDR_SIZE,
+            self.uart.clone(),
+        )?;
+        bus.add_device(
+            "rtc",
+            PERIF_START_ADDR + RTC_START_OFFSET,
+            RTC_ADDR_SIZE,
+            self.rtc.clone(),
+        )?;
+        bus.add_device(
+            "kdb",
+            PERIF_START_ADDR + KDB_START_OFFSET,
+            KDB_ADDR_SIZE,
+            self.kdb.clone(),
+        )?;
+        bus.add_device(
+            "vgactl",
+            PERIF_START_ADDR + VGA_VGACTL_START_OFFSET,
+            VGA_VGACTL_ADDR_SIZE,
+            vgactl,
+        )
+    }
+
     pub fn reset(&mut self) {
-        self.rtc.reset();
-        self.kdb.reset();
-        self.vga.reset();
+        self.rtc.borrow_mut().reset();
+        self.kdb.borrow_mut().reset();
+        self.vga.borrow_mut().reset();
+        self.clint.borrow_mut().reset();
+        self.plic.borrow_mut().reset();
+    }
+
+    pub fn save(&self) -> DevState {
+        DevState {
+            kdb: self.kdb.borrow().clone(),
+            vga: Box::new(self.vga.borrow().clone()),
+            clint: self.clint.borrow().clone(),
+            plic: self.plic.borrow().clone(),
+        }
+    }
+
+    pub fn restore(&self, state: &DevState) {
+        *self.kdb.borrow_mut() = state.kdb.clone();
+        *self.vga.borrow_mut() = (*state.vga).clone();
+        *self.clint.borrow_mut() = state.clint.clone();
+        *self.plic.borrow_mut() = state.plic.clone();
+    }
+
+    // NOTE: forward the irq lines of devices to the plic
+    pub fn update_irq(&mut self) {
+        if self.kdb.borrow_mut().take_irq() {
+            self.plic.borrow_mut().raise(KDB_IRQ);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bus::MmioDevice;
+    use crate::device::{Clint, Device, Plic, VgaCtl, KDB_IRQ, PLIC_CTX_MMODE, PLIC_CTX_SMODE};
+
+    fn store_word(plic: &mut Plic, offset: u64, val: u32) {
+        plic.write(offset, 4, val as u64);
+    }
+
+    fn load_word(plic: &mut Plic, offset: u64) -> u32 {
+        plic.read(offset, 4) as u32
+    }
+
+    #[test]
+    fn plic_claim_complete() {
+        let mut dut = Plic::new();
+        store_word(&mut dut, 0x4 * 3, 2); // priority of src 3
+        store_word(&mut dut, 0x4 * 5, 5); // priority of src 5
+        store_word(&mut dut, 0x2000, 0xFFFF_FFFF);
+        dut.raise(3);
+        dut.raise(5);
+        assert_eq!(load_word(&mut dut, 0x1000), (1 << 3) | (1 << 5));
+        assert!(dut.irq(PLIC_CTX_MMODE));
+        assert!(!dut.irq(PLIC_CTX_SMODE));
+        // NOTE: higher priority is claimed first
+        assert_eq!(load_word(&mut dut, 0x20_0004), 5);
+        // NOTE: the gateway holds the src until completion
+        dut.raise(5);
+        assert_eq!(load_word(&mut dut, 0x1000), 1 << 3);
+        // NOTE: threshold masks the irq output but not the claim
+        store_word(&mut dut, 0x20_0000, 2);
+        assert!(!dut.irq(PLIC_CTX_MMODE));
+        assert_eq!(load_word(&mut dut, 0x20_0004), 3);
+        assert_eq!(load_word(&mut dut, 0x20_0004), 0);
+        store_word(&mut dut, 0x20_0004, 5);
+        dut.raise(5);
+        assert_eq!(load_word(&mut dut, 0x1000), 1 << 5);
+        assert_eq!(load_word(&mut dut, 0x2000), 0xFFFF_FFFE);
+        assert_eq!(load_word(&mut dut, 0x4 * 5), 5);
+    }
+
+    #[test]
+    fn plic_kdb_irq() {
+        let mut dut = Device::new();
+        store_word(&mut dut.plic.borrow_mut(), 0x4 * KDB_IRQ as u64, 1);
+        store_word(&mut dut.plic.borrow_mut(), 0x2080, 1 << KDB_IRQ);
+        dut.kdb.borrow_mut().det(1, 0x1c);
+        dut.update_irq();
+        assert!(dut.plic.borrow().irq(PLIC_CTX_SMODE));
+        assert_eq!(load_word(&mut dut.plic.borrow_mut(), 0x20_1004), KDB_IRQ as u32);
+        assert!(!dut.plic.borrow().irq(PLIC_CTX_SMODE));
+    }
+
+    #[test]
+    fn clint_width() {
+        let mut dut = Clint::new();
+        dut.write(0x4000, 8, 0x1122_3344_5566_7788);
+        assert_eq!(dut.read(0x4000, 8), 0x1122_3344_5566_7788);
+        // NOTE: rv32 accesses the halves apart
+        dut.write(0x4004, 4, 0xAABB_CCDD);
+        assert_eq!(dut.read(0x4000, 4), 0x5566_7788);
+        assert_eq!(dut.read(0x4004, 4), 0xAABB_CCDD);
+        assert_eq!(dut.read(0x4006, 2), 0xAABB);
+        dut.write(0x0, 4, 0x3);
+        assert!(dut.msip());
+    }
+
+    #[test]
+    fn clint_reset() {
+        let mut dut = Clint::new();
+        assert_eq!(dut.read(0x4000, 8), u64::MAX);
+        assert!(!dut.mtip());
+        dut.write(0x4000, 8, 2);
+        dut.update_time();
+        assert!(!dut.mtip());
+        dut.update_time();
+        assert!(dut.mtip());
+        dut.reset();
+        assert_eq!((dut.read(0x4000, 8), dut.mtime()), (u64::MAX, 0));
+        assert!(!dut.mtip());
+    }
+
+    #[test]
+    fn vga_sync() {
+        let dev = Device::new();
+        let mut dut = VgaCtl {
+            vga: dev.vga.clone(),
+        };
+        assert_eq!(dut.read(0x0, 4), (192 << 16) | 128);
+        dut.write(0x4, 4, 1);
+        assert!(dev.vga.borrow().sync);
+        dev.vga.borrow_mut().send_dat();
+        assert!(!dev.vga.borrow().sync);
+        // NOTE: the size reg is read-only
+        dut.write(0x0, 4, 0);
+        assert_eq!(dut.read(0x0, 2), 128);
+        assert!(!dev.vga.borrow().sync);
     }
 }
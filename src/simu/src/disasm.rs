@@ -0,0 +1,364 @@
+use crate::config::XLen;
+use crate::core::Core;
+use crate::csr;
+use crate::data::Word;
+use crate::decode::Decode;
+use crate::elf::SymTab;
+use crate::inst::{get_inst_name, Inst, InstType};
+use crate::regfile::{FRegfile, Regfile};
+use std::collections::HashMap;
+
+// NOTE: objdump-style syntax, like 'addi a0,a1,-5' or 'j 80000010 <main+0x8>'.
+// The common pseudo insts are shown as objdump does, rvc inst is shown as its
+// 32-bit equivalent
+pub struct Disasm {
+    xname: Vec<String>,
+    fname: Vec<String>,
+}
+
+const RM_NAME: [&str; 8] = ["rne", "rtz", "rdn", "rup", "rmm", "", "", "dyn"];
+
+fn names(alias: &HashMap<String, u8>) -> Vec<String> {
+    let mut res = vec![String::new(); 32];
+    // NOTE: 'fp' is the other alias of 's0', objdump prints 's0'
+    for (name, idx) in alias.iter().filter(|v| v.0 != "fp") {
+        res[*idx as usize] = name.clone();
+    }
+    res
+}
+
+// NOTE: 'FCVT_W_S' is 'fcvt.w.s'
+fn mnemonic(inst: &Inst) -> String {
+    get_inst_name(inst).to_lowercase().replace('_', ".")
+}
+
+fn join(name: &str, ops: &str) -> String {
+    match ops.is_empty() {
+        true => name.to_string(),
+        false => format!("{:<7} {}", name, ops),
+    }
+}
+
+fn fence_set(val: u32) -> String {
+    "iorw"
+        .chars()
+        .enumerate()
+        .filter(|(i, _)| (val >> (3 - i)) & 1 == 1)
+        .map(|(_, c)| c)
+        .collect()
+}
+
+impl Disasm {
+    pub fn new(reg: &Regfile, freg: &FRegfile) -> Self {
+        Disasm {
+            xname: names(&reg.alias),
+            fname: names(&freg.alias),
+        }
+    }
+
+    // NOTE: 'raw' is the fetched bits, the halfword for rvc inst
+    pub fn disasm(&self, pc: u64, raw: u32, xlen: &XLen, symtab: &SymTab) -> String {
+        let word = match raw & 0x3 {
+            0x3 => raw,
+            _ => Decode::expand_compressed(raw as u16, xlen),
+        };
+        match Decode::decode(word, xlen) {
+            Ok(inst) if word != 0 => self.format(pc, word, &inst, symtab),
+            _ => match (raw & 0x3, raw) {
+                (_, 0) => "unimp".to_string(),
+                (0x3, _) => format!(".4byte  {:#010x}", raw),
+                _ => format!(".2byte  {:#06x}", raw),
+            },
+        }
+    }
+
+    // NOTE: 'word' is the decoded 32-bit inst
+    pub fn format(&self, pc: u64, word: u32, inst: &Inst, symtab: &SymTab) -> String {
+        let w = Word::new(word);
+        let (rd, rs1, rs2, rs3) = (
+            w.val(11, 7) as usize,
+            w.val(19, 15) as usize,
+            w.val(24, 20) as usize,
+            w.val(31, 27) as usize,
+        );
+        let x = |i: usize| self.xname[i].as_str();
+        let f = |i: usize| self.fname[i].as_str();
+        let imm_i = Core::imm_ext_gen(InstType::I, word);
+        let imm_s = Core::imm_ext_gen(InstType::S, word);
+        let target = |t: InstType| {
+            let addr = pc.wrapping_add(Core::imm_ext_gen(t, word) as u64);
+            match symtab.lookup(addr) {
+                Some((name, 0)) => format!("{:x} <{}>", addr, name),
+                Some((name, off)) => format!("{:x} <{}+{:#x}>", addr, name, off),
+                None => format!("{:x}", addr),
+            }
+        };
+        let csr = {
+            let addr = w.val(31, 20) as u16;
+            match csr::csr_name(addr) {
+                Some(v) => v.to_string(),
+                None => format!("{:#x}", addr),
+            }
+        };
+        // NOTE: the rounding mode is only shown when it is not dynamic
+        let rm = match w.val(14, 12) {
+            7 => String::new(),
+            v => format!(",{}", RM_NAME[v as usize]),
+        };
+        let name = mnemonic(inst);
+        match inst {
+            Inst::LUI | Inst::AUIPC => join(&name, &format!("{},{:#x}", x(rd), word >> 12)),
+            Inst::JAL => match rd {
+                0 => join("j", &target(InstType::J)),
+                1 => join("jal", &target(InstType::J)),
+                _ => join("jal", &format!("{},{}", x(rd), target(InstType::J))),
+            },
+            Inst::JALR => match (rd, rs1, imm_i) {
+                (0, 1, 0) => "ret".to_string(),
+                (0, _, 0) => join("jr", x(rs1)),
+                (1, _, 0) => join("jalr", x(rs1)),
+                _ => join("jalr", &format!("{},{}({})", x(rd), imm_i, x(rs1))),
+            },
+            Inst::BEQ | Inst::BNE | Inst::BLT | Inst::BGE | Inst::BLTU | Inst::BGEU => {
+                let t = target(InstType::B);
+                match (inst, rs1, rs2) {
+                    (Inst::BEQ, _, 0) => join("beqz", &format!("{},{}", x(rs1), t)),
+                    (Inst::BNE, _, 0) => join("bnez", &format!("{},{}", x(rs1), t)),
+                    (Inst::BLT, _, 0) => join("bltz", &format!("{},{}", x(rs1), t)),
+                    (Inst::BGE, _, 0) => join("bgez", &format!("{},{}", x(rs1), t)),
+                    (Inst::BLT, 0, _) => join("bgtz", &format!("{},{}", x(rs2), t)),
+                    (Inst::BGE, 0, _) => join("blez", &format!("{},{}", x(rs2), t)),
+                    _ => join(&name, &format!("{},{},{}", x(rs1), x(rs2), t)),
+                }
+            }
+            Inst::LB | Inst::LH | Inst::LW | Inst::LBU | Inst::LHU | Inst::LWU | Inst::LD => {
+                join(&name, &format!("{},{}({})", x(rd), imm_i, x(rs1)))
+            }
+            Inst::FLW | Inst::FLD => join(&name, &format!("{},{}({})", f(rd), imm_i, x(rs1))),
+            Inst::SB | Inst::SH | Inst::SW | Inst::SD => {
+                join(&name, &format!("{},{}({})", x(rs2), imm_s, x(rs1)))
+            }
+            Inst::FSW | Inst::FSD => join(&name, &format!("{},{}({})", f(rs2), imm_s, x(rs1))),
+            Inst::ADDI if word == 0x13 => "nop".to_string(),
+            Inst::ADDI if rs1 == 0 => join("li", &format!("{},{}", x(rd), imm_i)),
+            Inst::ADDI if imm_i == 0 => join("mv", &format!("{},{}", x(rd), x(rs1))),
+            Inst::ADDIW if imm_i == 0 => join("sext.w", &format!("{},{}", x(rd), x(rs1))),
+            Inst::XORI if imm_i == -1 => join("not", &format!("{},{}", x(rd), x(rs1))),
+            Inst::SLTIU if imm_i == 1 => join("seqz", &format!("{},{}", x(rd), x(rs1))),
+            Inst::ADDI
+            | Inst::SLTI
+            | Inst::SLTIU
+            | Inst::XORI
+            | Inst::ORI
+            | Inst::ANDI
+            | Inst::ADDIW => join(&name, &format!("{},{},{}", x(rd), x(rs1), imm_i)),
+            Inst::SLLI | Inst::SRLI | Inst::SRAI => {
+                join(&name, &format!("{},{},{:#x}", x(rd), x(rs1), w.val(25, 20)))
+            }
+            Inst::SLLIW | Inst::SRLIW | Inst::SRAIW => {
+                join(&name, &format!("{},{},{:#x}", x(rd), x(rs1), w.val(24, 20)))
+            }
+            // NOTE: c.mv is expanded to 'add rd,zero,rs2'
+            Inst::ADD if rs1 == 0 => join("mv", &format!("{},{}", x(rd), x(rs2))),
+            Inst::SUB if rs1 == 0 => join("neg", &format!("{},{}", x(rd), x(rs2))),
+            Inst::SUBW if rs1 == 0 => join("negw", &format!("{},{}", x(rd), x(rs2))),
+            Inst::SLTU if rs1 == 0 => join("snez", &format!("{},{}", x(rd), x(rs2))),
+            Inst::ADD
+            | Inst::SUB
+            | Inst::SLL
+            | Inst::SLT
+            | Inst::SLTU
+            | Inst::XOR
+            | Inst::SRL
+            | Inst::SRA
+            | Inst::OR
+            | Inst::AND
+            | Inst::MUL
+            | Inst::MULH
+            | Inst::MULHSU
+            | Inst::MULHU
+            | Inst::DIV
+            | Inst::DIVU
+            | Inst::REM
+            | Inst::REMU
+            | Inst::ADDW
+            | Inst::SUBW
+            | Inst::SLLW
+            | Inst::SRLW
+            | Inst::SRAW
+            | Inst::MULW
+            | Inst::DIVW
+            | Inst::DIVUW
+            | Inst::REMW
+            | Inst::REMUW => join(&name, &format!("{},{},{}", x(rd), x(rs1), x(rs2))),
+            Inst::CSRRS if rs1 == 0 => join("csrr", &format!("{},{}", x(rd), csr)),
+            Inst::CSRRW if rd == 0 => join("csrw", &format!("{},{}", csr, x(rs1))),
+            Inst::CSRRS if rd == 0 => join("csrs", &format!("{},{}", csr, x(rs1))),
+            Inst::CSRRC if rd == 0 => join("csrc", &format!("{},{}", csr, x(rs1))),
+            Inst::CSRRWI if rd == 0 => join("csrwi", &format!("{},{}", csr, rs1)),
+            Inst::CSRRSI if rd == 0 => join("csrsi", &format!("{},{}", csr, rs1)),
+            Inst::CSRRCI if rd == 0 => join("csrci", &format!("{},{}", csr, rs1)),
+            Inst::CSRRW | Inst::CSRRS | Inst::CSRRC => {
+                join(&name, &format!("{},{},{}", x(rd), csr, x(rs1)))
+            }
+            Inst::CSRRWI | Inst::CSRRSI | Inst::CSRRCI => {
+                join(&name, &format!("{},{},{}", x(rd), csr, rs1))
+            }
+            Inst::FENCE => match (w.val(27, 24), w.val(23, 20)) {
+                (0xf, 0xf) => name,
+                (pred, succ) => join(&name, &format!("{},{}", fence_set(pred), fence_set(succ))),
+            },
+            Inst::SFENCEVMA => match (rs1, rs2) {
+                (0, 0) => name,
+                (_, 0) => join(&name, x(rs1)),
+                _ => join(&name, &format!("{},{}", x(rs1), x(rs2))),
+            },
+            Inst::LRW
+            | Inst::SCW
+            | Inst::AMOSWAPW
+            | Inst::AMOADDW
+            | Inst::AMOXORW
+            | Inst::AMOANDW
+            | Inst::AMOORW
+            | Inst::AMOMINW
+            | Inst::AMOMAXW
+            | Inst::AMOMINUW
+            | Inst::AMOMAXUW
+            | Inst::LRD
+            | Inst::SCD
+            | Inst::AMOSWAPD
+            | Inst::AMOADDD
+            | Inst::AMOXORD
+            | Inst::AMOANDD
+            | Inst::AMOORD
+            | Inst::AMOMIND
+            | Inst::AMOMAXD
+            | Inst::AMOMINUD
+            | Inst::AMOMAXUD => {
+                let name = match w.val(26, 25) {
+                    0b11 => name + ".aqrl",
+                    0b10 => name + ".aq",
+                    0b01 => name + ".rl",
+                    _ => name,
+                };
+                match inst {
+                    Inst::LRW | Inst::LRD => join(&name, &format!("{},({})", x(rd), x(rs1))),
+                    _ => join(&name, &format!("{},{},({})", x(rd), x(rs2), x(rs1))),
+                }
+            }
+            Inst::FMADDS
+            | Inst::FMSUBS
+            | Inst::FNMSUBS
+            | Inst::FNMADDS
+            | Inst::FMADDD
+            | Inst::FMSUBD
+            | Inst::FNMSUBD
+            | Inst::FNMADDD => join(
+                &name,
+                &format!("{},{},{},{}{}", f(rd), f(rs1), f(rs2), f(rs3), rm),
+            ),
+            Inst::FADDS
+            | Inst::FSUBS
+            | Inst::FMULS
+            | Inst::FDIVS
+            | Inst::FADDD
+            | Inst::FSUBD
+            | Inst::FMULD
+            | Inst::FDIVD => join(&name, &format!("{},{},{}{}", f(rd), f(rs1), f(rs2), rm)),
+            Inst::FSGNJS | Inst::FSGNJD if rs1 == rs2 => join(
+                &name.replace("fsgnj", "fmv"),
+                &format!("{},{}", f(rd), f(rs1)),
+            ),
+            Inst::FSGNJNS | Inst::FSGNJND if rs1 == rs2 => join(
+                &name.replace("fsgnjn", "fneg"),
+                &format!("{},{}", f(rd), f(rs1)),
+            ),
+            Inst::FSGNJXS | Inst::FSGNJXD if rs1 == rs2 => join(
+                &name.replace("fsgnjx", "fabs"),
+                &format!("{},{}", f(rd), f(rs1)),
+            ),
+            Inst::FSGNJS
+            | Inst::FSGNJNS
+            | Inst::FSGNJXS
+            | Inst::FMINS
+            | Inst::FMAXS
+            | Inst::FSGNJD
+            | Inst::FSGNJND
+            | Inst::FSGNJXD
+            | Inst::FMIND
+            | Inst::FMAXD => join(&name, &format!("{},{},{}", f(rd), f(rs1), f(rs2))),
+            Inst::FEQS | Inst::FLTS | Inst::FLES | Inst::FEQD | Inst::FLTD | Inst::FLED => {
+                join(&name, &format!("{},{},{}", x(rd), f(rs1), f(rs2)))
+            }
+            Inst::FCLASSS | Inst::FCLASSD | Inst::FMVXW | Inst::FMVXD => {
+                join(&name, &format!("{},{}", x(rd), f(rs1)))
+            }
+            Inst::FMVWX | Inst::FMVDX => join(&name, &format!("{},{}", f(rd), x(rs1))),
+            Inst::FCVTWS
+            | Inst::FCVTWUS
+            | Inst::FCVTLS
+            | Inst::FCVTLUS
+            | Inst::FCVTWD
+            | Inst::FCVTWUD
+            | Inst::FCVTLD
+            | Inst::FCVTLUD => join(&name, &format!("{},{}{}", x(rd), f(rs1), rm)),
+            Inst::FCVTSW
+            | Inst::FCVTSWU
+            | Inst::FCVTSL
+            | Inst::FCVTSLU
+            | Inst::FCVTDW
+            | Inst::FCVTDWU
+            | Inst::FCVTDL
+            | Inst::FCVTDLU => join(&name, &format!("{},{}{}", f(rd), x(rs1), rm)),
+            Inst::FSQRTS | Inst::FSQRTD | Inst::FCVTSD | Inst::FCVTDS => {
+                join(&name, &format!("{},{}{}", f(rd), f(rs1), rm))
+            }
+            // NOTE: ecall, ebreak, fence.i, the xret and wfi
+            _ => name,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::XLen;
+    use crate::disasm::Disasm;
+    use crate::elf::{SymTab, Symbol};
+    use crate::regfile::{FRegfile, Regfile};
+
+    #[test]
+    fn disasm_syntax() {
+        let dis = Disasm::new(&Regfile::new(), &FRegfile::new());
+        let symtab = SymTab::new();
+        let xlen = XLen::X64;
+        let asm = |pc: u64, raw: u32| dis.disasm(pc, raw, &xlen, &symtab);
+        assert_eq!(asm(0x8000_0000, 0x00500513), "li      a0,5");
+        assert_eq!(asm(0x8000_0000, 0xffb58513), "addi    a0,a1,-5");
+        assert_eq!(asm(0x8000_0000, 0x00058513), "mv      a0,a1");
+        assert_eq!(asm(0x8000_0000, 0x00008067), "ret");
+        assert_eq!(asm(0x8000_0000, 0x8082), "ret");
+        assert_eq!(asm(0x8000_0000, 0x840a), "mv      s0,sp");
+        assert_eq!(asm(0x8000_0000, 0x0100006f), "j       80000010");
+        assert_eq!(asm(0x8000_0010, 0xfe0518e3), "bnez    a0,80000000");
+        assert_eq!(asm(0x8000_0000, 0x00813403), "ld      s0,8(sp)");
+        assert_eq!(asm(0x8000_0000, 0x00a12423), "sw      a0,8(sp)");
+        assert_eq!(asm(0x8000_0000, 0x800005b7), "lui     a1,0x80000");
+        assert_eq!(asm(0x8000_0000, 0x34102573), "csrr    a0,mepc");
+        assert_eq!(asm(0x8000_0000, 0x04b5252f), "amoadd.w.aq a0,a1,(a0)");
+        assert_eq!(asm(0x8000_0000, 0x00b57553), "fadd.s  fa0,fa0,fa1");
+        assert_eq!(asm(0x8000_0000, 0xc0051553), "fcvt.w.s a0,fa0,rtz");
+        assert_eq!(asm(0x8000_0000, 0x30200073), "mret");
+        assert_eq!(asm(0x8000_0000, 0x0000100f), "fence.i");
+        assert_eq!(asm(0x8000_0000, 0x0000), "unimp");
+        assert_eq!(asm(0x8000_0000, 0xffffffff), ".4byte  0xffffffff");
+        let symtab = SymTab::from_vec(vec![Symbol {
+            name: "main".to_string(),
+            addr: 0x8000_0008,
+            size: 0x10,
+            is_func: true,
+        }]);
+        let asm = |pc: u64, raw: u32| dis.disasm(pc, raw, &xlen, &symtab);
+        assert_eq!(asm(0x8000_0000, 0x0100006f), "j       80000010 <main+0x8>");
+        assert_eq!(asm(0x8000_0000, 0x008000ef), "jal     80000008 <main>");
+    }
+}
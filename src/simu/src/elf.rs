@@ -0,0 +1,164 @@
+use object::elf::{FileHeader32, FileHeader64, PT_LOAD};
+use object::read::elf::{FileHeader, ProgramHeader};
+use object::{Endianness, Object, ObjectSymbol, SymbolKind};
+use std::error::Error;
+
+pub struct Symbol {
+    pub name: String,
+    pub addr: u64,
+    pub size: u64,
+    pub is_func: bool,
+}
+
+// NOTE: symbols are sorted by addr for the lookup
+pub struct SymTab {
+    syms: Vec<Symbol>,
+}
+
+impl SymTab {
+    pub fn new() -> Self {
+        SymTab { syms: vec![] }
+    }
+
+    pub fn from_vec(mut syms: Vec<Symbol>) -> Self {
+        syms.sort_by_key(|v| v.addr);
+        SymTab { syms }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.syms.is_empty()
+    }
+
+    pub fn find(&self, name: &str) -> Option<u64> {
+        self.syms.iter().find(|v| v.name == name).map(|v| v.addr)
+    }
+
+    // NOTE: the func which starts exactly at addr
+    pub fn func_at(&self, addr: u64) -> Option<&str> {
+        self.syms
+            .iter()
+            .find(|v| v.is_func && v.addr == addr)
+            .map(|v| v.name.as_str())
+    }
+
+    // NOTE: the symbol which covers addr and the offset in it, a zero-sized
+    // symbol only matches its own addr
+    pub fn lookup(&self, addr: u64) -> Option<(&str, u64)> {
+        let idx = self.syms.partition_point(|v| v.addr <= addr);
+        self.syms[..idx]
+            .iter()
+            .rev()
+            .find(|v| addr - v.addr < v.size.max(1))
+            .map(|v| (v.name.as_str(), addr - v.addr))
+    }
+}
+
+pub struct Segment {
+    pub paddr: u64,
+    pub data: Vec<u8>,
+    pub mem_size: u64, // the part beyond data is bss and zero filled
+}
+
+pub struct ElfFile {
+    pub entry: u64,
+    pub segments: Vec<Segment>,
+    pub symtab: SymTab,
+}
+
+fn parse_segments<Elf: FileHeader<Endian = Endianness>>(
+    data: &[u8],
+) -> Result<(u64, Vec<Segment>), Box<dyn Error>> {
+    let header = Elf::parse(data)?;
+    let endian = header.endian()?;
+    let mut segments = vec![];
+    for ph in header.program_headers(endian, data)? {
+        if ph.p_type(endian) != PT_LOAD {
+            continue;
+        }
+        let seg_data = match ph.data(endian, data) {
+            Ok(v) => v,
+            Err(()) => return Err("invalid PT_LOAD segment range".into()),
+        };
+        segments.push(Segment {
+            paddr: ph.p_paddr(endian).into(),
+            data: seg_data.to_vec(),
+            mem_size: ph.p_memsz(endian).into(),
+        });
+    }
+    Ok((header.e_entry(endian).into(), segments))
+}
+
+pub fn is_elf(data: &[u8]) -> bool {
+    data.starts_with(b"\x7fELF")
+}
+
+pub fn parse(data: &[u8]) -> Result<ElfFile, Box<dyn Error>> {
+    let obj_file = object::File::parse(data)?;
+    let (entry, segments) = match obj_file.is_64() {
+        true => parse_segments::<FileHeader64<Endianness>>(data)?,
+        false => parse_segments::<FileHeader32<Endianness>>(data)?,
+    };
+
+    let mut syms = vec![];
+    for v in obj_file.symbols() {
+        let is_func = match v.kind() {
+            SymbolKind::Text => true,
+            SymbolKind::Data | SymbolKind::Unknown => false,
+            _ => continue,
+        };
+        match v.name() {
+            Ok(name) if !name.is_empty() && v.is_definition() => syms.push(Symbol {
+                name: name.to_string(),
+                addr: v.address(),
+                size: v.size(),
+                is_func,
+            }),
+            _ => {}
+        }
+    }
+
+    Ok(ElfFile {
+        entry,
+        segments,
+        symtab: SymTab::from_vec(syms),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::elf::{is_elf, parse, SymTab, Symbol};
+
+    fn sym(name: &str, addr: u64, size: u64, is_func: bool) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            addr,
+            size,
+            is_func,
+        }
+    }
+
+    #[test]
+    fn symtab_lookup() {
+        let dut = SymTab::from_vec(vec![
+            sym("main", 0x8000_0100, 0x40, true),
+            sym("_start", 0x8000_0000, 0x10, true),
+            sym("buf", 0x8000_1000, 0x0, false),
+        ]);
+        assert_eq!(dut.find("main"), Some(0x8000_0100));
+        assert_eq!(dut.func_at(0x8000_0000), Some("_start"));
+        assert_eq!(dut.func_at(0x8000_1000), None);
+        assert_eq!(dut.lookup(0x8000_0108), Some(("main", 0x8)));
+        assert_eq!(dut.lookup(0x8000_0140), None);
+        assert_eq!(dut.lookup(0x8000_1000), Some(("buf", 0x0)));
+        assert_eq!(dut.lookup(0x10), None);
+        // NOTE: the symbol at the top of the addr space does not overflow
+        let dut = SymTab::from_vec(vec![sym("top", u64::MAX - 0x7, 0x10, false)]);
+        assert_eq!(dut.lookup(u64::MAX), Some(("top", 0x7)));
+    }
+
+    #[test]
+    fn elf_reject() {
+        assert!(!is_elf(&[0x97, 0x02, 0x00, 0x00]));
+        assert!(parse(&[0x7f, b'E', b'L', b'F']).is_err());
+    }
+}
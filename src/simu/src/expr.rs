@@ -0,0 +1,333 @@
+use crate::config::XLen;
+use crate::core::Core;
+use crate::csr;
+
+// NOTE: C-like expressions of the debugger, like '$a0 + 8' or
+// '*(u64*)($sp + 16)'. A bare name is an elf symbol, '$name' is a x/f reg,
+// the pc or a csr. The arithmetic wraps in u64
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Num(u64),
+    Reg(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+const OPS: [&str; 22] = [
+    "<<", ">>", "==", "!=", "<=", ">=", "&&", "||", "+", "-", "*", "/", "%", "&", "|", "^", "~",
+    "!", "<", ">", "(", ")",
+];
+
+fn lex(val: &str) -> Result<Vec<Tok>, String> {
+    let mut res = vec![];
+    let mut rest = val.trim_start();
+    while !rest.is_empty() {
+        let word_len = |s: &str| {
+            s.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.'))
+                .unwrap_or(s.len())
+        };
+        let first = rest.chars().next().unwrap();
+        let len = if first.is_ascii_digit() {
+            let len = word_len(rest);
+            let num = &rest[..len];
+            let num = match num.strip_prefix("0x") {
+                Some(v) => u64::from_str_radix(v, 16),
+                None => num.parse::<u64>(),
+            };
+            match num {
+                Ok(v) => res.push(Tok::Num(v)),
+                Err(_e) => return Err(format!("invalid number '{}'", &rest[..len])),
+            }
+            len
+        } else if first == '$' {
+            let len = word_len(&rest[1..]) + 1;
+            res.push(Tok::Reg(rest[1..len].to_string()));
+            len
+        } else if first.is_ascii_alphabetic() || first == '_' || first == '.' {
+            let len = word_len(rest);
+            res.push(Tok::Ident(rest[..len].to_string()));
+            len
+        } else {
+            match OPS.iter().find(|v| rest.starts_with(**v)) {
+                Some(v) => {
+                    res.push(Tok::Op(v));
+                    v.len()
+                }
+                None => return Err(format!("unexpected '{}'", first)),
+            }
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(res)
+}
+
+// NOTE: the size in bytes and the sign of the cast type
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Ty {
+    size: u64,
+    signed: bool,
+}
+
+fn type_of(name: &str) -> Option<Ty> {
+    let (signed, bits) = match name.split_at(1) {
+        ("u", v) => (false, v),
+        ("i", v) => (true, v),
+        _ => return None,
+    };
+    match bits {
+        "8" | "16" | "32" | "64" => Some(Ty {
+            size: bits.parse::<u64>().unwrap() / 8,
+            signed,
+        }),
+        _ => None,
+    }
+}
+
+fn extend(val: u64, ty: Ty) -> u64 {
+    let shift = 64 - ty.size * 8;
+    match ty.signed {
+        true => (((val << shift) as i64) >> shift) as u64,
+        false => (val << shift) >> shift,
+    }
+}
+
+// NOTE: 'lval' is the addr and the size of the deref, the watchpoint on the
+// mem uses it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Value {
+    pub val: u64,
+    pub lval: Option<(u64, u64)>,
+    ptr: Option<Ty>, // the pointee of the pointer cast
+}
+
+impl Value {
+    fn new(val: u64) -> Self {
+        Value {
+            val,
+            lval: None,
+            ptr: None,
+        }
+    }
+}
+
+struct Parser<'a> {
+    toks: Vec<Tok>,
+    pos: usize,
+    core: &'a mut Core,
+}
+
+// NOTE: the binding power of the binary op, as in C
+fn prec(op: &str) -> Option<u8> {
+    match op {
+        "||" => Some(1),
+        "&&" => Some(2),
+        "|" => Some(3),
+        "^" => Some(4),
+        "&" => Some(5),
+        "==" | "!=" => Some(6),
+        "<" | ">" | "<=" | ">=" => Some(7),
+        "<<" | ">>" => Some(8),
+        "+" | "-" => Some(9),
+        "*" | "/" | "%" => Some(10),
+        _ => None,
+    }
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let res = self.toks.get(self.pos).cloned();
+        self.pos += 1;
+        res
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        match self.next() {
+            Some(Tok::Op(v)) if v == op => Ok(()),
+            _ => Err(format!("expect '{}'", op)),
+        }
+    }
+
+    fn binary(&mut self, min_prec: u8) -> Result<Value, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Tok::Op(op)) = self.peek() {
+            let op = *op;
+            let p = match prec(op) {
+                Some(p) if p >= min_prec => p,
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.binary(p + 1)?.val;
+            let l = lhs.val;
+            let val = match op {
+                "||" => (l != 0 || rhs != 0) as u64,
+                "&&" => (l != 0 && rhs != 0) as u64,
+                "|" => l | rhs,
+                "^" => l ^ rhs,
+                "&" => l & rhs,
+                "==" => (l == rhs) as u64,
+                "!=" => (l != rhs) as u64,
+                "<" => (l < rhs) as u64,
+                ">" => (l > rhs) as u64,
+                "<=" => (l <= rhs) as u64,
+                ">=" => (l >= rhs) as u64,
+                "<<" => l.wrapping_shl(rhs as u32),
+                ">>" => l.wrapping_shr(rhs as u32),
+                "+" => l.wrapping_add(rhs),
+                "-" => l.wrapping_sub(rhs),
+                "*" => l.wrapping_mul(rhs),
+                _ if rhs == 0 => return Err("division by zero".to_string()),
+                "/" => l / rhs,
+                _ => l % rhs,
+            };
+            lhs = Value::new(val);
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Value, String> {
+        match self.next() {
+            Some(Tok::Op("-")) => Ok(Value::new(self.unary()?.val.wrapping_neg())),
+            Some(Tok::Op("~")) => Ok(Value::new(!self.unary()?.val)),
+            Some(Tok::Op("!")) => Ok(Value::new((self.unary()?.val == 0) as u64)),
+            Some(Tok::Op("*")) => {
+                let ptr = self.unary()?;
+                let ty = ptr.ptr.unwrap_or(Ty {
+                    size: match self.core.xlen() {
+                        XLen::X32 => 4,
+                        XLen::X64 => 8,
+                    },
+                    signed: false,
+                });
+                let val = self.read_mem(ptr.val, ty.size)?;
+                Ok(Value {
+                    val: extend(val, ty),
+                    lval: Some((ptr.val, ty.size)),
+                    ptr: None,
+                })
+            }
+            Some(Tok::Op("(")) => {
+                // NOTE: '(u32)' truncates, '(u32*)' makes the pointer of the deref
+                if let Some(Tok::Ident(name)) = self.peek() {
+                    if let Some(ty) = type_of(name) {
+                        self.pos += 1;
+                        let is_ptr = self.peek() == Some(&Tok::Op("*"));
+                        if is_ptr {
+                            self.pos += 1;
+                        }
+                        self.expect(")")?;
+                        let val = self.unary()?.val;
+                        return Ok(match is_ptr {
+                            true => Value {
+                                val,
+                                lval: None,
+                                ptr: Some(ty),
+                            },
+                            false => Value::new(extend(val, ty)),
+                        });
+                    }
+                }
+                let res = self.binary(1)?;
+                self.expect(")")?;
+                Ok(res)
+            }
+            Some(Tok::Num(v)) => Ok(Value::new(v)),
+            Some(Tok::Reg(name)) => match self.reg(&name) {
+                Some(v) => Ok(Value::new(v)),
+                None => Err(format!("unknown reg '${}'", name)),
+            },
+            Some(Tok::Ident(name)) => match self.core.symtab().find(&name) {
+                Some(v) => Ok(Value::new(v)),
+                None => Err(format!("unknown symbol '{}'", name)),
+            },
+            Some(Tok::Op(v)) => Err(format!("unexpected '{}'", v)),
+            None => Err("unexpected end".to_string()),
+        }
+    }
+
+    fn reg(&self, name: &str) -> Option<u64> {
+        let core = &self.core;
+        if name == "pc" {
+            return Some(core.pc());
+        }
+        if let Some(idx) = core.reg().alias.get(name) {
+            return Some(core.reg().x[*idx as usize] as u64);
+        }
+        if let Some(idx) = core.freg().alias.get(name) {
+            return Some(core.freg().f[*idx as usize]);
+        }
+        let idx = |v: &str| v.parse::<usize>().ok().filter(|v| *v < 32);
+        if let Some(idx) = name.strip_prefix('x').and_then(idx) {
+            return Some(core.reg().x[idx] as u64);
+        }
+        if let Some(idx) = name.strip_prefix('f').and_then(idx) {
+            return Some(core.freg().f[idx]);
+        }
+        csr::csr_addr(name).and_then(|v| core.debug_csr(v))
+    }
+
+    fn read_mem(&mut self, addr: u64, size: u64) -> Result<u64, String> {
+        match self.core.debug_read(addr, size as usize) {
+            Some(data) => {
+                let mut buf = [0u8; 8];
+                buf[..data.len()].copy_from_slice(&data);
+                Ok(u64::from_le_bytes(buf))
+            }
+            None => Err(format!("cannot access mem at {:#x}", addr)),
+        }
+    }
+}
+
+pub fn eval(core: &mut Core, val: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        toks: lex(val)?,
+        pos: 0,
+        core,
+    };
+    let res = parser.binary(1)?;
+    match parser.peek() {
+        Some(v) => Err(format!("unexpected {:?}", v)),
+        None => Ok(res),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{CoreConfig, XLen};
+    use crate::core::Core;
+    use crate::expr::eval;
+
+    #[test]
+    fn expr_eval() {
+        let mut core = Core::new(
+            String::from("none"),
+            vec![],
+            XLen::X64,
+            0x8000_0000,
+            0x6b,
+            CoreConfig::default(),
+        );
+        core.load_bin_file(vec![0x13, 0x05, 0x50, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]).unwrap();
+        core.set_reg(2, 0x8000_0000);
+        core.set_reg(10, 7);
+        let mut val = |v: &str| eval(&mut core, v).map(|v| v.val);
+        assert_eq!(val("$a0 + 8 * 2"), Ok(23));
+        assert_eq!(val("($a0 + 1) << 4 | 0x3"), Ok(0x83));
+        assert_eq!(val("$x10 == 7 && $pc == 0x80000000"), Ok(1));
+        assert_eq!(val("*(u64*)($sp)"), Ok(0xFFFF_FFFF_0050_0513));
+        assert_eq!(val("*(i32*)($sp + 4)"), Ok(u64::MAX));
+        assert_eq!(val("(u8)0x1234 - 0x35"), Ok(u64::MAX));
+        assert_eq!(val("$mhartid"), Ok(0));
+        assert!(val("$a0 / 0").is_err());
+        assert!(val("main").is_err());
+        assert!(val("(1 + 2").is_err());
+        assert_eq!(
+            eval(&mut core, "*(u16*)0x80000002").unwrap().lval,
+            Some((0x8000_0002, 2))
+        );
+    }
+}
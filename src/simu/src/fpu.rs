@@ -0,0 +1,724 @@
+// IEEE 754 binary32/binary64 arithmetic for the F/D extension
+// NOTE: all ops work on raw bits and return the result with the exception
+// flags, so every rounding mode and flag is exact instead of host-dependent
+
+pub const FLAG_NX: u8 = 0x01; // inexact
+pub const FLAG_UF: u8 = 0x02; // underflow
+pub const FLAG_OF: u8 = 0x04; // overflow
+pub const FLAG_DZ: u8 = 0x08; // divide by zero
+pub const FLAG_NV: u8 = 0x10; // invalid operation
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FpFmt {
+    Single,
+    Double,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundMode {
+    RNE,
+    RTZ,
+    RDN,
+    RUP,
+    RMM,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IntFmt {
+    W,
+    WU,
+    L,
+    LU,
+}
+
+impl FpFmt {
+    fn exp_bits(self) -> u32 {
+        match self {
+            FpFmt::Single => 8,
+            FpFmt::Double => 11,
+        }
+    }
+
+    fn man_bits(self) -> u32 {
+        match self {
+            FpFmt::Single => 23,
+            FpFmt::Double => 52,
+        }
+    }
+
+    fn bias(self) -> i32 {
+        (1 << (self.exp_bits() - 1)) - 1
+    }
+
+    fn sign_bit(self) -> u64 {
+        1u64 << (self.exp_bits() + self.man_bits())
+    }
+
+    fn inf(self, sign: bool) -> u64 {
+        let exp = ((1u64 << self.exp_bits()) - 1) << self.man_bits();
+        match sign {
+            true => self.sign_bit() | exp,
+            false => exp,
+        }
+    }
+
+    fn zero(self, sign: bool) -> u64 {
+        match sign {
+            true => self.sign_bit(),
+            false => 0,
+        }
+    }
+
+    fn max_finite(self, sign: bool) -> u64 {
+        self.inf(sign) - 1
+    }
+
+    pub fn canonical_nan(self) -> u64 {
+        match self {
+            FpFmt::Single => 0x7FC0_0000,
+            FpFmt::Double => 0x7FF8_0000_0000_0000,
+        }
+    }
+}
+
+impl RoundMode {
+    pub fn from_bits(val: u32) -> Option<RoundMode> {
+        match val {
+            0 => Some(RoundMode::RNE),
+            1 => Some(RoundMode::RTZ),
+            2 => Some(RoundMode::RDN),
+            3 => Some(RoundMode::RUP),
+            4 => Some(RoundMode::RMM),
+            _ => None,
+        }
+    }
+}
+
+// NOTE: a single-precision value lives in the low half of a 64-bit f reg, the
+// high half must be all ones, otherwise it reads as the canonical NaN
+pub fn box_f32(val: u64) -> u64 {
+    0xFFFF_FFFF_0000_0000 | (val & 0xFFFF_FFFF)
+}
+
+pub fn unbox_f32(val: u64) -> u64 {
+    match val >> 32 {
+        0xFFFF_FFFF => val & 0xFFFF_FFFF,
+        _ => FpFmt::Single.canonical_nan(),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Zero,
+    Finite,
+    Inf,
+    QNaN,
+    SNaN,
+}
+
+// value = (-1)^sign * sig * 2^exp for the finite kind
+#[derive(Clone, Copy)]
+struct Unpacked {
+    sign: bool,
+    kind: Kind,
+    exp: i32,
+    sig: u128,
+}
+
+impl Unpacked {
+    fn is_nan(&self) -> bool {
+        self.kind == Kind::QNaN || self.kind == Kind::SNaN
+    }
+}
+
+fn unpack(bits: u64, fmt: FpFmt) -> Unpacked {
+    let man = fmt.man_bits();
+    let sign = bits & fmt.sign_bit() != 0;
+    let exp = ((bits >> man) & ((1u64 << fmt.exp_bits()) - 1)) as i32;
+    let frac = bits & ((1u64 << man) - 1);
+    let mut res = Unpacked {
+        sign,
+        kind: Kind::Finite,
+        exp: 0,
+        sig: 0,
+    };
+
+    if exp == (1 << fmt.exp_bits()) - 1 {
+        res.kind = match (frac, (frac >> (man - 1)) & 1) {
+            (0, _) => Kind::Inf,
+            (_, 1) => Kind::QNaN,
+            _ => Kind::SNaN,
+        };
+    } else if exp == 0 {
+        match frac {
+            0 => res.kind = Kind::Zero,
+            _ => {
+                res.exp = 1 - fmt.bias() - man as i32;
+                res.sig = frac as u128;
+            }
+        }
+    } else {
+        res.exp = exp - fmt.bias() - man as i32;
+        res.sig = (frac | (1u64 << man)) as u128;
+    }
+    res
+}
+
+// shift right and 'jam' the lost bits into the lsb so they still count as sticky
+fn shr_jam(val: u128, sht: u32) -> u128 {
+    match sht {
+        0 => val,
+        1..=127 => (val >> sht) | ((val & ((1u128 << sht) - 1) != 0) as u128),
+        _ => (val != 0) as u128,
+    }
+}
+
+// drop the low 'sht' bits of sig with the rounding mode, return (rounded, inexact)
+fn round_sig(sig: u128, sht: u32, sign: bool, rm: RoundMode) -> (u128, bool) {
+    if sht >= 128 {
+        let inexact = sig != 0;
+        let inc = match rm {
+            RoundMode::RDN => sign && inexact,
+            RoundMode::RUP => !sign && inexact,
+            _ => false, // the dropped part is always less than half
+        };
+        return (inc as u128, inexact);
+    }
+
+    let kept = sig >> sht;
+    let rem = sig & ((1u128 << sht) - 1);
+    let half = 1u128 << (sht - 1);
+    let inexact = rem != 0;
+    let inc = match rm {
+        RoundMode::RNE => rem > half || (rem == half && kept & 1 == 1),
+        RoundMode::RTZ => false,
+        RoundMode::RDN => inexact && sign,
+        RoundMode::RUP => inexact && !sign,
+        RoundMode::RMM => rem >= half,
+    };
+    (kept + inc as u128, inexact)
+}
+
+// round the exact value (-1)^sign * sig * 2^exp to the format, sig must be nonzero
+fn round_pack(sign: bool, exp: i32, sig: u128, fmt: FpFmt, rm: RoundMode) -> (u64, u8) {
+    let man = fmt.man_bits() as i32;
+    let emin = 1 - fmt.bias();
+    let emax = fmt.bias();
+
+    // normalize the msb to bit 125, then the sticky bits are far below the round bit
+    let msb = 127 - sig.leading_zeros() as i32;
+    let (sig, exp) = match msb {
+        125 => (sig, exp),
+        126 | 127 => (shr_jam(sig, (msb - 125) as u32), exp + msb - 125),
+        _ => (sig << (125 - msb), exp - (125 - msb)),
+    };
+    let val_exp = exp + 125; // exponent of the msb
+
+    // NOTE: riscv detects tininess after rounding
+    let tiny = val_exp < emin - 1
+        || (val_exp == emin - 1 && {
+            let (kept, _) = round_sig(sig, (125 - man) as u32, sign, rm);
+            kept >> (man + 1) == 0
+        });
+
+    let mut lsb_exp = match val_exp < emin {
+        true => emin - man,
+        false => val_exp - man,
+    };
+    let (mut kept, inexact) = round_sig(sig, (lsb_exp - exp) as u32, sign, rm);
+    if kept >> (man + 1) != 0 {
+        kept >>= 1;
+        lsb_exp += 1;
+    }
+
+    let mut flags = 0u8;
+    if inexact {
+        flags |= FLAG_NX;
+        if tiny {
+            flags |= FLAG_UF;
+        }
+    }
+
+    if lsb_exp + man > emax {
+        let to_inf = match rm {
+            RoundMode::RNE | RoundMode::RMM => true,
+            RoundMode::RTZ => false,
+            RoundMode::RDN => sign,
+            RoundMode::RUP => !sign,
+        };
+        let res = match to_inf {
+            true => fmt.inf(sign),
+            false => fmt.max_finite(sign),
+        };
+        return (res, FLAG_OF | FLAG_NX);
+    }
+
+    // NOTE: the implicit bit of 'kept' carries into the exponent field, so the
+    // subnormal to normal transition works without a special case
+    let exp_field = (lsb_exp + man + fmt.bias() - 1) as u64;
+    let res = fmt.zero(sign) | ((exp_field << man) + kept as u64);
+    (res, flags)
+}
+
+fn nan_res(fmt: FpFmt, ops: &[Unpacked]) -> (u64, u8) {
+    let invalid = ops.iter().any(|v| v.kind == Kind::SNaN);
+    (fmt.canonical_nan(), if invalid { FLAG_NV } else { 0 })
+}
+
+// move the msb of sig to bit 125 without losing any bit
+fn norm(sig: u128, exp: i32) -> (u128, i32) {
+    let sht = sig.leading_zeros() as i32 - 2;
+    (sig << sht, exp - sht)
+}
+
+fn add_unpacked(x: Unpacked, y: Unpacked, fmt: FpFmt, rm: RoundMode) -> (u64, u8) {
+    if x.is_nan() || y.is_nan() {
+        return nan_res(fmt, &[x, y]);
+    }
+
+    match (x.kind, y.kind) {
+        (Kind::Inf, Kind::Inf) => match x.sign == y.sign {
+            true => (fmt.inf(x.sign), 0),
+            false => (fmt.canonical_nan(), FLAG_NV),
+        },
+        (Kind::Inf, _) => (fmt.inf(x.sign), 0),
+        (_, Kind::Inf) => (fmt.inf(y.sign), 0),
+        (Kind::Zero, Kind::Zero) => match x.sign == y.sign {
+            true => (fmt.zero(x.sign), 0),
+            false => (fmt.zero(rm == RoundMode::RDN), 0),
+        },
+        (Kind::Zero, _) => round_pack(y.sign, y.exp, y.sig, fmt, rm),
+        (_, Kind::Zero) => round_pack(x.sign, x.exp, x.sig, fmt, rm),
+        _ => {
+            let (mut sig_a, mut exp_a) = norm(x.sig, x.exp);
+            let (mut sig_b, mut exp_b) = norm(y.sig, y.exp);
+            let (mut sign_a, mut sign_b) = (x.sign, y.sign);
+            if exp_a < exp_b {
+                std::mem::swap(&mut sig_a, &mut sig_b);
+                std::mem::swap(&mut exp_a, &mut exp_b);
+                std::mem::swap(&mut sign_a, &mut sign_b);
+            }
+            let sig_b = shr_jam(sig_b, (exp_a - exp_b) as u32);
+
+            if sign_a == sign_b {
+                return round_pack(sign_a, exp_a, sig_a + sig_b, fmt, rm);
+            }
+            let (sign, diff) = match sig_a >= sig_b {
+                true => (sign_a, sig_a - sig_b),
+                false => (sign_b, sig_b - sig_a),
+            };
+            match diff {
+                0 => (fmt.zero(rm == RoundMode::RDN), 0),
+                _ => round_pack(sign, exp_a, diff, fmt, rm),
+            }
+        }
+    }
+}
+
+pub fn add(a: u64, b: u64, fmt: FpFmt, rm: RoundMode) -> (u64, u8) {
+    add_unpacked(unpack(a, fmt), unpack(b, fmt), fmt, rm)
+}
+
+pub fn sub(a: u64, b: u64, fmt: FpFmt, rm: RoundMode) -> (u64, u8) {
+    let mut y = unpack(b, fmt);
+    y.sign = !y.sign;
+    add_unpacked(unpack(a, fmt), y, fmt, rm)
+}
+
+fn mul_unpacked(x: Unpacked, y: Unpacked) -> Unpacked {
+    let sign = x.sign ^ y.sign;
+    let kind = match (x.kind, y.kind) {
+        (Kind::Inf, _) | (_, Kind::Inf) => Kind::Inf,
+        (Kind::Zero, _) | (_, Kind::Zero) => Kind::Zero,
+        _ => Kind::Finite,
+    };
+    Unpacked {
+        sign,
+        kind,
+        exp: x.exp + y.exp,
+        sig: x.sig * y.sig, // at most 106 bits, so the product is exact
+    }
+}
+
+fn is_inf_mul_zero(x: &Unpacked, y: &Unpacked) -> bool {
+    (x.kind == Kind::Inf && y.kind == Kind::Zero) || (x.kind == Kind::Zero && y.kind == Kind::Inf)
+}
+
+pub fn mul(a: u64, b: u64, fmt: FpFmt, rm: RoundMode) -> (u64, u8) {
+    let (x, y) = (unpack(a, fmt), unpack(b, fmt));
+    if x.is_nan() || y.is_nan() {
+        return nan_res(fmt, &[x, y]);
+    }
+    if is_inf_mul_zero(&x, &y) {
+        return (fmt.canonical_nan(), FLAG_NV);
+    }
+
+    let prod = mul_unpacked(x, y);
+    match prod.kind {
+        Kind::Inf => (fmt.inf(prod.sign), 0),
+        Kind::Zero => (fmt.zero(prod.sign), 0),
+        _ => round_pack(prod.sign, prod.exp, prod.sig, fmt, rm),
+    }
+}
+
+// (-1)^neg_prod * a * b + (-1)^neg_add * c with a single rounding
+pub fn fma(
+    a: u64,
+    b: u64,
+    c: u64,
+    neg_prod: bool,
+    neg_add: bool,
+    fmt: FpFmt,
+    rm: RoundMode,
+) -> (u64, u8) {
+    let (x, y, mut z) = (unpack(a, fmt), unpack(b, fmt), unpack(c, fmt));
+    // NOTE: inf * 0 is invalid even if the addend is a quiet NaN
+    if is_inf_mul_zero(&x, &y) {
+        return (fmt.canonical_nan(), FLAG_NV);
+    }
+    if x.is_nan() || y.is_nan() || z.is_nan() {
+        return nan_res(fmt, &[x, y, z]);
+    }
+
+    let mut prod = mul_unpacked(x, y);
+    prod.sign ^= neg_prod;
+    z.sign ^= neg_add;
+    add_unpacked(prod, z, fmt, rm)
+}
+
+pub fn div(a: u64, b: u64, fmt: FpFmt, rm: RoundMode) -> (u64, u8) {
+    let (x, y) = (unpack(a, fmt), unpack(b, fmt));
+    if x.is_nan() || y.is_nan() {
+        return nan_res(fmt, &[x, y]);
+    }
+
+    let sign = x.sign ^ y.sign;
+    match (x.kind, y.kind) {
+        (Kind::Inf, Kind::Inf) | (Kind::Zero, Kind::Zero) => (fmt.canonical_nan(), FLAG_NV),
+        (Kind::Inf, _) => (fmt.inf(sign), 0),
+        (_, Kind::Inf) | (Kind::Zero, _) => (fmt.zero(sign), 0),
+        (_, Kind::Zero) => (fmt.inf(sign), FLAG_DZ),
+        _ => {
+            // dividend msb at bit 125, divisor msb at bit 62: at least 63 quotient bits
+            let (sig_a, exp_a) = norm(x.sig, x.exp);
+            let sht = y.sig.leading_zeros() as i32 - 65;
+            let (sig_b, exp_b) = (y.sig << sht, y.exp - sht);
+            let quot = sig_a / sig_b;
+            let rem = sig_a % sig_b;
+            round_pack(sign, exp_a - exp_b, quot | (rem != 0) as u128, fmt, rm)
+        }
+    }
+}
+
+// integer square root, return (root, remainder)
+fn isqrt(val: u128) -> (u128, u128) {
+    let mut rem = val;
+    let mut res = 0u128;
+    let mut bit = 1u128 << 126;
+    while bit > val {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rem >= res + bit {
+            rem -= res + bit;
+            res = (res >> 1) + bit;
+        } else {
+            res >>= 1;
+        }
+        bit >>= 2;
+    }
+    (res, rem)
+}
+
+pub fn sqrt(a: u64, fmt: FpFmt, rm: RoundMode) -> (u64, u8) {
+    let x = unpack(a, fmt);
+    if x.is_nan() {
+        return nan_res(fmt, &[x]);
+    }
+
+    match (x.kind, x.sign) {
+        (Kind::Zero, _) => (a, 0),
+        (_, true) => (fmt.canonical_nan(), FLAG_NV),
+        (Kind::Inf, _) => (a, 0),
+        _ => {
+            // the exponent must be even to halve it
+            let (mut sig, mut exp) = norm(x.sig, x.exp);
+            sig >>= 1;
+            exp += 1;
+            if exp & 1 == 1 {
+                sig <<= 1;
+                exp -= 1;
+            }
+            let (root, rem) = isqrt(sig);
+            round_pack(false, exp / 2, root | (rem != 0) as u128, fmt, rm)
+        }
+    }
+}
+
+// total order key, -0 sorts before +0
+fn order_key(bits: u64, fmt: FpFmt) -> u64 {
+    match bits & fmt.sign_bit() {
+        0 => bits | fmt.sign_bit(),
+        _ => !bits & (fmt.sign_bit() | (fmt.sign_bit() - 1)),
+    }
+}
+
+pub fn min_max(a: u64, b: u64, fmt: FpFmt, is_max: bool) -> (u64, u8) {
+    let (x, y) = (unpack(a, fmt), unpack(b, fmt));
+    let flags = match x.kind == Kind::SNaN || y.kind == Kind::SNaN {
+        true => FLAG_NV,
+        false => 0,
+    };
+
+    let res = match (x.is_nan(), y.is_nan()) {
+        (true, true) => fmt.canonical_nan(),
+        (true, false) => b,
+        (false, true) => a,
+        _ => match (order_key(a, fmt) < order_key(b, fmt)) ^ is_max {
+            true => a,
+            false => b,
+        },
+    };
+    (res, flags)
+}
+
+// quiet compare, only a signaling NaN is invalid
+pub fn eq(a: u64, b: u64, fmt: FpFmt) -> (bool, u8) {
+    let (x, y) = (unpack(a, fmt), unpack(b, fmt));
+    if x.is_nan() || y.is_nan() {
+        return (false, nan_res(fmt, &[x, y]).1);
+    }
+    match (x.kind, y.kind) {
+        (Kind::Zero, Kind::Zero) => (true, 0),
+        _ => (a == b, 0),
+    }
+}
+
+// signaling compare, any NaN is invalid
+pub fn lt(a: u64, b: u64, fmt: FpFmt) -> (bool, u8) {
+    let (x, y) = (unpack(a, fmt), unpack(b, fmt));
+    if x.is_nan() || y.is_nan() {
+        return (false, FLAG_NV);
+    }
+    match (x.kind, y.kind) {
+        (Kind::Zero, Kind::Zero) => (false, 0),
+        _ => (order_key(a, fmt) < order_key(b, fmt), 0),
+    }
+}
+
+pub fn le(a: u64, b: u64, fmt: FpFmt) -> (bool, u8) {
+    let (x, y) = (unpack(a, fmt), unpack(b, fmt));
+    if x.is_nan() || y.is_nan() {
+        return (false, FLAG_NV);
+    }
+    match (x.kind, y.kind) {
+        (Kind::Zero, Kind::Zero) => (true, 0),
+        _ => (order_key(a, fmt) <= order_key(b, fmt), 0),
+    }
+}
+
+pub fn classify(a: u64, fmt: FpFmt) -> u64 {
+    let x = unpack(a, fmt);
+    let subnormal = x.kind == Kind::Finite && x.sig >> fmt.man_bits() == 0;
+    let bit = match (x.kind, x.sign) {
+        (Kind::Inf, true) => 0,
+        (Kind::Finite, true) if !subnormal => 1,
+        (Kind::Finite, true) => 2,
+        (Kind::Zero, true) => 3,
+        (Kind::Zero, false) => 4,
+        (Kind::Finite, false) if subnormal => 5,
+        (Kind::Finite, false) => 6,
+        (Kind::Inf, false) => 7,
+        (Kind::SNaN, _) => 8,
+        (Kind::QNaN, _) => 9,
+    };
+    1u64 << bit
+}
+
+// fp -> int, the result is the xlen register value(w/wu are sign-extended)
+pub fn to_int(a: u64, fmt: FpFmt, ifmt: IntFmt, rm: RoundMode) -> (u64, u8) {
+    let (min, max): (i128, i128) = match ifmt {
+        IntFmt::W => (i32::MIN as i128, i32::MAX as i128),
+        IntFmt::WU => (0, u32::MAX as i128),
+        IntFmt::L => (i64::MIN as i128, i64::MAX as i128),
+        IntFmt::LU => (0, u64::MAX as i128),
+    };
+    let pack = |val: i128| match ifmt {
+        IntFmt::W | IntFmt::WU => val as i32 as i64 as u64,
+        IntFmt::L | IntFmt::LU => val as u64,
+    };
+
+    let x = unpack(a, fmt);
+    let (val, inexact) = match x.kind {
+        Kind::QNaN | Kind::SNaN => return (pack(max), FLAG_NV),
+        Kind::Inf => return (pack(if x.sign { min } else { max }), FLAG_NV),
+        Kind::Zero => (0i128, false),
+        Kind::Finite => {
+            let (mag, inexact) = match x.exp >= 0 {
+                // NOTE: anything not less than 2^65 is out of range anyway
+                true if x.exp + (128 - x.sig.leading_zeros() as i32) > 65 => (1u128 << 65, false),
+                true => (x.sig << x.exp, false),
+                false => round_sig(x.sig, (-x.exp) as u32, x.sign, rm),
+            };
+            match x.sign {
+                true => (-(mag as i128), inexact),
+                false => (mag as i128, inexact),
+            }
+        }
+    };
+
+    if val < min {
+        (pack(min), FLAG_NV)
+    } else if val > max {
+        (pack(max), FLAG_NV)
+    } else {
+        (pack(val), if inexact { FLAG_NX } else { 0 })
+    }
+}
+
+// int -> fp, 'val' is the xlen register value
+pub fn from_int(val: u64, ifmt: IntFmt, fmt: FpFmt, rm: RoundMode) -> (u64, u8) {
+    let (sign, mag) = match ifmt {
+        IntFmt::W => {
+            let v = val as i32 as i64;
+            (v < 0, v.unsigned_abs())
+        }
+        IntFmt::WU => (false, val as u32 as u64),
+        IntFmt::L => ((val as i64) < 0, (val as i64).unsigned_abs()),
+        IntFmt::LU => (false, val),
+    };
+
+    match mag {
+        0 => (fmt.zero(false), 0),
+        _ => round_pack(sign, 0, mag as u128, fmt, rm),
+    }
+}
+
+// fp -> fp of another precision
+pub fn convert(a: u64, from: FpFmt, to: FpFmt, rm: RoundMode) -> (u64, u8) {
+    let x = unpack(a, from);
+    match x.kind {
+        Kind::QNaN | Kind::SNaN => nan_res(to, &[x]),
+        Kind::Inf => (to.inf(x.sign), 0),
+        Kind::Zero => (to.zero(x.sign), 0),
+        Kind::Finite => round_pack(x.sign, x.exp, x.sig, to, rm),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fpu::*;
+
+    // simple xorshift, so that the tests are reproducible without extra crates
+    fn rand_seq(seed: u64, num: usize) -> Vec<u64> {
+        let mut state = seed;
+        (0..num)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fpu_rne_match_host() {
+        let vals = rand_seq(0x2545_F491_4F6C_DD1D, 3000);
+        for v in vals.chunks(3) {
+            let (a, b, c) = (v[0] >> 2, v[1] >> 2, v[2] >> 2);
+            let (fa, fb, fc) = (f64::from_bits(a), f64::from_bits(b), f64::from_bits(c));
+            let rne = RoundMode::RNE;
+            let host = [fa + fb, fa - fb, fa * fb, fa / fb, fa.sqrt(), fa.mul_add(fb, fc)];
+            let dut = [
+                add(a, b, FpFmt::Double, rne).0,
+                sub(a, b, FpFmt::Double, rne).0,
+                mul(a, b, FpFmt::Double, rne).0,
+                div(a, b, FpFmt::Double, rne).0,
+                sqrt(a, FpFmt::Double, rne).0,
+                fma(a, b, c, false, false, FpFmt::Double, rne).0,
+            ];
+            for (h, d) in host.iter().zip(dut.iter()) {
+                assert!(h.is_nan() && f64::from_bits(*d).is_nan() || h.to_bits() == *d);
+            }
+
+            let (a, b) = (v[0] & 0x7FFF_FFFF, v[1] & 0xFFFF_FFFF);
+            let (fa, fb) = (f32::from_bits(a as u32), f32::from_bits(b as u32));
+            let host = [fa + fb, fa * fb, fa / fb, fa.sqrt()];
+            let dut = [
+                add(a, b, FpFmt::Single, rne).0,
+                mul(a, b, FpFmt::Single, rne).0,
+                div(a, b, FpFmt::Single, rne).0,
+                sqrt(a, FpFmt::Single, rne).0,
+            ];
+            for (h, d) in host.iter().zip(dut.iter()) {
+                assert!(h.is_nan() && f32::from_bits(*d as u32).is_nan() || h.to_bits() as u64 == *d);
+            }
+        }
+    }
+
+    #[test]
+    fn fpu_round_mode() {
+        let one = 1.0f32.to_bits() as u64;
+        let three = 3.0f32.to_bits() as u64;
+        let (v, flags) = div(one, three, FpFmt::Single, RoundMode::RTZ);
+        assert_eq!(v, 0x3EAA_AAAA);
+        assert_eq!(flags, FLAG_NX);
+        assert_eq!(div(one, three, FpFmt::Single, RoundMode::RUP).0, 0x3EAA_AAAB);
+        assert_eq!(div(one, three, FpFmt::Single, RoundMode::RDN).0, 0x3EAA_AAAA);
+        let neg_one = (-1.0f32).to_bits() as u64;
+        assert_eq!(div(neg_one, three, FpFmt::Single, RoundMode::RDN).0, 0xBEAA_AAAB);
+
+        let big = f64::MAX.to_bits();
+        assert_eq!(add(big, big, FpFmt::Double, RoundMode::RTZ), (big, FLAG_OF | FLAG_NX));
+        assert_eq!(
+            add(big, big, FpFmt::Double, RoundMode::RNE),
+            (f64::INFINITY.to_bits(), FLAG_OF | FLAG_NX)
+        );
+        let half = 2.5f64.to_bits();
+        assert_eq!(to_int(half, FpFmt::Double, IntFmt::W, RoundMode::RNE), (2, FLAG_NX));
+        assert_eq!(to_int(half, FpFmt::Double, IntFmt::W, RoundMode::RMM), (3, FLAG_NX));
+    }
+
+    #[test]
+    fn fpu_special_case() {
+        let snan = 0x7F80_0001u64;
+        let qnan = FpFmt::Single.canonical_nan();
+        let inf = f32::INFINITY.to_bits() as u64;
+        let zero = 0u64;
+        let neg_zero = 0x8000_0000u64;
+        let one = 1.0f32.to_bits() as u64;
+        let rne = RoundMode::RNE;
+
+        assert_eq!(add(snan, one, FpFmt::Single, rne), (qnan, FLAG_NV));
+        assert_eq!(sub(inf, inf, FpFmt::Single, rne), (qnan, FLAG_NV));
+        assert_eq!(div(one, zero, FpFmt::Single, rne), (inf, FLAG_DZ));
+        assert_eq!(fma(inf, zero, qnan, false, false, FpFmt::Single, rne), (qnan, FLAG_NV));
+        assert_eq!(sqrt(neg_zero, FpFmt::Single, rne), (neg_zero, 0));
+        assert_eq!(sub(one, one, FpFmt::Single, RoundMode::RDN), (neg_zero, 0));
+        assert_eq!(min_max(neg_zero, zero, FpFmt::Single, false), (neg_zero, 0));
+        assert_eq!(min_max(qnan, one, FpFmt::Single, true), (one, 0));
+        assert_eq!(min_max(snan, one, FpFmt::Single, true), (one, FLAG_NV));
+        assert_eq!(eq(qnan, one, FpFmt::Single), (false, 0));
+        assert_eq!(lt(qnan, one, FpFmt::Single), (false, FLAG_NV));
+        assert_eq!(le(neg_zero, zero, FpFmt::Single), (true, 0));
+        assert_eq!(classify(snan, FpFmt::Single), 1 << 8);
+        assert_eq!(classify(0x0000_0001, FpFmt::Single), 1 << 5);
+        assert_eq!(unbox_f32(one), qnan);
+        assert_eq!(unbox_f32(box_f32(one)), one);
+
+        // the smallest subnormal halved is tiny and inexact
+        let tiny = 0x0000_0001u64;
+        let half = 0.5f32.to_bits() as u64;
+        assert_eq!(mul(tiny, half, FpFmt::Single, rne), (0, FLAG_UF | FLAG_NX));
+        assert_eq!(to_int(qnan, FpFmt::Single, IntFmt::WU, rne), (u64::MAX, FLAG_NV));
+        assert_eq!(
+            to_int((-1.5f32).to_bits() as u64, FpFmt::Single, IntFmt::LU, rne),
+            (0, FLAG_NV)
+        );
+        assert_eq!(
+            from_int(u64::MAX, IntFmt::LU, FpFmt::Double, rne),
+            (18446744073709551616.0f64.to_bits(), FLAG_NX)
+        );
+    }
+}
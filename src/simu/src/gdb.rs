@@ -0,0 +1,576 @@
+use crate::config::XLen;
+use crate::core::Core;
+use crate::csr;
+use crate::debug::{Debugger, StopReason, WatchKind, Watchpoint};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::net::{TcpListener, TcpStream};
+
+// NOTE: gdb numbers the regs as x0-x31, pc, f0-f31, then each csr at
+// CSR_REGNUM + its addr
+const PC_REGNUM: usize = 32;
+const FREG_REGNUM: usize = 33;
+const CSR_REGNUM: usize = 65;
+
+const X_NAME: [&str; 32] = [
+    "zero", "ra", "sp", "gp", "tp", "t0", "t1", "t2", "fp", "s1", "a0", "a1", "a2", "a3", "a4",
+    "a5", "a6", "a7", "s2", "s3", "s4", "s5", "s6", "s7", "s8", "s9", "s10", "s11", "t3", "t4",
+    "t5", "t6",
+];
+
+const F_NAME: [&str; 32] = [
+    "ft0", "ft1", "ft2", "ft3", "ft4", "ft5", "ft6", "ft7", "fs0", "fs1", "fa0", "fa1", "fa2",
+    "fa3", "fa4", "fa5", "fa6", "fa7", "fs2", "fs3", "fs4", "fs5", "fs6", "fs7", "fs8", "fs9",
+    "fs10", "fs11", "ft8", "ft9", "ft10", "ft11",
+];
+
+fn to_hex(data: &[u8]) -> String {
+    data.iter().map(|v| format!("{:02x}", v)).collect()
+}
+
+fn from_hex(val: &str) -> Option<Vec<u8>> {
+    if val.len() & 0x1 != 0 {
+        return None;
+    }
+    (0..val.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(val.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_u64(val: &str) -> Option<u64> {
+    u64::from_str_radix(val, 16).ok()
+}
+
+// NOTE: the regs are sent in the target byte order, little endian
+fn reg_hex(val: u64, bytes: usize) -> String {
+    to_hex(&val.to_le_bytes()[..bytes])
+}
+
+fn reg_val(val: &str) -> Option<u64> {
+    let data = from_hex(val)?;
+    let mut buf = [0u8; 8];
+    buf[..data.len().min(8)].copy_from_slice(&data[..data.len().min(8)]);
+    Some(u64::from_le_bytes(buf))
+}
+
+pub struct GdbStub {
+    dbg: Debugger,
+    no_ack: bool,
+}
+
+impl Default for GdbStub {
+    fn default() -> Self {
+        GdbStub::new()
+    }
+}
+
+impl GdbStub {
+    pub fn new() -> Self {
+        GdbStub {
+            dbg: Debugger::new(),
+            no_ack: false,
+        }
+    }
+
+    fn xlen_bytes(core: &Core) -> usize {
+        match core.xlen() {
+            XLen::X32 => 4,
+            XLen::X64 => 8,
+        }
+    }
+
+    pub fn target_xml(core: &Core) -> String {
+        let (arch, bits) = match core.xlen() {
+            XLen::X32 => ("riscv:rv32", 32),
+            XLen::X64 => ("riscv:rv64", 64),
+        };
+        let mut res = format!(
+            "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+             <target version=\"1.0\"><architecture>{}</architecture>\
+             <feature name=\"org.gnu.gdb.riscv.cpu\">",
+            arch
+        );
+        for (i, v) in X_NAME.iter().enumerate() {
+            let kind = match i {
+                1 | 5 => "code_ptr",
+                2 | 8 => "data_ptr",
+                _ => "int",
+            };
+            res += &format!(
+                "<reg name=\"{}\" bitsize=\"{}\" regnum=\"{}\" type=\"{}\"/>",
+                v, bits, i, kind
+            );
+        }
+        res += &format!(
+            "<reg name=\"pc\" bitsize=\"{}\" regnum=\"{}\" type=\"code_ptr\"/></feature>",
+            bits, PC_REGNUM
+        );
+        res += "<feature name=\"org.gnu.gdb.riscv.fpu\">";
+        for (i, v) in F_NAME.iter().enumerate() {
+            res += &format!(
+                "<reg name=\"{}\" bitsize=\"64\" regnum=\"{}\" type=\"ieee_double\"/>",
+                v,
+                FREG_REGNUM + i
+            );
+        }
+        res += "</feature><feature name=\"org.gnu.gdb.riscv.csr\">";
+        for (addr, name) in csr::CSR_NAME.iter() {
+            if core.debug_csr(*addr).is_some() {
+                res += &format!(
+                    "<reg name=\"{}\" bitsize=\"{}\" regnum=\"{}\" group=\"csr\"/>",
+                    name,
+                    bits,
+                    CSR_REGNUM + *addr as usize
+                );
+            }
+        }
+        res + "</feature></target>"
+    }
+
+    fn read_reg(core: &Core, idx: usize) -> Option<u64> {
+        match idx {
+            0..=31 => Some(core.reg().x[idx] as u64),
+            PC_REGNUM => Some(core.pc()),
+            33..=64 => Some(core.freg().f[idx - FREG_REGNUM]),
+            _ if idx - CSR_REGNUM < 0x1000 => core.debug_csr((idx - CSR_REGNUM) as u16),
+            _ => None,
+        }
+    }
+
+    fn write_reg(core: &mut Core, idx: usize, val: u64) -> bool {
+        match idx {
+            0..=31 => core.set_reg(idx, val),
+            PC_REGNUM => core.set_pc(val),
+            33..=64 => core.set_freg(idx - FREG_REGNUM, val),
+            _ if idx - CSR_REGNUM < 0x1000 => {
+                return core.debug_set_csr((idx - CSR_REGNUM) as u16, val)
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn stop_reply(reason: StopReason) -> String {
+        match reason {
+            StopReason::Step => "T05thread:1;".to_string(),
+            StopReason::Breakpoint => "T05thread:1;swbreak:;".to_string(),
+            StopReason::Watch(kind, addr) => {
+                let name = match kind {
+                    WatchKind::Write => "watch",
+                    WatchKind::Read => "rwatch",
+                    WatchKind::Access => "awatch",
+                };
+                format!("T05thread:1;{}:{:x};", name, addr)
+            }
+            StopReason::Exited(code) => format!("W{:02x}", code & 0xFF),
+            StopReason::Interrupt => "T02thread:1;".to_string(),
+            StopReason::HistoryBegin => "T05thread:1;replaylog:begin;".to_string(),
+        }
+    }
+
+    // NOTE: 'Z1,addr,kind', the kind of the watchpoint is its len
+    fn set_point(&mut self, core: &mut Core, val: &str, insert: bool) -> Option<()> {
+        let mut items = val.split(',');
+        let kind = items.next()?;
+        let addr = parse_u64(items.next()?)?;
+        let len = parse_u64(items.next()?)?;
+        let watch = match kind {
+            "0" | "1" => {
+                self.dbg.breakpoints.retain(|v| *v != addr);
+                if insert {
+                    self.dbg.breakpoints.push(addr);
+                }
+                return Some(());
+            }
+            "2" => WatchKind::Write,
+            "3" => WatchKind::Read,
+            "4" => WatchKind::Access,
+            _ => return None,
+        };
+        let point = Watchpoint {
+            addr,
+            len,
+            kind: watch,
+        };
+        let points = core.watchpoints_mut();
+        points.retain(|v| *v != point);
+        if insert {
+            points.push(point);
+        }
+        Some(())
+    }
+
+    // NOTE: the reply of the packet, None closes the connection. The run of
+    // 'c' and 's' polls the connection for the ctrl-c
+    pub fn handle(
+        &mut self,
+        core: &mut Core,
+        pkt: &str,
+        poll: &mut dyn FnMut() -> bool,
+    ) -> Option<String> {
+        let bytes = GdbStub::xlen_bytes(core);
+        let (cmd, args) = pkt.split_at(pkt.len().min(1));
+        let res = match cmd {
+            "?" => "S05".to_string(),
+            "g" => {
+                let mut res: String = (0..32)
+                    .map(|i| reg_hex(core.reg().x[i] as u64, bytes))
+                    .collect();
+                res += &reg_hex(core.pc(), bytes);
+                res
+            }
+            "G" => {
+                for (i, v) in args.as_bytes().chunks(bytes * 2).take(33).enumerate() {
+                    match std::str::from_utf8(v).ok().and_then(reg_val) {
+                        Some(val) => GdbStub::write_reg(core, i, val),
+                        None => return Some("E01".to_string()),
+                    };
+                }
+                "OK".to_string()
+            }
+            "p" => match parse_u64(args).map(|v| v as usize) {
+                Some(idx) => match GdbStub::read_reg(core, idx) {
+                    Some(v) if (FREG_REGNUM..CSR_REGNUM).contains(&idx) => reg_hex(v, 8),
+                    Some(v) => reg_hex(v, bytes),
+                    None => "E01".to_string(),
+                },
+                None => "E01".to_string(),
+            },
+            "P" => {
+                let res = args.split_once('=').and_then(|(idx, val)| {
+                    let idx = parse_u64(idx)? as usize;
+                    GdbStub::write_reg(core, idx, reg_val(val)?).then_some(())
+                });
+                match res {
+                    Some(()) => "OK".to_string(),
+                    None => "E01".to_string(),
+                }
+            }
+            "m" => {
+                let res = args.split_once(',').and_then(|(addr, len)| {
+                    core.debug_read(parse_u64(addr)?, parse_u64(len)? as usize)
+                });
+                match res {
+                    Some(v) => to_hex(&v),
+                    None => "E14".to_string(),
+                }
+            }
+            "M" => {
+                let res = args.split_once(':').and_then(|(head, data)| {
+                    let (addr, _len) = head.split_once(',')?;
+                    core.debug_write(parse_u64(addr)?, &from_hex(data)?)
+                        .then_some(())
+                });
+                match res {
+                    Some(()) => "OK".to_string(),
+                    None => "E14".to_string(),
+                }
+            }
+            "Z" | "z" => match self.set_point(core, args, cmd == "Z") {
+                Some(()) => "OK".to_string(),
+                None => String::new(),
+            },
+            "c" | "s" => {
+                if let Some(addr) = parse_u64(args) {
+                    core.set_pc(addr);
+                }
+                GdbStub::stop_reply(self.dbg.resume(core, cmd == "s", poll))
+            }
+            // NOTE: 'bs' and 'bc' are the reverse step and continue
+            "b" if core.history().is_none() => "E01".to_string(),
+            "b" => match args {
+                "s" | "c" => {
+                    GdbStub::stop_reply(self.dbg.reverse(core, (args == "s").then_some(1)))
+                }
+                _ => String::new(),
+            },
+            "H" | "T" => "OK".to_string(),
+            "D" => return None,
+            "k" => return None,
+            "q" | "Q" => self.query(core, pkt),
+            _ => String::new(),
+        };
+        Some(res)
+    }
+
+    fn query(&mut self, core: &Core, pkt: &str) -> String {
+        if pkt.starts_with("qSupported") {
+            let res = "PacketSize=4000;qXfer:features:read+;swbreak+;hwbreak+;QStartNoAckMode+";
+            return match core.history() {
+                Some(_) => format!("{};ReverseStep+;ReverseContinue+", res),
+                None => res.to_string(),
+            };
+        }
+        if let Some(args) = pkt.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = GdbStub::target_xml(core);
+            let range = args
+                .split_once(',')
+                .and_then(|(off, len)| Some((parse_u64(off)? as usize, parse_u64(len)? as usize)));
+            return match range {
+                Some((off, _len)) if off >= xml.len() => "l".to_string(),
+                Some((off, len)) if off + len >= xml.len() => format!("l{}", &xml[off..]),
+                Some((off, len)) => format!("m{}", &xml[off..off + len]),
+                None => "E01".to_string(),
+            };
+        }
+        match pkt {
+            "QStartNoAckMode" => {
+                self.no_ack = true;
+                "OK".to_string()
+            }
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            "qSymbol::" => "OK".to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+// NOTE: '$data#checksum', the '}' escapes the special chars in the reply
+fn send_packet(stream: &mut impl Write, data: &str) -> std::io::Result<()> {
+    let mut body = vec![];
+    for v in data.bytes() {
+        match v {
+            b'#' | b'$' | b'}' | b'*' => body.extend_from_slice(&[b'}', v ^ 0x20]),
+            _ => body.push(v),
+        }
+    }
+    let sum = body.iter().fold(0u8, |acc, v| acc.wrapping_add(*v));
+    let mut res = vec![b'$'];
+    res.extend_from_slice(&body);
+    res.extend_from_slice(format!("#{:02x}", sum).as_bytes());
+    stream.write_all(&res)
+}
+
+#[derive(Debug, PartialEq)]
+enum Packet {
+    Data(String),
+    Break, // the ctrl-c out of the run
+    BadSum,
+    Closed,
+}
+
+// NOTE: skip the acks, the checksum is over the escaped data
+fn read_packet(reader: &mut impl BufRead) -> std::io::Result<Packet> {
+    let mut byte = [0u8; 1];
+    loop {
+        if reader.read(&mut byte)? == 0 {
+            return Ok(Packet::Closed);
+        }
+        match byte[0] {
+            b'$' => break,
+            0x03 => return Ok(Packet::Break),
+            _ => {}
+        }
+    }
+    let mut body = vec![];
+    reader.read_until(b'#', &mut body)?;
+    if body.pop() != Some(b'#') {
+        return Ok(Packet::Closed);
+    }
+    let mut sum = [0u8; 2];
+    reader.read_exact(&mut sum)?;
+    let expect = std::str::from_utf8(&sum)
+        .ok()
+        .and_then(|v| u8::from_str_radix(v, 16).ok());
+    if expect != Some(body.iter().fold(0u8, |acc, v| acc.wrapping_add(*v))) {
+        return Ok(Packet::BadSum);
+    }
+    let mut data = vec![];
+    let mut iter = body.into_iter();
+    while let Some(v) = iter.next() {
+        match v {
+            b'}' => data.extend(iter.next().map(|v| v ^ 0x20)),
+            _ => data.push(v),
+        }
+    }
+    Ok(Packet::Data(String::from_utf8_lossy(&data).to_string()))
+}
+
+// NOTE: the ctrl-c comes as a raw 0x03 byte while the target runs
+fn poll_interrupt(reader: &mut BufReader<TcpStream>) -> bool {
+    if reader.get_ref().set_nonblocking(true).is_err() {
+        return false;
+    }
+    let res = match reader.fill_buf() {
+        Ok(buf) => buf.first() == Some(&0x03),
+        Err(e) if e.kind() == ErrorKind::WouldBlock => false,
+        Err(_e) => false,
+    };
+    if res {
+        reader.consume(1);
+    }
+    let _ = reader.get_ref().set_nonblocking(false);
+    res
+}
+
+pub fn gdb_setup(core: &mut Core, port: u16) -> std::io::Result<()> {
+    let server = TcpListener::bind(("127.0.0.1", port))?;
+    println!("[gdb] waiting for the connection on port {}", port);
+    let (stream, addr) = server.accept()?;
+    println!("[gdb] connected from {}", addr);
+    stream.set_nodelay(true)?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut stub = GdbStub::new();
+
+    loop {
+        let pkt = match read_packet(&mut reader)? {
+            Packet::Data(v) => v,
+            Packet::Break => continue,
+            // NOTE: gdb sends the packet again on the nak
+            Packet::BadSum => {
+                if !stub.no_ack {
+                    writer.write_all(b"-")?;
+                }
+                continue;
+            }
+            Packet::Closed => break,
+        };
+        if !stub.no_ack {
+            writer.write_all(b"+")?;
+        }
+        let mut poll = || poll_interrupt(&mut reader);
+        match stub.handle(core, &pkt, &mut poll) {
+            Some(v) => send_packet(&mut writer, &v)?,
+            None => {
+                if pkt == "D" {
+                    send_packet(&mut writer, "OK")?;
+                }
+                break;
+            }
+        }
+    }
+    println!("[gdb] disconnected");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{CoreConfig, XLen};
+    use crate::core::Core;
+    use crate::gdb::{read_packet, send_packet, GdbStub, Packet};
+    use std::io::Cursor;
+
+    fn new_core(prog: &[u32]) -> Core {
+        let mut core = Core::new(
+            String::from("none"),
+            vec![],
+            XLen::X64,
+            0x8000_0000,
+            0x6b,
+            CoreConfig::default(),
+        );
+        core.load_bin_file(prog.iter().flat_map(|v| v.to_le_bytes()).collect()).unwrap();
+        core
+    }
+
+    // NOTE: li a0, 5; addi a0, a0, 1; auipc t0, 0; sd a0, 0x100(t0); the end inst
+    const PROG: [u32; 5] = [0x0050_0513, 0x0015_0513, 0x0000_0297, 0x10a2_b023, 0x6b];
+
+    #[test]
+    fn gdb_packets() {
+        let mut core = new_core(&PROG);
+        let mut stub = GdbStub::new();
+        let mut poll = || false;
+        let mut req = |core: &mut Core, pkt: &str| stub.handle(core, pkt, &mut poll).unwrap();
+
+        assert_eq!(req(&mut core, "?"), "S05");
+        assert_eq!(req(&mut core, "m80000000,4"), "13055000");
+        assert!(
+            GdbStub::target_xml(&core).contains("name=\"mstatus\" bitsize=\"64\" regnum=\"833\"")
+        );
+        assert_eq!(req(&mut core, "s"), "T05thread:1;");
+        assert_eq!(req(&mut core, "pa"), "0500000000000000");
+        assert_eq!(req(&mut core, "p20"), "0400008000000000");
+        // NOTE: mscratch is 65 + 0x340
+        assert_eq!(req(&mut core, "P381=0010008000000000"), "OK");
+        assert_eq!(req(&mut core, "p381"), "0010008000000000");
+        // NOTE: the breakpoint stops before the inst, the watchpoint after it
+        assert_eq!(req(&mut core, "Z0,80000008,4"), "OK");
+        assert_eq!(req(&mut core, "Z2,80000108,8"), "OK");
+        assert_eq!(req(&mut core, "c"), "T05thread:1;swbreak:;");
+        assert_eq!(req(&mut core, "c"), "T05thread:1;watch:80000108;");
+        assert_eq!(req(&mut core, "m80000108,2"), "0600");
+        assert_eq!(req(&mut core, "c"), "W06");
+    }
+
+    #[test]
+    fn gdb_framing() {
+        let mut buf = vec![];
+        send_packet(&mut buf, "OK").unwrap();
+        assert_eq!(buf, b"$OK#9a");
+        // NOTE: the checksum is over the escaped bytes
+        let mut buf = vec![];
+        send_packet(&mut buf, "a#b$c}d*").unwrap();
+        assert_eq!(buf, b"$a}\x03b}\x04c}]d}\x0a#ec");
+        let data = |v: &str| Packet::Data(v.to_string());
+        let mut reader = Cursor::new(buf);
+        assert_eq!(read_packet(&mut reader).unwrap(), data("a#b$c}d*"));
+        assert_eq!(read_packet(&mut reader).unwrap(), Packet::Closed);
+
+        // NOTE: the acks are skipped, the bad packet is dropped for the resend
+        let mut reader = Cursor::new(b"+$g#67-$g#00$?#3F\x03$m80000000,4#55$g".to_vec());
+        assert_eq!(read_packet(&mut reader).unwrap(), data("g"));
+        assert_eq!(read_packet(&mut reader).unwrap(), Packet::BadSum);
+        assert_eq!(read_packet(&mut reader).unwrap(), data("?"));
+        assert_eq!(read_packet(&mut reader).unwrap(), Packet::Break);
+        assert_eq!(read_packet(&mut reader).unwrap(), data("m80000000,4"));
+        // NOTE: the packet cut by the close
+        assert_eq!(read_packet(&mut reader).unwrap(), Packet::Closed);
+    }
+
+    #[test]
+    fn gdb_interrupt() {
+        // NOTE: j . never ends, the ctrl-c stops it at the poll
+        let mut core = new_core(&[0x0000_006f]);
+        let mut stub = GdbStub::new();
+        let mut polls = 0;
+        let mut poll = || {
+            polls += 1;
+            polls == 3
+        };
+        assert_eq!(
+            stub.handle(&mut core, "c", &mut poll).unwrap(),
+            "T02thread:1;"
+        );
+        assert_eq!(polls, 3);
+        assert_eq!(core.pc(), 0x8000_0000);
+        assert_eq!(core.inst_num(), 3 * 0x1000);
+    }
+
+    #[test]
+    fn gdb_points() {
+        let mut core = new_core(&PROG);
+        let mut stub = GdbStub::new();
+        let mut poll = || false;
+        let mut req = |core: &mut Core, pkt: &str| stub.handle(core, pkt, &mut poll).unwrap();
+
+        // NOTE: the point inserted twice is removed by one z
+        for pkt in [
+            "Z0,80000004,4",
+            "Z0,80000004,4",
+            "Z1,80000008,4",
+            "z0,80000004,4",
+        ] {
+            assert_eq!(req(&mut core, pkt), "OK");
+        }
+        assert_eq!(req(&mut core, "c"), "T05thread:1;swbreak:;");
+        assert_eq!(core.pc(), 0x8000_0008);
+        assert_eq!(req(&mut core, "z1,80000008,4"), "OK");
+        // NOTE: the read watchpoint does not stop the store, the access one does
+        assert_eq!(req(&mut core, "Z3,80000108,8"), "OK");
+        assert_eq!(req(&mut core, "Z4,8000010c,4"), "OK");
+        assert_eq!(req(&mut core, "c"), "T05thread:1;awatch:8000010c;");
+        assert_eq!(core.watchpoints_mut().len(), 2);
+        assert_eq!(req(&mut core, "z3,80000108,8"), "OK");
+        assert_eq!(req(&mut core, "z4,8000010c,4"), "OK");
+        assert!(core.watchpoints_mut().is_empty());
+        // NOTE: the unsupported or malformed point gets the empty reply
+        assert_eq!(req(&mut core, "Z5,80000000,4"), "");
+        assert_eq!(req(&mut core, "Z0,80000000"), "");
+        assert_eq!(req(&mut core, "c"), "W06");
+    }
+}
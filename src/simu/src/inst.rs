@@ -38,14 +38,19 @@ pub enum Inst {
     OR,
     AND,
     FENCE,
+    FENCEI,
     ECALL,
     EBREAK,
     CSRRW,
     CSRRS,
+    CSRRC,
     CSRRWI,
+    CSRRSI,
+    CSRRCI,
     URET,
     SRET,
     MRET,
+    WFI,
     SFENCEVMA,
     
     // RV32M
@@ -76,6 +81,96 @@ pub enum Inst {
     DIVUW,
     REMW,
     REMUW,
+    // RV32A
+    LRW,
+    SCW,
+    AMOSWAPW,
+    AMOADDW,
+    AMOXORW,
+    AMOANDW,
+    AMOORW,
+    AMOMINW,
+    AMOMAXW,
+    AMOMINUW,
+    AMOMAXUW,
+    // RV64A addition
+    LRD,
+    SCD,
+    AMOSWAPD,
+    AMOADDD,
+    AMOXORD,
+    AMOANDD,
+    AMOORD,
+    AMOMIND,
+    AMOMAXD,
+    AMOMINUD,
+    AMOMAXUD,
+    // RV32F
+    FLW,
+    FSW,
+    FMADDS,
+    FMSUBS,
+    FNMSUBS,
+    FNMADDS,
+    FADDS,
+    FSUBS,
+    FMULS,
+    FDIVS,
+    FSQRTS,
+    FSGNJS,
+    FSGNJNS,
+    FSGNJXS,
+    FMINS,
+    FMAXS,
+    FCVTWS,
+    FCVTWUS,
+    FMVXW,
+    FEQS,
+    FLTS,
+    FLES,
+    FCLASSS,
+    FCVTSW,
+    FCVTSWU,
+    FMVWX,
+    // RV64F addition
+    FCVTLS,
+    FCVTLUS,
+    FCVTSL,
+    FCVTSLU,
+    // RV32D
+    FLD,
+    FSD,
+    FMADDD,
+    FMSUBD,
+    FNMSUBD,
+    FNMADDD,
+    FADDD,
+    FSUBD,
+    FMULD,
+    FDIVD,
+    FSQRTD,
+    FSGNJD,
+    FSGNJND,
+    FSGNJXD,
+    FMIND,
+    FMAXD,
+    FCVTSD,
+    FCVTDS,
+    FEQD,
+    FLTD,
+    FLED,
+    FCLASSD,
+    FCVTWD,
+    FCVTWUD,
+    FCVTDW,
+    FCVTDWU,
+    // RV64D addition
+    FCVTLD,
+    FCVTLUD,
+    FMVXD,
+    FCVTDL,
+    FCVTDLU,
+    FMVDX,
 }
 
 pub enum InstType {
@@ -86,6 +181,7 @@ pub enum InstType {
     U,
     J,
     C,
+    F,
 }
 
 // enum Opcode {
@@ -133,12 +229,17 @@ pub fn get_inst_name(inst: &Inst) -> &'static str {
         Inst::AND => "AND",
         Inst::CSRRS => "CSRRS",
         Inst::CSRRW => "CSRRW",
+        Inst::CSRRC => "CSRRC",
         Inst::CSRRWI => "CSRRWI",
+        Inst::CSRRSI => "CSRRSI",
+        Inst::CSRRCI => "CSRRCI",
         Inst::URET => "URET",
         Inst::SRET => "SRET",
         Inst::MRET => "MRET",
+        Inst::WFI => "WFI",
         Inst::SFENCEVMA => "SFENCE_VMA",
         Inst::FENCE => "FENCE",
+        Inst::FENCEI => "FENCE_I",
         Inst::ECALL => "ECALL",
         Inst::EBREAK => "EBREAK",
         Inst::MUL => "MUL",
@@ -166,6 +267,90 @@ pub fn get_inst_name(inst: &Inst) -> &'static str {
         Inst::DIVUW => "DIVUW",
         Inst::REMW => "REMW",
         Inst::REMUW => "REMUW",
+        Inst::LRW => "LR_W",
+        Inst::SCW => "SC_W",
+        Inst::AMOSWAPW => "AMOSWAP_W",
+        Inst::AMOADDW => "AMOADD_W",
+        Inst::AMOXORW => "AMOXOR_W",
+        Inst::AMOANDW => "AMOAND_W",
+        Inst::AMOORW => "AMOOR_W",
+        Inst::AMOMINW => "AMOMIN_W",
+        Inst::AMOMAXW => "AMOMAX_W",
+        Inst::AMOMINUW => "AMOMINU_W",
+        Inst::AMOMAXUW => "AMOMAXU_W",
+        Inst::LRD => "LR_D",
+        Inst::SCD => "SC_D",
+        Inst::AMOSWAPD => "AMOSWAP_D",
+        Inst::AMOADDD => "AMOADD_D",
+        Inst::AMOXORD => "AMOXOR_D",
+        Inst::AMOANDD => "AMOAND_D",
+        Inst::AMOORD => "AMOOR_D",
+        Inst::AMOMIND => "AMOMIN_D",
+        Inst::AMOMAXD => "AMOMAX_D",
+        Inst::AMOMINUD => "AMOMINU_D",
+        Inst::AMOMAXUD => "AMOMAXU_D",
+        Inst::FLW => "FLW",
+        Inst::FSW => "FSW",
+        Inst::FMADDS => "FMADD_S",
+        Inst::FMSUBS => "FMSUB_S",
+        Inst::FNMSUBS => "FNMSUB_S",
+        Inst::FNMADDS => "FNMADD_S",
+        Inst::FADDS => "FADD_S",
+        Inst::FSUBS => "FSUB_S",
+        Inst::FMULS => "FMUL_S",
+        Inst::FDIVS => "FDIV_S",
+        Inst::FSQRTS => "FSQRT_S",
+        Inst::FSGNJS => "FSGNJ_S",
+        Inst::FSGNJNS => "FSGNJN_S",
+        Inst::FSGNJXS => "FSGNJX_S",
+        Inst::FMINS => "FMIN_S",
+        Inst::FMAXS => "FMAX_S",
+        Inst::FCVTWS => "FCVT_W_S",
+        Inst::FCVTWUS => "FCVT_WU_S",
+        Inst::FMVXW => "FMV_X_W",
+        Inst::FEQS => "FEQ_S",
+        Inst::FLTS => "FLT_S",
+        Inst::FLES => "FLE_S",
+        Inst::FCLASSS => "FCLASS_S",
+        Inst::FCVTSW => "FCVT_S_W",
+        Inst::FCVTSWU => "FCVT_S_WU",
+        Inst::FMVWX => "FMV_W_X",
+        Inst::FCVTLS => "FCVT_L_S",
+        Inst::FCVTLUS => "FCVT_LU_S",
+        Inst::FCVTSL => "FCVT_S_L",
+        Inst::FCVTSLU => "FCVT_S_LU",
+        Inst::FLD => "FLD",
+        Inst::FSD => "FSD",
+        Inst::FMADDD => "FMADD_D",
+        Inst::FMSUBD => "FMSUB_D",
+        Inst::FNMSUBD => "FNMSUB_D",
+        Inst::FNMADDD => "FNMADD_D",
+        Inst::FADDD => "FADD_D",
+        Inst::FSUBD => "FSUB_D",
+        Inst::FMULD => "FMUL_D",
+        Inst::FDIVD => "FDIV_D",
+        Inst::FSQRTD => "FSQRT_D",
+        Inst::FSGNJD => "FSGNJ_D",
+        Inst::FSGNJND => "FSGNJN_D",
+        Inst::FSGNJXD => "FSGNJX_D",
+        Inst::FMIND => "FMIN_D",
+        Inst::FMAXD => "FMAX_D",
+        Inst::FCVTSD => "FCVT_S_D",
+        Inst::FCVTDS => "FCVT_D_S",
+        Inst::FEQD => "FEQ_D",
+        Inst::FLTD => "FLT_D",
+        Inst::FLED => "FLE_D",
+        Inst::FCLASSD => "FCLASS_D",
+        Inst::FCVTWD => "FCVT_W_D",
+        Inst::FCVTWUD => "FCVT_WU_D",
+        Inst::FCVTDW => "FCVT_D_W",
+        Inst::FCVTDWU => "FCVT_D_WU",
+        Inst::FCVTLD => "FCVT_L_D",
+        Inst::FCVTLUD => "FCVT_LU_D",
+        Inst::FMVXD => "FMV_X_D",
+        Inst::FCVTDL => "FCVT_D_L",
+        Inst::FCVTDLU => "FCVT_D_LU",
+        Inst::FMVDX => "FMV_D_X",
     }
 }
 
@@ -184,6 +369,7 @@ pub fn get_instruction_type(inst: &Inst) -> InstType {
         | Inst::URET
         | Inst::SRET
         | Inst::MRET
+        | Inst::WFI
         | Inst::SFENCEVMA
         | Inst::MUL
         | Inst::MULH
@@ -202,7 +388,29 @@ pub fn get_instruction_type(inst: &Inst) -> InstType {
         | Inst::DIVW
         | Inst::DIVUW
         | Inst::REMW
-        | Inst::REMUW => InstType::R,
+        | Inst::REMUW
+        | Inst::LRW
+        | Inst::SCW
+        | Inst::AMOSWAPW
+        | Inst::AMOADDW
+        | Inst::AMOXORW
+        | Inst::AMOANDW
+        | Inst::AMOORW
+        | Inst::AMOMINW
+        | Inst::AMOMAXW
+        | Inst::AMOMINUW
+        | Inst::AMOMAXUW
+        | Inst::LRD
+        | Inst::SCD
+        | Inst::AMOSWAPD
+        | Inst::AMOADDD
+        | Inst::AMOXORD
+        | Inst::AMOANDD
+        | Inst::AMOORD
+        | Inst::AMOMIND
+        | Inst::AMOMAXD
+        | Inst::AMOMINUD
+        | Inst::AMOMAXUD => InstType::R,
         Inst::ADDI
         | Inst::SLTI
         | Inst::SLTIU
@@ -219,6 +427,7 @@ pub fn get_instruction_type(inst: &Inst) -> InstType {
         | Inst::LBU
         | Inst::LHU
         | Inst::FENCE
+        | Inst::FENCEI
         | Inst::LWU
         | Inst::LD
         | Inst::ADDIW 
@@ -231,6 +440,161 @@ pub fn get_instruction_type(inst: &Inst) -> InstType {
         Inst::BEQ | Inst::BNE | Inst::BLT | Inst::BGE | Inst::BLTU | Inst::BGEU => InstType::B,
         Inst::LUI | Inst::AUIPC => InstType::U,
         Inst::JAL => InstType::J,
-        Inst::CSRRS | Inst::CSRRW | Inst::CSRRWI => InstType::C,
+        Inst::CSRRS
+        | Inst::CSRRW
+        | Inst::CSRRC
+        | Inst::CSRRWI
+        | Inst::CSRRSI
+        | Inst::CSRRCI => InstType::C,
+        Inst::FLW
+        | Inst::FSW
+        | Inst::FMADDS
+        | Inst::FMSUBS
+        | Inst::FNMSUBS
+        | Inst::FNMADDS
+        | Inst::FADDS
+        | Inst::FSUBS
+        | Inst::FMULS
+        | Inst::FDIVS
+        | Inst::FSQRTS
+        | Inst::FSGNJS
+        | Inst::FSGNJNS
+        | Inst::FSGNJXS
+        | Inst::FMINS
+        | Inst::FMAXS
+        | Inst::FCVTWS
+        | Inst::FCVTWUS
+        | Inst::FMVXW
+        | Inst::FEQS
+        | Inst::FLTS
+        | Inst::FLES
+        | Inst::FCLASSS
+        | Inst::FCVTSW
+        | Inst::FCVTSWU
+        | Inst::FMVWX
+        | Inst::FCVTLS
+        | Inst::FCVTLUS
+        | Inst::FCVTSL
+        | Inst::FCVTSLU
+        | Inst::FLD
+        | Inst::FSD
+        | Inst::FMADDD
+        | Inst::FMSUBD
+        | Inst::FNMSUBD
+        | Inst::FNMADDD
+        | Inst::FADDD
+        | Inst::FSUBD
+        | Inst::FMULD
+        | Inst::FDIVD
+        | Inst::FSQRTD
+        | Inst::FSGNJD
+        | Inst::FSGNJND
+        | Inst::FSGNJXD
+        | Inst::FMIND
+        | Inst::FMAXD
+        | Inst::FCVTSD
+        | Inst::FCVTDS
+        | Inst::FEQD
+        | Inst::FLTD
+        | Inst::FLED
+        | Inst::FCLASSD
+        | Inst::FCVTWD
+        | Inst::FCVTWUD
+        | Inst::FCVTDW
+        | Inst::FCVTDWU
+        | Inst::FCVTLD
+        | Inst::FCVTLUD
+        | Inst::FMVXD
+        | Inst::FCVTDL
+        | Inst::FCVTDLU
+        | Inst::FMVDX => InstType::F,
+    }
+}
+
+// NOTE: the coarse class of the inst, the timing model assigns its latency by it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InstClass {
+    Alu,
+    Mul,
+    Div,
+    Load,
+    Store,
+    Branch,
+    Jump,
+    Amo,
+    Fp,
+    FpDiv,
+    Csr,
+    System,
+}
+
+pub const INST_CLASS_NUM: usize = 12;
+
+pub fn get_inst_class(inst: &Inst) -> InstClass {
+    match inst {
+        Inst::MUL | Inst::MULH | Inst::MULHSU | Inst::MULHU | Inst::MULW => InstClass::Mul,
+        Inst::DIV
+        | Inst::DIVU
+        | Inst::REM
+        | Inst::REMU
+        | Inst::DIVW
+        | Inst::DIVUW
+        | Inst::REMW
+        | Inst::REMUW => InstClass::Div,
+        Inst::LB
+        | Inst::LH
+        | Inst::LW
+        | Inst::LBU
+        | Inst::LHU
+        | Inst::LWU
+        | Inst::LD
+        | Inst::FLW
+        | Inst::FLD => InstClass::Load,
+        Inst::SB | Inst::SH | Inst::SW | Inst::SD | Inst::FSW | Inst::FSD => InstClass::Store,
+        Inst::BEQ | Inst::BNE | Inst::BLT | Inst::BGE | Inst::BLTU | Inst::BGEU => {
+            InstClass::Branch
+        }
+        Inst::JAL | Inst::JALR => InstClass::Jump,
+        Inst::LRW
+        | Inst::SCW
+        | Inst::AMOSWAPW
+        | Inst::AMOADDW
+        | Inst::AMOXORW
+        | Inst::AMOANDW
+        | Inst::AMOORW
+        | Inst::AMOMINW
+        | Inst::AMOMAXW
+        | Inst::AMOMINUW
+        | Inst::AMOMAXUW
+        | Inst::LRD
+        | Inst::SCD
+        | Inst::AMOSWAPD
+        | Inst::AMOADDD
+        | Inst::AMOXORD
+        | Inst::AMOANDD
+        | Inst::AMOORD
+        | Inst::AMOMIND
+        | Inst::AMOMAXD
+        | Inst::AMOMINUD
+        | Inst::AMOMAXUD => InstClass::Amo,
+        Inst::FDIVS | Inst::FSQRTS | Inst::FDIVD | Inst::FSQRTD => InstClass::FpDiv,
+        Inst::CSRRW
+        | Inst::CSRRS
+        | Inst::CSRRC
+        | Inst::CSRRWI
+        | Inst::CSRRSI
+        | Inst::CSRRCI => InstClass::Csr,
+        Inst::FENCE
+        | Inst::FENCEI
+        | Inst::ECALL
+        | Inst::EBREAK
+        | Inst::URET
+        | Inst::SRET
+        | Inst::MRET
+        | Inst::WFI
+        | Inst::SFENCEVMA => InstClass::System,
+        // NOTE: the rest of the fp insts, all are in the F/D part of the enum
+        _ if matches!(get_instruction_type(inst), InstType::F) => InstClass::Fp,
+        _ => InstClass::Alu,
     }
 }
@@ -4,13 +4,26 @@ pub mod data;
 pub mod regfile;
 pub mod inst;
 pub mod decode;
+pub mod disasm;
 pub mod exec;
 pub mod privilege;
 pub mod mmu;
+pub mod pmp;
+pub mod timing;
+pub mod bpred;
 pub mod csr;
+pub mod fpu;
+pub mod elf;
+pub mod cache;
+pub mod bus;
+pub mod mem;
 pub mod device;
 pub mod config;
 pub mod cli;
+pub mod debug;
+pub mod rev;
+pub mod expr;
+pub mod gdb;
 pub mod thrp;
 pub mod web;
 pub mod ws;
\ No newline at end of file
@@ -1,11 +1,17 @@
 use clap::Parser;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, Read};
 use std::sync::mpsc;
 use std::thread;
+use treecore_simu::bpred::parse_predictor;
+use treecore_simu::cache::CacheConfig;
 use treecore_simu::cli::Cli;
-use treecore_simu::config::XLen;
+use treecore_simu::config::{CoreConfig, MisalignMode, XLen};
 use treecore_simu::core::{Core, RunMode};
+use treecore_simu::gdb::gdb_setup;
+use treecore_simu::mem::DRAM_HIGH_BASE;
+use treecore_simu::mmu::AdMode;
+use treecore_simu::timing::{FixedLatency, TimingModel};
 use treecore_simu::web::web_setup;
 use treecore_simu::ws::ws_setup;
 
@@ -17,11 +23,15 @@ struct Args {
     #[clap(short, long, default_value = "none")]
     bin: String,
 
+    /// Path of the elf file to simulate, the pc starts from its entry
+    #[clap(long, default_value = "none")]
+    elf: String,
+
     /// Debug level[err, warn, trace, none]
     #[clap(short, long, default_value = "none")]
     debug: String,
 
-    /// Trace type(sub cmd under Debug level)[itrace, rtrace, etrace, ftrace, none]
+    /// Trace type(sub cmd under Debug level)[itrace, rtrace, etrace, ftrace, ctrace, none]
     #[clap(short, long, default_value = "none")]
     trace: Vec<String>,
 
@@ -37,6 +47,66 @@ struct Args {
     #[clap(short, long, default_value = "0x0000006b")]
     end_inst: String,
 
+    /// Size of the dram in MiB, the part beyond 512MiB is placed at 0x100000000
+    #[clap(short, long, default_value = "128")]
+    mem_size: u64,
+
+    /// Update of the pte A/D bits[svade, svadu]
+    #[clap(long, default_value = "svade")]
+    ad_mode: String,
+
+    /// Number of the tlb entries, 0 disables the tlb
+    #[clap(long, default_value = "64")]
+    tlb_entries: usize,
+
+    /// Associativity of the tlb
+    #[clap(long, default_value = "4")]
+    tlb_ways: usize,
+
+    /// Number of the pmp entries[0, 16, 64]
+    #[clap(long, default_value = "16")]
+    pmp_entries: usize,
+
+    /// Misaligned load/store[hw, trap]
+    #[clap(long, default_value = "hw")]
+    misalign: String,
+
+    /// Timing model with the latency overrides, like 'default' or 'mul=3,div=20,taken=2'
+    #[clap(long)]
+    timing: Option<String>,
+
+    /// Instruction cache, like 'default' or 'size=16k,line=32,ways=2,replace=plru,write=wt'
+    #[clap(long)]
+    icache: Option<String>,
+
+    /// Data cache, same format as the icache
+    #[clap(long)]
+    dcache: Option<String>,
+
+    /// Unified l2 cache behind the icache and the dcache, same format as the icache
+    #[clap(long)]
+    l2: Option<String>,
+
+    /// Pc range with its own cache stats, like '0x80000000-0x80001000'
+    #[clap(long)]
+    cache_range: Vec<String>,
+
+    /// Branch predictor with its table bits[static, bimodal, gshare, tage], like 'gshare,bits=12'
+    #[clap(long)]
+    bpred: Option<String>,
+
+    /// Entries of the return addr stack, used with the branch predictor
+    #[clap(long, default_value = "8")]
+    ras_entries: usize,
+
+    /// Insts between the snapshots of the reverse exec, enables reverse-stepi/continue
+    #[clap(long)]
+    rev_interval: Option<u64>,
+
+    /// Snapshots kept for the reverse exec, the oldest is dropped beyond it
+    #[clap(long, default_value = "64")]
+    rev_snaps: usize,
+
     /// Interactive mode
     #[clap(short, long)]
     inter: bool,
@@ -44,10 +114,43 @@ struct Args {
     /// Web server(http) for simulating keyboard and gpu online
     #[clap(short, long)]
     web: bool,
+
+    /// Wait for the gdb on the port instead of running
+    #[clap(long)]
+    gdb: Option<u16>,
+}
+
+fn parse_cache(spec: &str) -> CacheConfig {
+    match CacheConfig::parse(spec) {
+        Ok(v) => v,
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn parse_range(val: &str) -> (u64, u64) {
+    let addr = |v: &str| u64::from_str_radix(v.trim().trim_start_matches("0x"), 16).ok();
+    match val.split_once('-').map(|(lo, hi)| (addr(lo), addr(hi))) {
+        Some((Some(lo), Some(hi))) if lo < hi => (lo, hi),
+        _ => panic!("need to set the right format!, the right format: 0xXXXX-0xXXXX"),
+    }
+}
+
+fn arg_error(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
 }
 
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
+    // NOTE: the high part of the dram must end inside the 64-bit addr space
+    let mem_size = match args.mem_size.checked_mul(1024 * 1024) {
+        Some(v) if v > 0 && v.checked_add(DRAM_HIGH_BASE).is_some() => v,
+        _ => return Err(arg_error("the mem size must be non-zero and fit in the addr space")),
+    };
+    let start_addr =
+        match u64::from_str_radix(args.start_addr.as_str().trim_start_matches("0x"), 16) {
+            Ok(v) => v,
+            Err(_e) => panic!("need to set the right format!, the right format: 0xXXXX"),
+        };
     let mut core = Core::new(
         args.debug,
         args.trace,
@@ -56,26 +159,80 @@ fn main() -> std::io::Result<()> {
             "x64" => XLen::X64,
             _ => panic!(),
         },
-        match u64::from_str_radix(args.start_addr.as_str().trim_start_matches("0x"), 16) {
-            Ok(v) => v,
-            Err(_e) => panic!("need to set the right format!, the right format: 0xXXXX"),
-        },
+        start_addr,
         match u32::from_str_radix(args.end_inst.as_str().trim_start_matches("0x"), 16) {
             Ok(v) => v,
             Err(_e) => panic!("need to set the right format!, the right format: 0xXXXX"),
         },
+        CoreConfig {
+            mem_size,
+            ad_mode: match args.ad_mode.as_str() {
+                "svade" => AdMode::Svade,
+                "svadu" => AdMode::Svadu,
+                _ => panic!(),
+            },
+            tlb_entries: args.tlb_entries,
+            tlb_ways: args.tlb_ways,
+            pmp_entries: match args.pmp_entries {
+                0 | 16 | 64 => args.pmp_entries,
+                _ => panic!("the pmp entries can only be 0, 16 or 64"),
+            },
+            misalign: match args.misalign.as_str() {
+                "hw" => MisalignMode::Hardware,
+                "trap" => MisalignMode::Trap,
+                _ => panic!(),
+            },
+            timing: args.timing.map(|v| match FixedLatency::parse(&v) {
+                Ok(model) => Box::new(model) as Box<dyn TimingModel>,
+                Err(e) => panic!("{}", e),
+            }),
+            icache: args.icache.map(|v| parse_cache(&v)),
+            dcache: args.dcache.map(|v| parse_cache(&v)),
+            l2: args.l2.map(|v| parse_cache(&v)),
+            cache_ranges: args.cache_range.iter().map(|v| parse_range(v)).collect(),
+            bpred: args.bpred.map(|v| match parse_predictor(&v) {
+                Ok(v) => v,
+                Err(e) => panic!("{}", e),
+            }),
+            ras_entries: args.ras_entries,
+            rev_interval: args.rev_interval,
+            rev_snaps: args.rev_snaps,
+        },
     );
 
+    // NOTE: only the boot rom and the dram are mapped, the pc must start in one of them
+    if !core.bus_mut().is_mem(start_addr, 2) {
+        let msg = format!("the start addr {:#x} is not in the boot rom or the dram", start_addr);
+        return Err(arg_error(&msg));
+    }
+
     if args.inter {
         let mut cli = Cli::new();
         cli.inter_mode(&mut core);
         return Ok(());
     }
 
-    let mut file = File::open(args.bin)?;
-    let mut contents = vec![];
-    file.read_to_end(&mut contents)?;
-    core.load_bin_file(contents);
+    if args.elf != "none" {
+        let mut file = File::open(args.elf)?;
+        let mut contents = vec![];
+        file.read_to_end(&mut contents)?;
+        if let Err(e) = core.load_elf_file(contents) {
+            let msg = format!("elf load error: {}", e);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        }
+    } else {
+        let mut file = File::open(args.bin)?;
+        let mut contents = vec![];
+        file.read_to_end(&mut contents)?;
+        if let Err(e) = core.load_bin_file(contents) {
+            let msg = format!("bin load error: {}", e);
+            return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        }
+    }
+
+    if let Some(port) = args.gdb {
+        return gdb_setup(&mut core, port);
+    }
 
     if args.web {
         // println!("web");
@@ -0,0 +1,125 @@
+// NOTE: the phy memory layout of the board
+pub const BOOT_ROM_BASE: u64 = 0x1000u64;
+pub const BOOT_ROM_SIZE: u64 = 0x1_0000u64;
+pub const DRAM_BASE: u64 = 0x8000_0000u64;
+// NOTE: the dram ends before the device window at 0xa000_0000, the rest of a
+// large dram is placed above 4GiB
+pub const DRAM_LOW_SIZE: u64 = 0x2000_0000u64;
+pub const DRAM_HIGH_BASE: u64 = 0x1_0000_0000u64;
+
+pub const PAGE_SHIFT: u64 = 12;
+pub const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+
+pub type Page = Box<[u8; PAGE_SIZE]>;
+
+// NOTE: pages are allocated on the first write, the untouched ones read zero,
+// so a large dram only costs the page table
+pub struct SparseMem {
+    size: u64,
+    pages: Vec<Option<Page>>,
+}
+
+impl SparseMem {
+    pub fn new(size: u64) -> Self {
+        let page_num = ((size + PAGE_SIZE as u64 - 1) >> PAGE_SHIFT) as usize;
+        SparseMem {
+            size,
+            pages: vec![None; page_num],
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn clear(&mut self) {
+        self.pages.fill(None);
+    }
+
+    fn split(offset: u64) -> (usize, usize) {
+        ((offset >> PAGE_SHIFT) as usize, offset as usize & (PAGE_SIZE - 1))
+    }
+
+    fn page_mut(&mut self, idx: usize) -> &mut Page {
+        self.pages[idx].get_or_insert_with(|| Box::new([0u8; PAGE_SIZE]))
+    }
+
+    // NOTE: the caller keeps [offset, offset + size) inside the mem
+    pub fn read(&self, offset: u64, size: u64) -> u64 {
+        let (idx, off) = SparseMem::split(offset);
+        let len = size as usize;
+        if off + len > PAGE_SIZE {
+            let mut res = 0u64;
+            for i in 0..size {
+                res |= self.read(offset + i, 1) << (8 * i);
+            }
+            return res;
+        }
+
+        match &self.pages[idx] {
+            Some(page) => {
+                let mut buf = [0u8; 8];
+                buf[..len].copy_from_slice(&page[off..off + len]);
+                u64::from_le_bytes(buf)
+            }
+            None => 0u64,
+        }
+    }
+
+    pub fn write(&mut self, offset: u64, size: u64, val: u64) {
+        let (idx, off) = SparseMem::split(offset);
+        let len = size as usize;
+        if off + len > PAGE_SIZE {
+            for i in 0..size {
+                self.write(offset + i, 1, val >> (8 * i));
+            }
+            return;
+        }
+
+        self.page_mut(idx)[off..off + len].copy_from_slice(&val.to_le_bytes()[..len]);
+    }
+
+    // NOTE: a copy of the page which holds offset, None if it is untouched
+    pub fn page(&self, offset: u64) -> Option<Page> {
+        self.pages[SparseMem::split(offset).0].clone()
+    }
+
+    pub fn set_page(&mut self, offset: u64, page: Option<Page>) {
+        let (idx, _) = SparseMem::split(offset);
+        self.pages[idx] = page;
+    }
+
+    // NOTE: bulk copy page by page for the loader
+    pub fn load(&mut self, offset: u64, data: &[u8]) {
+        let mut pos = 0usize;
+        while pos < data.len() {
+            let (idx, off) = SparseMem::split(offset + pos as u64);
+            let len = (PAGE_SIZE - off).min(data.len() - pos);
+            self.page_mut(idx)[off..off + len].copy_from_slice(&data[pos..pos + len]);
+            pos += len;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mem::SparseMem;
+
+    #[test]
+    fn sparse_mem() {
+        // NOTE: 4GiB only allocates the touched pages
+        let mut dut = SparseMem::new(0x1_0000_0000);
+        assert_eq!(dut.read(0xFFFF_FFF8, 8), 0);
+        dut.write(0x1FFC, 8, 0x1122_3344_5566_7788);
+        assert_eq!(dut.read(0x1FFC, 4), 0x5566_7788);
+        assert_eq!(dut.read(0x2000, 4), 0x1122_3344);
+        assert_eq!(dut.read(0x1FFE, 4), 0x3344_5566);
+        dut.load(0x2FFF, &[0xAA, 0xBB, 0xCC]);
+        assert_eq!(dut.read(0x2FFF, 2), 0xBBAA);
+        assert_eq!(dut.read(0x3001, 1), 0xCC);
+        assert_eq!(dut.pages.iter().filter(|v| v.is_some()).count(), 3);
+        dut.clear();
+        assert_eq!(dut.read(0x1FFC, 8), 0);
+        assert!(dut.pages.iter().all(|v| v.is_none()));
+    }
+}
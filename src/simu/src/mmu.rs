@@ -1,21 +1,250 @@
+use crate::privilege::ExceptionType;
+
+#[derive(Clone, Copy)]
 pub enum AddrMode {
     None,
     SV32,
     SV39,
     SV48,
+    SV57,
 }
 
+#[derive(Clone, Copy)]
 pub enum MAType {
     Exec,
     Read,
     Write,
 }
 
+impl MAType {
+    pub fn page_fault(&self) -> ExceptionType {
+        match self {
+            MAType::Exec => ExceptionType::InstPageFault,
+            MAType::Read => ExceptionType::LoadPageFault,
+            MAType::Write => ExceptionType::StorePageFault,
+        }
+    }
+
+    pub fn misaligned(&self) -> ExceptionType {
+        match self {
+            MAType::Exec => ExceptionType::InstAddrMisaligned,
+            MAType::Read => ExceptionType::LoadAddrMisaligned,
+            MAType::Write => ExceptionType::StoreAddrMisaligned,
+        }
+    }
+
+    pub fn access_fault(&self) -> ExceptionType {
+        match self {
+            MAType::Exec => ExceptionType::InstAccessFault,
+            MAType::Read => ExceptionType::LoadAccessFault,
+            MAType::Write => ExceptionType::StoreAccessFault,
+        }
+    }
+
+    // NOTE: the A/D bits of the leaf pte which the access needs
+    pub fn ad_bits(&self) -> u64 {
+        match self {
+            MAType::Write => PTE_A | PTE_D,
+            _ => PTE_A,
+        }
+    }
+}
+
+// NOTE: how the A/D bits of the leaf pte are handled, Svade raises a page fault
+// when the bit needs to be set, Svadu sets it in the page walk
+#[derive(Clone, Copy, Debug)]
+pub enum AdMode {
+    Svade,
+    Svadu,
+}
+
+pub const PTE_V: u64 = 1 << 0;
+pub const PTE_R: u64 = 1 << 1;
+pub const PTE_W: u64 = 1 << 2;
+pub const PTE_X: u64 = 1 << 3;
+pub const PTE_U: u64 = 1 << 4;
+pub const PTE_G: u64 = 1 << 5;
+pub const PTE_A: u64 = 1 << 6;
+pub const PTE_D: u64 = 1 << 7;
+
+// NOTE: the shape of the page table, (levels, vpn bits of each level, pte size)
+pub fn page_table_shape(mode: &AddrMode) -> (u64, u64, u64) {
+    match mode {
+        AddrMode::SV32 => (2, 10, 4),
+        AddrMode::SV39 => (3, 9, 8),
+        AddrMode::SV48 => (4, 9, 8),
+        AddrMode::SV57 => (5, 9, 8),
+        AddrMode::None => (0, 0, 0),
+    }
+}
+
 pub fn get_addr_mode_name(mode: &AddrMode) -> &'static str {
     match mode {
         AddrMode::None => "None",
         AddrMode::SV32 => "SV32",
         AddrMode::SV39 => "SV39",
         AddrMode::SV48 => "SV48",
+        AddrMode::SV57 => "SV57",
+    }
+}
+
+// NOTE: one cached leaf translation, the page_mask covers the page offset of
+// the 4KiB page or the superpage
+#[derive(Clone, Copy)]
+struct TlbEntry {
+    va_base: u64,
+    pa_base: u64,
+    page_mask: u64,
+    asid: u16,
+    global: bool,
+    pte: u64,
+    lru: u64,
+}
+
+// NOTE: the set is indexed by the 4KiB vpn, a superpage is cached in the set
+// of the vpn which misses, so the fence by addr scans all the sets
+pub struct Tlb {
+    sets: usize,
+    ways: usize,
+    entries: Vec<Option<TlbEntry>>,
+    tick: u64,
+    pub hit: u64,
+    pub miss: u64,
+    pub flush: u64,
+}
+
+impl Tlb {
+    // NOTE: 0 entries disable the tlb, every translation walks the page table
+    pub fn new(entries: usize, ways: usize) -> Self {
+        let ways = ways.clamp(1, entries.max(1));
+        Tlb {
+            sets: entries / ways,
+            ways,
+            entries: vec![None; entries / ways * ways],
+            tick: 0u64,
+            hit: 0u64,
+            miss: 0u64,
+            flush: 0u64,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.entries.fill(None);
+        self.tick = 0u64;
+        self.hit = 0u64;
+        self.miss = 0u64;
+        self.flush = 0u64;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.sets != 0
+    }
+
+    fn set_range(&self, va: u64) -> std::ops::Range<usize> {
+        let set = ((va >> 12) as usize) % self.sets;
+        set * self.ways..(set + 1) * self.ways
+    }
+
+    // NOTE: return the pa base, the page mask and the cached leaf pte
+    pub fn lookup(&mut self, va: u64, asid: u16) -> Option<(u64, u64, u64)> {
+        if !self.enabled() {
+            return None;
+        }
+        self.tick += 1;
+        let range = self.set_range(va);
+        for v in self.entries[range].iter_mut().flatten() {
+            if va & !v.page_mask == v.va_base && (v.global || v.asid == asid) {
+                v.lru = self.tick;
+                self.hit += 1;
+                return Some((v.pa_base, v.page_mask, v.pte));
+            }
+        }
+        self.miss += 1;
+        None
+    }
+
+    // NOTE: the invalid way is used first, then the least recently used one
+    pub fn insert(&mut self, va: u64, pa_base: u64, page_mask: u64, asid: u16, pte: u64) {
+        if !self.enabled() {
+            return;
+        }
+        let range = self.set_range(va);
+        let va_base = va & !page_mask;
+        let victim = range
+            .clone()
+            .find(|&i| match self.entries[i] {
+                Some(v) => v.va_base == va_base && v.page_mask == page_mask && v.asid == asid,
+                None => true,
+            })
+            .unwrap_or_else(|| {
+                range
+                    .min_by_key(|&i| self.entries[i].map_or(0, |v| v.lru))
+                    .unwrap()
+            });
+        self.entries[victim] = Some(TlbEntry {
+            va_base,
+            pa_base,
+            page_mask,
+            asid,
+            global: pte & PTE_G != 0,
+            pte,
+            lru: self.tick,
+        });
+    }
+
+    // NOTE: sfence.vma, rs1 == x0 matches all addrs, rs2 == x0 matches all
+    // asids, the global entries are only flushed when all asids match
+    pub fn fence(&mut self, va: Option<u64>, asid: Option<u16>) {
+        self.flush += 1;
+        for v in self.entries.iter_mut() {
+            let hit = match v {
+                Some(e) => {
+                    va.is_none_or(|va| va & !e.page_mask == e.va_base)
+                        && asid.is_none_or(|asid| !e.global && e.asid == asid)
+                }
+                None => false,
+            };
+            if hit {
+                *v = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mmu::{Tlb, PTE_G};
+
+    #[test]
+    fn tlb_lru() {
+        // NOTE: 2 sets of 2 ways, the even vpns share set 0
+        let mut dut = Tlb::new(4, 2);
+        dut.insert(0x0000, 0x8000_0000, 0xFFF, 1, 0);
+        dut.insert(0x2000, 0x8000_1000, 0xFFF, 1, 0);
+        assert!(dut.lookup(0x0010, 1).is_some());
+        dut.insert(0x4000, 0x8000_2000, 0xFFF, 1, 0);
+        assert_eq!(dut.lookup(0x0010, 1), Some((0x8000_0000, 0xFFF, 0)));
+        assert_eq!(dut.lookup(0x2000, 1), None);
+        assert_eq!(dut.lookup(0x4000, 2), None);
+        assert_eq!((dut.hit, dut.miss), (2, 2));
+    }
+
+    #[test]
+    fn tlb_fence() {
+        let mut dut = Tlb::new(16, 4);
+        dut.insert(0x1000, 0x8000_1000, 0xFFF, 1, 0);
+        dut.insert(0x2000, 0x8000_2000, 0xFFF, 2, 0);
+        dut.insert(0x20_0000, 0x8020_0000, 0x1F_FFFF, 1, PTE_G);
+        // NOTE: the global page survives the fence by asid
+        dut.fence(None, Some(1));
+        assert!(dut.lookup(0x1000, 1).is_none());
+        assert!(dut.lookup(0x2000, 2).is_some());
+        assert!(dut.lookup(0x20_0000, 3).is_some());
+        // NOTE: the fence by addr hits the superpage which covers it
+        dut.fence(Some(0x3F_F000), None);
+        assert!(dut.lookup(0x20_0000, 1).is_none());
+        dut.fence(None, None);
+        assert!(dut.lookup(0x2000, 2).is_none());
+        assert_eq!(dut.flush, 3);
     }
 }
@@ -0,0 +1,142 @@
+use crate::csr::{CsrFile, CSR_PMPADDR0_ADDR};
+use crate::mmu::MAType;
+
+pub const PMP_R: u64 = 1 << 0;
+pub const PMP_W: u64 = 1 << 1;
+pub const PMP_X: u64 = 1 << 2;
+pub const PMP_A_MASK: u64 = 0x3 << 3;
+pub const PMP_L: u64 = 1 << 7;
+
+pub const PMP_A_OFF: u64 = 0 << 3;
+pub const PMP_A_TOR: u64 = 1 << 3;
+pub const PMP_A_NA4: u64 = 2 << 3;
+pub const PMP_A_NAPOT: u64 = 3 << 3;
+
+// NOTE: the decoded range [lo, hi) of one entry, an empty range never matches
+#[derive(Clone, Copy, Default)]
+struct PmpEntry {
+    lo: u64,
+    hi: u64,
+    cfg: u64,
+}
+
+// NOTE: pmpcfg and pmpaddr are kept in the csr file, the ranges are decoded
+// again after each write of them
+pub struct Pmp {
+    entries: Vec<PmpEntry>,
+    locked: bool, // any entry is locked, m-mode is checked only with it
+}
+
+impl Pmp {
+    // NOTE: 0, 16 or 64 entries, 0 means no pmp and all accesses pass
+    pub fn new(num: usize) -> Self {
+        Pmp {
+            entries: vec![PmpEntry::default(); num],
+            locked: false,
+        }
+    }
+
+    pub fn num(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn update(&mut self, csr: &CsrFile) {
+        let mut prev = 0u64;
+        for (i, v) in self.entries.iter_mut().enumerate() {
+            let cfg = csr.pmp_cfg(i);
+            let addr = csr.get(CSR_PMPADDR0_ADDR + i as u16);
+            let (lo, hi) = match cfg & PMP_A_MASK {
+                PMP_A_TOR => (prev << 2, (addr << 2).max(prev << 2)),
+                PMP_A_NA4 => (addr << 2, (addr << 2) + 4),
+                PMP_A_NAPOT => {
+                    // NOTE: the trailing ones encode the size, 2^(ones + 3) bytes
+                    let ones = addr.trailing_ones();
+                    let lo = (addr & !((1u64 << ones) - 1)) << 2;
+                    (lo, lo + (1u64 << (ones + 3)))
+                }
+                _ => (0, 0),
+            };
+            *v = PmpEntry { lo, hi, cfg };
+            prev = addr;
+        }
+        self.locked = self.entries.iter().any(|v| v.cfg & PMP_L != 0);
+    }
+
+    // NOTE: the lowest-numbered entry which matches any byte of the access
+    // decides, the access across its boundary fails. Without a match only
+    // m-mode passes
+    pub fn check(&self, addr: u64, size: u64, ma_type: MAType, priv_val: u8) -> bool {
+        if self.entries.is_empty() || (priv_val == 3 && !self.locked) {
+            return true;
+        }
+        let end = addr.saturating_add(size);
+        for v in self.entries.iter() {
+            if v.lo >= v.hi || end <= v.lo || addr >= v.hi {
+                continue;
+            }
+            if addr < v.lo || end > v.hi {
+                return false;
+            }
+            if priv_val == 3 && v.cfg & PMP_L == 0 {
+                return true;
+            }
+            let perm = match ma_type {
+                MAType::Exec => PMP_X,
+                MAType::Read => PMP_R,
+                MAType::Write => PMP_W,
+            };
+            return v.cfg & perm != 0;
+        }
+        priv_val == 3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::XLen;
+    use crate::csr::{CsrFile, CSR_PMPADDR0_ADDR, CSR_PMPCFG0_ADDR};
+    use crate::mmu::MAType;
+    use crate::pmp::*;
+
+    #[test]
+    fn pmp_match() {
+        let mut csr = CsrFile::new(XLen::X64);
+        csr.set_pmp_num(16);
+        let mut dut = Pmp::new(16);
+        // NOTE: 0: NA4 r at 0x8000_0000, 1: TOR x in [0x8000_0000, 0x8000_1000),
+        // 2: NAPOT rw of 64KiB at 0x8001_0000
+        csr.write(CSR_PMPADDR0_ADDR, 0x8000_0000 >> 2);
+        csr.write(CSR_PMPADDR0_ADDR + 1, 0x8000_1000 >> 2);
+        csr.write(CSR_PMPADDR0_ADDR + 2, (0x8001_0000 >> 2) | 0x1FFF);
+        let cfg =
+            (PMP_A_NA4 | PMP_R) | (PMP_A_TOR | PMP_X) << 8 | (PMP_A_NAPOT | PMP_R | PMP_W) << 16;
+        csr.write(CSR_PMPCFG0_ADDR, cfg);
+        dut.update(&csr);
+
+        assert!(dut.check(0x8000_0000, 4, MAType::Read, 1));
+        assert!(!dut.check(0x8000_0000, 4, MAType::Exec, 1));
+        // NOTE: the access partially in entry 0 fails, even if entry 1 covers it
+        assert!(!dut.check(0x8000_0002, 4, MAType::Exec, 0));
+        assert!(dut.check(0x8000_0004, 4, MAType::Exec, 0));
+        assert!(dut.check(0x8001_FFF8, 8, MAType::Write, 1));
+        assert!(!dut.check(0x8002_0000, 1, MAType::Read, 1));
+        // NOTE: m-mode ignores the unlocked entries
+        assert!(dut.check(0x8000_0004, 4, MAType::Write, 3));
+
+        // NOTE: the locked entry binds m-mode and ignores the later write,
+        // the locked TOR entry also locks the pmpaddr below it
+        csr.write(CSR_PMPCFG0_ADDR, cfg | PMP_L << 8);
+        dut.update(&csr);
+        assert!(!dut.check(0x8000_0004, 4, MAType::Write, 3));
+        csr.write(CSR_PMPCFG0_ADDR, 0);
+        csr.write(CSR_PMPADDR0_ADDR, 0);
+        assert_eq!(csr.read(CSR_PMPCFG0_ADDR), (PMP_A_TOR | PMP_X | PMP_L) << 8);
+        assert_eq!(csr.read(CSR_PMPADDR0_ADDR), 0x8000_0000 >> 2);
+        // NOTE: R=0 W=1 is reserved, and the entries beyond the number read zero
+        csr.write(CSR_PMPCFG0_ADDR + 2, PMP_W | PMP_A_NA4);
+        assert_eq!(csr.read(CSR_PMPCFG0_ADDR + 2), PMP_A_NA4);
+        csr.set_pmp_num(0);
+        csr.write(CSR_PMPADDR0_ADDR + 5, 0x1234);
+        assert_eq!(csr.read(CSR_PMPADDR0_ADDR + 5), 0);
+    }
+}
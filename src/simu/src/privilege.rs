@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug)]
 pub enum PrivMode {
     User,
     Supervisor,
@@ -6,17 +6,25 @@ pub enum PrivMode {
     Machine,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum ExceptionType {
     EnvCallFromMMode,
     EnvCallFromUMode,
     EnvCallFromSMode,
     IllegalInst,
+    Breakpoint,
+    InstAddrMisaligned,
+    LoadAddrMisaligned,
+    StoreAddrMisaligned,
     InstPageFault,
     LoadPageFault,
     StorePageFault,
+    InstAccessFault,
+    LoadAccessFault,
+    StoreAccessFault,
 }
 
+#[derive(Debug)]
 pub struct Exception {
     pub excpt_type: ExceptionType,
     pub addr: u64,
@@ -43,12 +51,19 @@ pub fn get_priv_encoding(mode: &PrivMode) -> u8 {
 
 pub fn get_exception_cause(exception: &Exception) -> u64 {
     match exception.excpt_type {
+        ExceptionType::InstAddrMisaligned => 0,
         ExceptionType::IllegalInst => 2,
+        ExceptionType::Breakpoint => 3,
+        ExceptionType::LoadAddrMisaligned => 4,
+        ExceptionType::StoreAddrMisaligned => 6,
         ExceptionType::EnvCallFromUMode => 8,
         ExceptionType::EnvCallFromSMode => 9,
         ExceptionType::EnvCallFromMMode => 11,
         ExceptionType::InstPageFault => 12,
         ExceptionType::LoadPageFault => 13,
         ExceptionType::StorePageFault => 15,
+        ExceptionType::InstAccessFault => 1,
+        ExceptionType::LoadAccessFault => 5,
+        ExceptionType::StoreAccessFault => 7,
     }
 }
@@ -59,3 +59,45 @@ impl Regfile {
         }
     }
 }
+
+// NOTE: f regs are 64-bit, single-precision values are NaN-boxed
+pub struct FRegfile {
+    pub f: [u64; 32],
+    pub alias: HashMap<String, u8>,
+}
+
+impl FRegfile {
+    pub fn new() -> Self {
+        let mut res = FRegfile {
+            f: [0u64; 32],
+            alias: HashMap::new(),
+        };
+
+        for i in 0..8 {
+            res.alias.insert(format!("ft{}", i), i);
+        }
+        res.alias.insert("fs0".to_string(), 8);
+        res.alias.insert("fs1".to_string(), 9);
+        for i in 0..8 {
+            res.alias.insert(format!("fa{}", i), 10 + i);
+        }
+        for i in 2..12 {
+            res.alias.insert(format!("fs{}", i), 16 + i);
+        }
+        for i in 8..12 {
+            res.alias.insert(format!("ft{}", i), 20 + i);
+        }
+        res
+    }
+
+    pub fn reset(&mut self) {
+        self.f = [0u64; 32];
+    }
+
+    pub fn val(&self, v: &str) -> u64 {
+        match self.alias.get(v) {
+            Some(&idx) => self.f[idx as usize],
+            _ => panic!(),
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use crate::bpred::BranchPred;
+use crate::bus::UndoLog;
+use crate::cache::CacheSys;
+use crate::csr::CsrFile;
+use crate::device::DevState;
+use crate::mmu::AddrMode;
+use crate::privilege::PrivMode;
+use crate::timing::TimingState;
+use std::collections::VecDeque;
+
+// NOTE: the reverse exec goes back to the last snapshot before the target and
+// replays forward to it. The mem of a snapshot is not copied, the undo log of
+// the pages written after it brings the mem back
+
+// NOTE: the arch state, the device state and the timing, cache and bpred
+// models at a snapshot, so the replay counts the same cycles and stats. The
+// tlb is flushed on restore
+#[derive(Clone)]
+pub struct CpuState {
+    pub x: [i64; 32],
+    pub f: [u64; 32],
+    pub pc: u64,
+    pub inst_pc: u64,
+    pub inst_raw: u32,
+    pub ppn: u64,
+    pub asid: u16,
+    pub priv_mode: PrivMode,
+    pub addr_mode: AddrMode,
+    pub reservation: Option<u64>,
+    pub inst_num: u64,
+    pub csr: CsrFile,
+    pub dev: DevState,
+    pub timing: Option<TimingState>,
+    pub cache: Option<CacheSys>,
+    pub bpred: Option<BranchPred>,
+}
+
+struct Snapshot {
+    state: CpuState,
+    undo: UndoLog, // the pre-image of the pages written until the next one
+}
+
+pub struct History {
+    interval: u64,
+    limit: usize,
+    snaps: VecDeque<Snapshot>,
+}
+
+impl History {
+    // NOTE: a snapshot every 'interval' insts, the oldest one is dropped
+    // beyond 'limit' snapshots
+    pub fn new(interval: u64, limit: usize) -> Self {
+        History {
+            interval: interval.max(1),
+            limit: limit.max(1),
+            snaps: VecDeque::new(),
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.snaps.clear();
+    }
+
+    pub fn due(&self, inst_num: u64) -> bool {
+        self.snaps
+            .back()
+            .is_none_or(|v| inst_num >= v.state.inst_num + self.interval)
+    }
+
+    // NOTE: 'undo' is the log since the last snapshot, it is closed by the new one
+    pub fn push(&mut self, state: CpuState, undo: UndoLog) {
+        if let Some(v) = self.snaps.back_mut() {
+            v.undo = undo;
+        }
+        self.snaps.push_back(Snapshot {
+            state,
+            undo: vec![],
+        });
+        if self.snaps.len() > self.limit {
+            self.snaps.pop_front();
+        }
+    }
+
+    // NOTE: the inst num of the oldest state which can be reached
+    pub fn start(&self) -> Option<u64> {
+        self.snaps.front().map(|v| v.state.inst_num)
+    }
+
+    // NOTE: the last snapshot before inst_num
+    pub fn before(&self, inst_num: u64) -> Option<u64> {
+        self.snaps
+            .iter()
+            .rev()
+            .map(|v| v.state.inst_num)
+            .find(|v| *v < inst_num)
+    }
+
+    // NOTE: drop the snapshots after the last one at or before inst_num, and
+    // return its state with the undo log back to it, the later pages last.
+    // 'undo' is the log since the last snapshot
+    pub fn rewind(&mut self, inst_num: u64, undo: UndoLog) -> Option<(CpuState, UndoLog)> {
+        let idx = self
+            .snaps
+            .iter()
+            .rposition(|v| v.state.inst_num <= inst_num)?;
+        let mut res = vec![];
+        for v in self.snaps.iter_mut().skip(idx) {
+            res.append(&mut v.undo);
+        }
+        res.extend(undo);
+        self.snaps.truncate(idx + 1);
+        Some((self.snaps[idx].state.clone(), res))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bpred::{BranchStat, Gshare};
+    use crate::cache::CacheConfig;
+    use crate::config::{CoreConfig, XLen};
+    use crate::core::Core;
+    use crate::debug::{Debugger, StopReason};
+    use crate::expr::eval;
+    use crate::timing::FixedLatency;
+
+    #[test]
+    fn reverse_exec() {
+        let mut core = Core::new(
+            String::from("none"),
+            vec![],
+            XLen::X64,
+            0x8000_0000,
+            0x6b,
+            CoreConfig {
+                rev_interval: Some(4),
+                rev_snaps: 3,
+                ..CoreConfig::default()
+            },
+        );
+        // NOTE: a0 counts the loops and is stored at 0x80000100
+        core.load_bin_file(vec![
+            0x97, 0x02, 0x00, 0x00, // auipc t0, 0
+            0x13, 0x05, 0x15, 0x00, // addi a0, a0, 1
+            0x23, 0xa0, 0xa2, 0x10, // sw a0, 256(t0)
+            0x6f, 0xf0, 0x9f, 0xff, // j -8
+        ]).unwrap();
+        let mut dbg = Debugger::new();
+        for _ in 0..19 {
+            core.step();
+        }
+        let word = |core: &mut Core| core.debug_read(0x8000_0100, 4).unwrap()[0];
+        // NOTE: 6 loops and the auipc
+        assert_eq!((core.reg().x[10], word(&mut core)), (6, 6));
+        assert_eq!(dbg.reverse(&mut core, Some(1)), StopReason::Step);
+        assert_eq!((core.inst_num(), core.pc()), (18, 0x8000_000c));
+        assert_eq!((core.reg().x[10], word(&mut core)), (6, 6));
+        assert_eq!(dbg.reverse(&mut core, Some(4)), StopReason::Step);
+        assert_eq!((core.reg().x[10], word(&mut core)), (5, 4));
+
+        dbg.breakpoints.push(0x8000_0008);
+        assert_eq!(dbg.reverse(&mut core, None), StopReason::Breakpoint);
+        assert_eq!(
+            (core.inst_num(), core.reg().x[10], word(&mut core)),
+            (11, 4, 3)
+        );
+        // NOTE: 3 snapshots of 4 insts are kept, the history starts at 8
+        assert_eq!(dbg.reverse(&mut core, None), StopReason::HistoryBegin);
+        assert_eq!(
+            (core.inst_num(), core.reg().x[10], word(&mut core)),
+            (8, 3, 2)
+        );
+        assert_eq!(
+            dbg.resume(&mut core, false, || false),
+            StopReason::Breakpoint
+        );
+        assert_eq!((core.inst_num(), word(&mut core)), (11, 3));
+    }
+
+    #[test]
+    fn reverse_exec_timing() {
+        let mut core = Core::new(
+            String::from("none"),
+            vec![],
+            XLen::X64,
+            0x8000_0000,
+            0x6b,
+            CoreConfig {
+                timing: Some(Box::new(FixedLatency::default())),
+                icache: Some(CacheConfig::default()),
+                dcache: Some(CacheConfig::default()),
+                bpred: Some(Box::new(Gshare::new(4))),
+                rev_interval: Some(4),
+                rev_snaps: 8,
+                ..CoreConfig::default()
+            },
+        );
+        // NOTE: the loop of the counter store and the taken branch
+        core.load_bin_file(vec![
+            0x97, 0x02, 0x00, 0x00, // auipc t0, 0
+            0x13, 0x05, 0x15, 0x00, // addi a0, a0, 1
+            0x23, 0xa0, 0xa2, 0x10, // sw a0, 256(t0)
+            0xe3, 0x1c, 0x05, 0xfe, // bnez a0, -8
+        ]).unwrap();
+        let stats = |core: &mut Core| -> (u64, String, String, Option<BranchStat>) {
+            (
+                eval(core, "$mcycle").unwrap().val,
+                core.timing().unwrap().report(),
+                core.cache().unwrap().report(),
+                core.bpred().unwrap().stat_of(0x8000_000c),
+            )
+        };
+        let dbg = Debugger::new();
+        for _ in 0..10 {
+            core.step();
+        }
+        let mid = stats(&mut core);
+        for _ in 0..11 {
+            core.step();
+        }
+        let end = stats(&mut core);
+        assert!(end.0 > mid.0);
+
+        // NOTE: the models go back with the arch state, the replay counts the
+        // same cycles and stats again
+        assert_eq!(dbg.reverse(&mut core, Some(11)), StopReason::Step);
+        assert_eq!(core.inst_num(), 10);
+        assert_eq!(stats(&mut core), mid);
+        for _ in 0..11 {
+            core.step();
+        }
+        assert_eq!(stats(&mut core), end);
+        assert_eq!(dbg.reverse(&mut core, Some(1)), StopReason::Step);
+        core.step();
+        assert_eq!(stats(&mut core), end);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::inst::{InstClass, INST_CLASS_NUM};
+
+// NOTE: where the cycles beyond the base one of each inst go
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stall {
+    Exec,       // multi-cycle units, mul/div/fp/amo
+    Mem,        // load-use and the store
+    Control,    // the redirect of the taken branch and the jump
+    Cache,      // the cache miss
+    Mispredict, // the branch predictor miss
+    Trap,       // the pipeline flush of the exception and the interrupt
+}
+
+const STALL_NUM: usize = 6;
+const STALL_NAME: [&str; STALL_NUM] = ["exec", "mem", "control", "cache", "mispredict", "trap"];
+
+// NOTE: the pluggable part, a model maps the inst to its cycles, the
+// penalties are charged by the core when the event happens
+pub trait TimingModel {
+    fn name(&self) -> &str;
+    // NOTE: the cycles of the retired inst beyond the base one, and the stall
+    // they are charged to
+    fn inst_stall(&self, class: InstClass, taken: bool) -> (Stall, u64);
+    fn trap_penalty(&self) -> u64;
+    fn miss_penalty(&self) -> u64;
+    fn mispredict_penalty(&self) -> u64;
+}
+
+// NOTE: a fixed latency for each inst class, like a simple in-order pipeline
+pub struct FixedLatency {
+    lat: [u64; INST_CLASS_NUM],
+    taken: u64,
+    trap: u64,
+    miss: u64,
+    mispredict: u64,
+}
+
+const CLASS_NAME: [&str; INST_CLASS_NUM] = [
+    "alu", "mul", "div", "load", "store", "branch", "jump", "amo", "fp", "fpdiv", "csr", "system",
+];
+
+impl Default for FixedLatency {
+    fn default() -> Self {
+        FixedLatency {
+            // alu mul div load store branch jump amo fp fpdiv csr system
+            lat: [1, 3, 20, 2, 1, 1, 1, 4, 4, 20, 1, 1],
+            taken: 1,
+            trap: 3,
+            miss: 10,
+            mispredict: 3,
+        }
+    }
+}
+
+impl FixedLatency {
+    // NOTE: the overrides of the default like "mul=3,div=34,taken=2", the
+    // latency includes the base cycle, the penalties are extra cycles
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let mut res = FixedLatency::default();
+        for item in spec.split(',').filter(|v| !v.is_empty() && *v != "default") {
+            let (key, val) = match item.split_once('=') {
+                Some(v) => v,
+                None => return Err(format!("[timing] expect key=cycles, get '{}'", item)),
+            };
+            let val = match val.parse::<u64>() {
+                Ok(v) => v,
+                Err(_e) => return Err(format!("[timing] invalid cycles '{}'", val)),
+            };
+            match key {
+                "taken" => res.taken = val,
+                "trap" => res.trap = val,
+                "miss" => res.miss = val,
+                "mispredict" => res.mispredict = val,
+                _ => match CLASS_NAME.iter().position(|v| *v == key) {
+                    Some(idx) if val > 0 => res.lat[idx] = val,
+                    _ => return Err(format!("[timing] unknown class '{}'", key)),
+                },
+            }
+        }
+        Ok(res)
+    }
+}
+
+impl TimingModel for FixedLatency {
+    fn name(&self) -> &str {
+        "fixed-latency"
+    }
+
+    fn inst_stall(&self, class: InstClass, taken: bool) -> (Stall, u64) {
+        let extra = self.lat[class as usize] - 1;
+        match class {
+            InstClass::Branch | InstClass::Jump => {
+                (Stall::Control, extra + taken as u64 * self.taken)
+            }
+            InstClass::Load | InstClass::Store => (Stall::Mem, extra),
+            _ => (Stall::Exec, extra),
+        }
+    }
+
+    fn trap_penalty(&self) -> u64 {
+        self.trap
+    }
+
+    fn miss_penalty(&self) -> u64 {
+        self.miss
+    }
+
+    fn mispredict_penalty(&self) -> u64 {
+        self.mispredict
+    }
+}
+
+// NOTE: the counters of the snapshot for the reverse exec, the model has no state
+#[derive(Clone, Copy)]
+pub struct TimingState {
+    cycles: u64,
+    insts: u64,
+    stalls: [u64; STALL_NUM],
+    class_cnt: [u64; INST_CLASS_NUM],
+}
+
+pub struct Timing {
+    model: Box<dyn TimingModel>,
+    pub cycles: u64,
+    pub insts: u64,
+    stalls: [u64; STALL_NUM],
+    class_cnt: [u64; INST_CLASS_NUM],
+}
+
+impl Timing {
+    pub fn new(model: Box<dyn TimingModel>) -> Self {
+        Timing {
+            model,
+            cycles: 0u64,
+            insts: 0u64,
+            stalls: [0; STALL_NUM],
+            class_cnt: [0; INST_CLASS_NUM],
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.cycles = 0u64;
+        self.insts = 0u64;
+        self.stalls = [0; STALL_NUM];
+        self.class_cnt = [0; INST_CLASS_NUM];
+    }
+
+    pub fn save(&self) -> TimingState {
+        TimingState {
+            cycles: self.cycles,
+            insts: self.insts,
+            stalls: self.stalls,
+            class_cnt: self.class_cnt,
+        }
+    }
+
+    pub fn restore(&mut self, state: &TimingState) {
+        self.cycles = state.cycles;
+        self.insts = state.insts;
+        self.stalls = state.stalls;
+        self.class_cnt = state.class_cnt;
+    }
+
+    pub fn model(&self) -> &dyn TimingModel {
+        self.model.as_ref()
+    }
+
+    // NOTE: return the cycles of the inst, which also advance mcycle
+    pub fn retire(&mut self, class: InstClass, taken: bool) -> u64 {
+        let (kind, extra) = self.model.inst_stall(class, taken);
+        self.insts += 1;
+        self.class_cnt[class as usize] += 1;
+        self.stall(kind, extra);
+        self.cycles += 1;
+        1 + extra
+    }
+
+    // NOTE: the trapped inst does not retire, only the flush is charged
+    pub fn trap(&mut self) -> u64 {
+        let penalty = self.model.trap_penalty();
+        self.stall(Stall::Trap, penalty);
+        penalty
+    }
+
+    pub fn stall(&mut self, kind: Stall, cycles: u64) {
+        self.stalls[kind as usize] += cycles;
+        self.cycles += cycles;
+    }
+
+    pub fn stall_of(&self, kind: Stall) -> u64 {
+        self.stalls[kind as usize]
+    }
+
+    pub fn report(&self) -> String {
+        let insts = self.insts.max(1) as f64;
+        let mut res = format!(
+            "timing[{}] cycles: {} insts: {} CPI: {:.3} IPC: {:.3}\n",
+            self.model.name(),
+            self.cycles,
+            self.insts,
+            self.cycles as f64 / insts,
+            self.insts as f64 / self.cycles.max(1) as f64
+        );
+        res += "stall";
+        for (name, val) in STALL_NAME.iter().zip(self.stalls.iter()) {
+            res += &format!(" {}: {}", name, val);
+        }
+        res += "\nclass";
+        for (name, val) in CLASS_NAME.iter().zip(self.class_cnt.iter()) {
+            if *val != 0 {
+                res += &format!(" {}: {}", name, val);
+            }
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inst::InstClass;
+    use crate::timing::{FixedLatency, Stall, Timing};
+
+    #[test]
+    fn fixed_latency() {
+        assert!(FixedLatency::parse("mul").is_err());
+        assert!(FixedLatency::parse("fma=3").is_err());
+        assert!(FixedLatency::parse("div=0").is_err());
+        let model = FixedLatency::parse("mul=5,taken=2").unwrap();
+        let mut dut = Timing::new(Box::new(model));
+        assert_eq!(dut.retire(InstClass::Alu, false), 1);
+        assert_eq!(dut.retire(InstClass::Mul, false), 5);
+        assert_eq!(dut.retire(InstClass::Branch, true), 3);
+        assert_eq!(dut.retire(InstClass::Branch, false), 1);
+        assert_eq!(dut.trap(), 3);
+        assert_eq!((dut.cycles, dut.insts), (13, 4));
+        assert_eq!(dut.stall_of(Stall::Exec), 4);
+        assert_eq!(dut.stall_of(Stall::Control), 2);
+        assert_eq!(dut.stall_of(Stall::Trap), 3);
+        assert!(dut.report().contains("CPI: 3.250"));
+    }
+}
@@ -1,27 +1,16 @@
-use crate::inst::{get_inst_name, Inst};
+use crate::elf::SymTab;
+use crate::mmu::MAType;
 use crate::privilege::Exception;
 use crate::regfile::Regfile;
-use object::{Object, ObjectSymbol};
-use std::error::Error;
-use std::fs;
 
-pub fn execpt_handle(pc: u64, word: u32) {
-    println!(
-        "[UNKNOWN] PC:{:016x}, Word:{:08x}",
-        pc.wrapping_sub(4),
-        word
-    );
-    panic!();
-}
-
-pub fn itrace(pc: u64, word: u32, inst: &Inst, rge: &[u64; 2]) {
+// NOTE: 'word' is the raw fetched bits, rvc inst is shown as its original halfword
+pub fn itrace(pc: u64, word: u32, asm: &str, rge: &[u64; 2]) {
     if pc >= rge[0] && pc <= rge[1] {
-        println!(
-            "PC:{:016x}, Word:{:08x}, Inst:{}",
-            pc.wrapping_sub(4),
-            word,
-            get_inst_name(&inst)
-        );
+        let raw = match word & 0x3 {
+            0x3 => format!("{:08x}", word),
+            _ => format!("{:04x}", word),
+        };
+        println!("PC:{:016x}, Word:{:>8}, Inst:{}", pc, raw, asm);
     }
 }
 
@@ -36,7 +25,7 @@ pub struct FTrace {
 }
 
 impl FTrace {
-    pub fn new(_elf: &str) -> Self {
+    pub fn new() -> Self {
         FTrace {
             sym_addr_sta: vec![],
             sym_addr_name: vec![],
@@ -44,27 +33,21 @@ impl FTrace {
         }
     }
 
-    pub fn ftrace(&mut self, ori_addr: u64, addr: u64) -> Result<(), Box<dyn Error>> {
-        let bin_data = fs::read(
-            "./dependency/crt/am-kernels/tests/cpu-tests/build/string-riscv64-treecore.elf",
-        )?;
-        let obj_file = object::File::parse(&*bin_data)?;
-        // let dat = obj_file.symbol_table();
-        for v in obj_file.symbols() {
-            if v.address() == addr {
-                match v.name() {
-                    Ok(vv) => {
-                        self.sym_addr_sta.push(ori_addr);
-                        self.sym_num += 1;
-                        self.sym_addr_name.push(vv.to_string());
-                        print!("{:#x}:", ori_addr);
-                        print!("{:>1$}", " call ", (self.sym_num * 5) as usize);
-                        println!("[{}@{:#x}]", vv, addr);
-                    }
-                    Err(_e) => {}
-                }
-            } else {
-                if self.sym_addr_sta.last() == Some(&addr.wrapping_sub(4)) {
+    pub fn ftrace(&mut self, ori_addr: u64, addr: u64, symtab: &SymTab) {
+        match symtab.func_at(addr) {
+            Some(name) => {
+                self.sym_addr_sta.push(ori_addr);
+                self.sym_num += 1;
+                self.sym_addr_name.push(name.to_string());
+                print!("{:#x}:", ori_addr);
+                print!("{:>1$}", " call ", (self.sym_num * 5) as usize);
+                println!("[{}@{:#x}]", name, addr);
+            }
+            None => {
+                // NOTE: the call site can be a 4-byte jal or a 2-byte c.jal(r)
+                if self.sym_addr_sta.last() == Some(&addr.wrapping_sub(4))
+                    || self.sym_addr_sta.last() == Some(&addr.wrapping_sub(2))
+                {
                     print!("{:#x}:", ori_addr);
                     print!("{:>1$}", " ret ", (self.sym_num * 5) as usize);
                     match self.sym_addr_name.last() {
@@ -77,8 +60,6 @@ impl FTrace {
                 }
             }
         }
-
-        Ok(())
     }
 }
 
@@ -98,14 +79,16 @@ pub fn etrace(excpt: &Exception) {
     );
 }
 
-macro_rules! log {
-    ($($args: expr),*) => {
-        print!("[{}] line: {}", file!(), line!());
-        $(
-            print!(" {}: {:016x}", stringify!($args), $args);
-        )*
-        println!(""); // to get a new line at the end
-    }
+// NOTE: 'miss' is the number of the levels which miss, 0 is the l1 hit
+pub fn ctrace(pc: u64, addr: u64, ma_type: MAType, miss: u64) {
+    let kind = match ma_type {
+        MAType::Exec => "fetch",
+        MAType::Read => "load",
+        MAType::Write => "store",
+    };
+    let res = match miss {
+        0 => "hit".to_string(),
+        v => format!("miss x{}", v),
+    };
+    println!("[ctrace] pc: {:016x} {:<5} addr: {:016x} {}", pc, kind, addr, res);
 }
-
-pub(crate) use log;